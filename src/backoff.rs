@@ -0,0 +1,76 @@
+use std::time::Duration;
+
+/// An endless schedule of intervals that doubles in length each time, up to a maximum.
+///
+/// Used to check often for processes that exit quickly, without busy polling for processes that
+/// take a long time.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    current: Duration,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Backoff {
+        let initial = initial.min(max);
+        Backoff {
+            initial,
+            max,
+            current: initial,
+        }
+    }
+
+    /// Start over from the initial interval again.
+    pub fn reset(&mut self) {
+        self.current = self.initial;
+    }
+}
+
+impl Iterator for Backoff {
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        let interval = self.current;
+        self.current = (self.current * 2).min(self.max);
+        Some(interval)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(intervals: &[u64]) -> Vec<Duration> {
+        intervals
+            .iter()
+            .map(|&ms| Duration::from_millis(ms))
+            .collect()
+    }
+
+    #[test]
+    fn it_doubles_up_to_the_max() {
+        let backoff = Backoff::new(Duration::from_millis(10), Duration::from_millis(100));
+
+        assert_eq!(
+            backoff.take(7).collect::<Vec<_>>(),
+            millis(&[10, 20, 40, 80, 100, 100, 100])
+        );
+    }
+
+    #[test]
+    fn it_restarts_after_reset() {
+        let mut backoff = Backoff::new(Duration::from_millis(10), Duration::from_millis(500));
+        backoff.by_ref().take(4).for_each(drop);
+        backoff.reset();
+
+        assert_eq!(backoff.take(2).collect::<Vec<_>>(), millis(&[10, 20]));
+    }
+
+    #[test]
+    fn it_never_starts_above_the_max() {
+        let backoff = Backoff::new(Duration::from_millis(10), Duration::from_millis(5));
+
+        assert_eq!(backoff.take(2).collect::<Vec<_>>(), millis(&[5, 5]));
+    }
+}
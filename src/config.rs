@@ -0,0 +1,97 @@
+use regex::RegexSet;
+use serde_json;
+use std::fs;
+use std::path::Path;
+
+/// Where the config file is read from when --config is not given. It does not have to exist.
+pub const DEFAULT_PATH: &str = "/etc/graceful-shutdown.json";
+
+/// Settings for everybody on the machine, which cannot be overridden on the command line.
+///
+/// ```json
+/// {"deny": ["^sshd$", "^systemd"]}
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Regular expressions for names of processes that are never signaled, no matter what the
+    /// patterns match.
+    pub deny: Vec<String>,
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Could not read config file {0}: {1}")]
+    Unreadable(String, String),
+    #[error("Config file {0} is not valid: {1}")]
+    Invalid(String, String),
+}
+
+impl Config {
+    /// Reads the config file at the path, or the one at `DEFAULT_PATH` if no path is given and it
+    /// exists.
+    pub fn load(path: Option<&Path>) -> Result<Config, ConfigError> {
+        match path {
+            Some(path) => Config::read(path),
+            None if Path::new(DEFAULT_PATH).exists() => Config::read(Path::new(DEFAULT_PATH)),
+            None => Ok(Config::default()),
+        }
+    }
+
+    fn read(path: &Path) -> Result<Config, ConfigError> {
+        let name = path.display().to_string();
+        let text = fs::read_to_string(path)
+            .map_err(|err| ConfigError::Unreadable(name.clone(), err.to_string()))?;
+        let config = Config::parse(&text).map_err(|err| ConfigError::Invalid(name.clone(), err))?;
+        config
+            .deny_set()
+            .map_err(|err| ConfigError::Invalid(name, err.to_string()))?;
+        Ok(config)
+    }
+
+    pub fn parse(text: &str) -> Result<Config, String> {
+        serde_json::from_str(text).map_err(|err| err.to_string())
+    }
+
+    /// The denylist as a set of case-sensitive regular expressions.
+    pub fn deny_set(&self) -> Result<RegexSet, ::regex::Error> {
+        RegexSet::new(&self.deny)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_dir::TestDir;
+
+    #[test]
+    fn it_parses_the_denylist() {
+        let config = Config::parse(r#"{"deny": ["^sshd$", "^systemd"]}"#).unwrap();
+
+        assert_eq!(config.deny, vec!["^sshd$", "^systemd"]);
+        assert!(config.deny_set().unwrap().is_match("systemd-journald"));
+        assert!(Config::parse("{}").unwrap().deny.is_empty());
+    }
+
+    #[test]
+    fn it_rejects_unknown_settings() {
+        assert!(Config::parse(r#"{"denied": ["^sshd$"]}"#).is_err());
+        assert!(Config::parse(r#"{"deny": "^sshd$"}"#).is_err());
+    }
+
+    #[test]
+    fn it_rejects_invalid_denylists() {
+        let dir = TestDir::new("config");
+        let path = dir.path().join("config.json");
+        fs::write(&path, r#"{"deny": ["^sshd("]}"#).unwrap();
+
+        match Config::load(Some(&path)) {
+            Err(ConfigError::Invalid(_, _)) => {}
+            other => panic!("Expected an invalid config, got {:?}", other),
+        }
+        match Config::load(Some(Path::new("/nonexistent/graceful-shutdown.json"))) {
+            Err(ConfigError::Unreadable(_, _)) => {}
+            other => panic!("Expected an unreadable config, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use anyhow::{Context, Error};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+
+/// Asks the user questions on the controlling terminal.
+///
+/// STDIN cannot be used for this since it is used to read the patterns, so this talks to
+/// `/dev/tty` directly.
+pub struct Confirmation {
+    input: BufReader<File>,
+    output: File,
+}
+
+impl Confirmation {
+    pub fn open() -> Result<Confirmation, Error> {
+        let tty = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/tty")
+            .context("--interactive requires a controlling terminal")?;
+        let output = tty.try_clone()?;
+
+        Ok(Confirmation {
+            input: BufReader::new(tty),
+            output,
+        })
+    }
+
+    /// Asks a yes/no question. Anything other than an explicit yes is treated as no, including
+    /// failing to read an answer at all.
+    pub fn ask(&mut self, question: &str) -> bool {
+        if write!(self.output, "{} [y/N] ", question).is_err() || self.output.flush().is_err() {
+            return false;
+        }
+
+        let mut answer = String::new();
+        match self.input.read_line(&mut answer) {
+            Ok(0) | Err(_) => false,
+            Ok(_) => is_yes(&answer),
+        }
+    }
+}
+
+fn is_yes(answer: &str) -> bool {
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_only_accepts_explicit_yes() {
+        assert!(is_yes("y\n"));
+        assert!(is_yes("Yes\n"));
+        assert!(is_yes("  Y  "));
+
+        assert!(!is_yes("\n"));
+        assert!(!is_yes("n\n"));
+        assert!(!is_yes("yep\n"));
+        assert!(!is_yes(""));
+    }
+}
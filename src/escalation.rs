@@ -0,0 +1,211 @@
+use options::parse_duration;
+use signal::Signal;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// A single step in an escalation: send a signal, then wait for processes to exit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Step {
+    pub signal: Signal,
+    pub wait: Duration,
+}
+
+/// A sequence of signals to send to processes that do not exit, each after the previous step's
+/// waiting time has run out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Escalation {
+    steps: Vec<Step>,
+}
+
+impl Escalation {
+    /// The classic escalation: Send the terminate signal and wait, then send the kill signal if
+    /// processes are still alive.
+    pub fn new(
+        terminate_signal: Signal,
+        wait_time: Option<Duration>,
+        kill_signal: Option<Signal>,
+    ) -> Escalation {
+        let mut steps = vec![Step {
+            signal: terminate_signal,
+            wait: wait_time.unwrap_or_default(),
+        }];
+
+        // Without any waiting time, there is no time for processes to ignore the first signal.
+        if let (Some(_), Some(kill_signal)) = (wait_time, kill_signal) {
+            steps.push(Step {
+                signal: kill_signal,
+                wait: Duration::from_secs(0),
+            });
+        }
+
+        Escalation { steps }
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    pub fn first_signal(&self) -> Signal {
+        self.steps[0].signal
+    }
+
+    /// The signal of the last step, which is the kill signal in the classic escalation.
+    pub fn last_signal(&self) -> Signal {
+        self.steps[self.steps.len() - 1].signal
+    }
+
+    /// The longest time that can be spent waiting on processes to exit.
+    pub fn total_wait(&self) -> Duration {
+        self.steps
+            .iter()
+            .fold(Duration::from_secs(0), |total, step| total + step.wait)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParseError {
+    Empty,
+    UnknownSignal(String),
+    InvalidSeconds(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "Escalation must contain at least one signal"),
+            ParseError::UnknownSignal(signal) => write!(f, "Unknown signal \"{}\"", signal),
+            ParseError::InvalidSeconds(seconds) => write!(
+                f,
+                "\"{}\" is not a valid number of seconds to wait",
+                seconds
+            ),
+        }
+    }
+}
+
+impl FromStr for Step {
+    type Err = ParseError;
+
+    fn from_str(step: &str) -> Result<Step, ParseError> {
+        let mut parts = step.splitn(2, ':');
+        let signal_name = parts.next().unwrap_or("").trim();
+        let signal = signal_name
+            .parse()
+            .map_err(|_| ParseError::UnknownSignal(signal_name.to_owned()))?;
+
+        let wait = match parts.next().map(str::trim) {
+            None => Duration::from_secs(0),
+            Some(seconds) => parse_duration(seconds, 1.0)
+                .ok_or_else(|| ParseError::InvalidSeconds(seconds.to_owned()))?,
+        };
+
+        Ok(Step { signal, wait })
+    }
+}
+
+impl FromStr for Escalation {
+    type Err = ParseError;
+
+    fn from_str(spec: &str) -> Result<Escalation, ParseError> {
+        if spec.trim().is_empty() {
+            return Err(ParseError::Empty);
+        }
+
+        let steps = spec
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<Vec<Step>, ParseError>>()?;
+
+        Ok(Escalation { steps })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(signal: &str, seconds: u64) -> Step {
+        Step {
+            signal: signal.parse().unwrap(),
+            wait: Duration::from_secs(seconds),
+        }
+    }
+
+    #[test]
+    fn it_parses_escalations() {
+        let escalation: Escalation = "HUP:2,sigterm:5,9".parse().expect("Failed to parse");
+
+        assert_eq!(
+            escalation.steps(),
+            &[step("hup", 2), step("term", 5), step("kill", 0)]
+        );
+        assert_eq!(escalation.first_signal(), "hup".parse().unwrap());
+    }
+
+    #[test]
+    fn it_parses_fractional_seconds() {
+        let escalation: Escalation = "term:0.5".parse().expect("Failed to parse");
+
+        assert_eq!(escalation.steps()[0].wait, Duration::from_millis(500));
+    }
+
+    #[test]
+    fn it_parses_waiting_times_with_units() {
+        let escalation: Escalation = "term:2m,int:500ms,kill".parse().expect("Failed to parse");
+
+        assert_eq!(escalation.steps()[0].wait, Duration::from_secs(120));
+        assert_eq!(escalation.steps()[1].wait, Duration::from_millis(500));
+    }
+
+    #[test]
+    fn it_does_not_parse_invalid_escalations() {
+        assert_eq!("".parse::<Escalation>(), Err(ParseError::Empty));
+        assert_eq!(
+            "term:5,,kill".parse::<Escalation>(),
+            Err(ParseError::UnknownSignal(String::new()))
+        );
+        assert_eq!(
+            "foo:5".parse::<Escalation>(),
+            Err(ParseError::UnknownSignal("foo".into()))
+        );
+        assert_eq!(
+            "term:soon".parse::<Escalation>(),
+            Err(ParseError::InvalidSeconds("soon".into()))
+        );
+        assert_eq!(
+            "term:-1".parse::<Escalation>(),
+            Err(ParseError::InvalidSeconds("-1".into()))
+        );
+        assert_eq!(
+            "term:".parse::<Escalation>(),
+            Err(ParseError::InvalidSeconds("".into()))
+        );
+    }
+
+    #[test]
+    fn it_sums_the_waiting_time() {
+        let escalation: Escalation = "hup:2,term:0.5,kill".parse().unwrap();
+
+        assert_eq!(escalation.total_wait(), Duration::from_millis(2500));
+    }
+
+    #[test]
+    fn it_builds_the_classic_escalation() {
+        let term = "term".parse().unwrap();
+        let kill = "kill".parse().unwrap();
+
+        assert_eq!(
+            Escalation::new(term, Some(Duration::from_secs(5)), Some(kill)).steps(),
+            &[step("term", 5), step("kill", 0)]
+        );
+        assert_eq!(
+            Escalation::new(term, Some(Duration::from_secs(5)), None).steps(),
+            &[step("term", 5)]
+        );
+        assert_eq!(
+            Escalation::new(term, None, Some(kill)).steps(),
+            &[step("term", 0)]
+        );
+    }
+}
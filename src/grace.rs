@@ -0,0 +1,125 @@
+use options::parse_duration;
+use processes::Process;
+use regex::Regex;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// Waiting times for processes that need more or less time to shut down than the rest.
+///
+/// Every line has a regular expression for the process name and a waiting time, separated by
+/// whitespace. Empty lines and lines starting with # are ignored.
+///
+/// ```text
+/// # Flushing everything to disk takes a while.
+/// ^postgres$  60
+/// ^nginx      2s
+/// ```
+#[derive(Debug, Default)]
+pub struct GraceMap {
+    entries: Vec<(Regex, Duration)>,
+}
+
+#[derive(Debug, Error)]
+pub enum GraceMapError {
+    #[error("Could not read grace map {0}: {1}")]
+    Unreadable(String, String),
+    #[error("Grace map {0} is not valid: {1}")]
+    Invalid(String, String),
+}
+
+impl GraceMap {
+    pub fn load(path: &Path) -> Result<GraceMap, GraceMapError> {
+        let name = path.display().to_string();
+        let text = fs::read_to_string(path)
+            .map_err(|err| GraceMapError::Unreadable(name.clone(), err.to_string()))?;
+        GraceMap::parse(&text).map_err(|err| GraceMapError::Invalid(name, err))
+    }
+
+    pub fn parse(text: &str) -> Result<GraceMap, String> {
+        let mut entries = Vec::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let number = index + 1;
+            let (pattern, wait) = match line.rfind(char::is_whitespace) {
+                Some(split) => (line[..split].trim_end(), &line[split + 1..]),
+                None => {
+                    return Err(format!(
+                        "Line {}: Expected a pattern and a waiting time",
+                        number
+                    ))
+                }
+            };
+            let wait = parse_duration(wait, 1.0).ok_or_else(|| {
+                format!("Line {}: \"{}\" is not a valid waiting time", number, wait)
+            })?;
+            let regex = Regex::new(pattern).map_err(|err| format!("Line {}: {}", number, err))?;
+
+            entries.push((regex, wait));
+        }
+
+        Ok(GraceMap { entries })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The waiting time of the first line matching the name of the process, if any.
+    pub fn wait_time(&self, process: &Process) -> Option<Duration> {
+        self.entries
+            .iter()
+            .find(|(regex, _)| regex.is_match(process.name()))
+            .map(|&(_, wait)| wait)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_patterns_with_waiting_times() {
+        let map = GraceMap::parse(
+            "# Flushing takes a while\n^postgres$  60\n\n  ^nginx 500ms\n^my app$\t2m\n",
+        )
+        .unwrap();
+
+        let wait = |name| map.wait_time(&Process::fake(1, name, name));
+        assert_eq!(wait("postgres"), Some(Duration::from_secs(60)));
+        assert_eq!(wait("nginx-worker"), Some(Duration::from_millis(500)));
+        assert_eq!(wait("my app"), Some(Duration::from_secs(120)));
+        assert_eq!(wait("postgres-exporter"), None);
+        assert!(GraceMap::parse("# Nothing yet\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn it_uses_the_first_matching_line() {
+        let map = GraceMap::parse("^nginx$ 1\nnginx 2\n").unwrap();
+
+        assert_eq!(
+            map.wait_time(&Process::fake(1, "nginx", "nginx")),
+            Some(Duration::from_secs(1))
+        );
+    }
+
+    #[test]
+    fn it_rejects_invalid_lines() {
+        assert_eq!(
+            GraceMap::parse("^nginx$ 1\n^postgres$\n").unwrap_err(),
+            "Line 2: Expected a pattern and a waiting time"
+        );
+        assert_eq!(
+            GraceMap::parse("^nginx$ soon\n").unwrap_err(),
+            "Line 1: \"soon\" is not a valid waiting time"
+        );
+        assert!(GraceMap::parse("^nginx( 1\n")
+            .unwrap_err()
+            .starts_with("Line 1: "));
+    }
+}
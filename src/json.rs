@@ -0,0 +1,391 @@
+use nix::unistd::Pid;
+use processes::Process;
+use serde_json;
+use signal::Signal;
+use std::io::BufRead;
+use std::time::{Duration, SystemTime};
+use timestamp;
+use {ProcessDescription, RunReport};
+
+/// What happened to a process, as reported in JSON output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    WouldSend,
+    /// Signaled in the dry run that was compared with, but not anymore.
+    WouldNoLongerSend,
+    Sent,
+    Died,
+    Killed,
+    Survived,
+    Failed,
+}
+
+/// A single action taken on a process. Rendered as one line of JSON (NDJSON) on STDOUT.
+#[derive(Debug, Serialize)]
+pub struct Event<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp: Option<String>,
+    outcome: Outcome,
+    pid: i32,
+    name: &'a str,
+    cmdline: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signal: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    termination_ms: Option<u64>,
+}
+
+impl<'a> Event<'a> {
+    pub fn new(outcome: Outcome, process: &'a Process) -> Event<'a> {
+        Event {
+            timestamp: None,
+            outcome,
+            pid: process.pid().into(),
+            name: process.name(),
+            cmdline: process.commandline(),
+            signal: None,
+            error: None,
+            termination_ms: None,
+        }
+    }
+
+    /// An event for a process that is only known from its description, like one from a saved dry
+    /// run.
+    pub fn described(outcome: Outcome, process: &'a ProcessDescription) -> Event<'a> {
+        Event {
+            timestamp: None,
+            outcome,
+            pid: process.pid.into(),
+            name: &process.name,
+            cmdline: &process.cmdline,
+            signal: None,
+            error: None,
+            termination_ms: None,
+        }
+    }
+
+    pub fn signal(mut self, signal: Signal) -> Event<'a> {
+        self.signal = Some(signal.name());
+        self
+    }
+
+    pub fn error<E: ToString>(mut self, error: E) -> Event<'a> {
+        self.error = Some(error.to_string());
+        self
+    }
+
+    /// Adds when the event happened, as an RFC 3339 timestamp.
+    pub fn timestamp(mut self, time: SystemTime) -> Event<'a> {
+        self.timestamp = Some(timestamp::rfc3339(time));
+        self
+    }
+
+    pub fn termination_time(mut self, time: Duration) -> Event<'a> {
+        self.termination_ms = Some(time.as_millis() as u64);
+        self
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Events are always serializable")
+    }
+}
+
+/// An event read back from JSON output, like a saved dry run. Other fields are ignored.
+#[derive(Debug, Deserialize)]
+struct SavedEvent {
+    outcome: Outcome,
+    pid: i32,
+    name: String,
+    cmdline: String,
+}
+
+/// Reads the processes that a dry run would have signaled from its saved JSON output.
+pub fn read_dry_run<R: BufRead>(input: R) -> Result<Vec<ProcessDescription>, String> {
+    let mut processes = Vec::new();
+
+    for (index, line) in input.lines().enumerate() {
+        let line = line.map_err(|err| err.to_string())?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let event: SavedEvent = serde_json::from_str(&line)
+            .map_err(|err| format!("Line {} is not a JSON event: {}", index + 1, err))?;
+        if event.outcome == Outcome::WouldSend {
+            processes.push(ProcessDescription {
+                pid: Pid::from_raw(event.pid),
+                name: event.name,
+                cmdline: event.cmdline,
+            });
+        }
+    }
+
+    Ok(processes)
+}
+
+/// How the processes of a dry run differ from the ones of a previous dry run.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct DryRunDiff {
+    /// Processes that would be signaled now, but were not before.
+    pub added: Vec<ProcessDescription>,
+    /// Processes that would have been signaled before, but are not now.
+    pub removed: Vec<ProcessDescription>,
+}
+
+impl DryRunDiff {
+    /// Processes are the same if they have the same name and command line, so a service that was
+    /// restarted with a new PID is not a difference. The PID only decides which of several such
+    /// processes are the same.
+    pub fn between(previous: &[ProcessDescription], current: &[ProcessDescription]) -> DryRunDiff {
+        let mut unpaired: Vec<Option<&ProcessDescription>> = previous.iter().map(Some).collect();
+        let mut pair = |is_same: &dyn Fn(&ProcessDescription) -> bool| match unpaired
+            .iter()
+            .position(|previous| previous.is_some_and(is_same))
+        {
+            Some(index) => unpaired[index].take().is_some(),
+            None => false,
+        };
+
+        // Pair up the processes that kept their PID first, so they are not taken by others.
+        let restarted: Vec<&ProcessDescription> = current
+            .iter()
+            .filter(|process| !pair(&|previous| previous == *process))
+            .collect();
+        let added = restarted
+            .into_iter()
+            .filter(|process| {
+                !pair(&|previous| {
+                    previous.name == process.name && previous.cmdline == process.cmdline
+                })
+            })
+            .cloned()
+            .collect();
+
+        DryRunDiff {
+            added,
+            removed: unpaired.into_iter().flatten().cloned().collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// The outcome of a whole run, printed as a single JSON object by --json-summary.
+#[derive(Debug, Serialize)]
+pub struct RunSummary {
+    matched: usize,
+    skipped: usize,
+    terminated: Vec<i32>,
+    killed: Vec<i32>,
+    failed: Vec<i32>,
+    alive: Vec<i32>,
+    elapsed_ms: u64,
+    signals: Vec<String>,
+}
+
+impl RunSummary {
+    /// Summarizes the report of a run that took the given time, and could send the signals.
+    pub fn new<I>(report: &RunReport, signals: I, elapsed: Duration) -> RunSummary
+    where
+        I: IntoIterator<Item = Signal>,
+    {
+        RunSummary {
+            matched: report.matched,
+            skipped: report.skipped,
+            terminated: pids(&report.terminated),
+            killed: pids(&report.killed),
+            failed: pids(&report.failed),
+            alive: pids(&report.alive),
+            elapsed_ms: elapsed.as_millis() as u64,
+            signals: signals.into_iter().map(|signal| signal.name()).collect(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Summaries are always serializable")
+    }
+}
+
+fn pids(descriptions: &[ProcessDescription]) -> Vec<i32> {
+    descriptions
+        .iter()
+        .map(|description| description.pid.into())
+        .collect()
+}
+
+/// A signal, as listed by --list-signals.
+#[derive(Debug, Serialize)]
+pub struct SignalDescription {
+    number: i32,
+    name: String,
+    basename: String,
+}
+
+impl From<Signal> for SignalDescription {
+    fn from(signal: Signal) -> SignalDescription {
+        SignalDescription {
+            number: signal.number(),
+            name: signal.name(),
+            basename: signal.basename(),
+        }
+    }
+}
+
+/// Renders the given signals as a single JSON array.
+pub fn signals_to_json<I: IntoIterator<Item = Signal>>(signals: I) -> String {
+    let descriptions: Vec<SignalDescription> = signals.into_iter().map(Into::into).collect();
+    serde_json::to_string(&descriptions).expect("Signals are always serializable")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn it_serializes_events() {
+        let process = Process::fake(42, "firefox", "/usr/bin/firefox --new-window");
+        let signal: Signal = "term".parse().unwrap();
+
+        assert_eq!(
+            Event::new(Outcome::WouldSend, &process)
+                .signal(signal)
+                .to_json(),
+            r#"{"outcome":"would-send","pid":42,"name":"firefox","cmdline":"/usr/bin/firefox --new-window","signal":"SIGTERM"}"#,
+        );
+    }
+
+    #[test]
+    fn it_serializes_timestamps_first() {
+        let process = Process::fake(42, "firefox", "/usr/bin/firefox");
+
+        assert_eq!(
+            Event::new(Outcome::Died, &process)
+                .timestamp(UNIX_EPOCH + Duration::from_secs(1_528_034_875))
+                .to_json(),
+            r#"{"timestamp":"2018-06-03T14:07:55.000Z","outcome":"died","pid":42,"name":"firefox","cmdline":"/usr/bin/firefox"}"#,
+        );
+    }
+
+    #[test]
+    fn it_serializes_termination_times() {
+        let process = Process::fake(42, "firefox", "/usr/bin/firefox");
+
+        assert_eq!(
+            Event::new(Outcome::Died, &process)
+                .termination_time(Duration::from_millis(4200))
+                .to_json(),
+            r#"{"outcome":"died","pid":42,"name":"firefox","cmdline":"/usr/bin/firefox","termination_ms":4200}"#,
+        );
+    }
+
+    #[test]
+    fn it_serializes_signal_lists() {
+        let signals = vec!["term".parse().unwrap(), "kill".parse().unwrap()];
+
+        assert_eq!(
+            signals_to_json(signals),
+            r#"[{"number":15,"name":"SIGTERM","basename":"TERM"},{"number":9,"name":"SIGKILL","basename":"KILL"}]"#,
+        );
+        assert_eq!(signals_to_json(Vec::new()), "[]");
+    }
+
+    #[test]
+    fn it_serializes_errors() {
+        let process = Process::fake(1, "init", "/sbin/init");
+        let signal: Signal = "kill".parse().unwrap();
+
+        assert_eq!(
+            Event::new(Outcome::Failed, &process)
+                .signal(signal)
+                .error("Insufficient permission")
+                .to_json(),
+            r#"{"outcome":"failed","pid":1,"name":"init","cmdline":"/sbin/init","signal":"SIGKILL","error":"Insufficient permission"}"#,
+        );
+    }
+
+    #[test]
+    fn it_serializes_run_summaries() {
+        let description = |pid| ProcessDescription::from(&Process::fake(pid, "sleep", "sleep 60"));
+        let report = RunReport {
+            matched: 4,
+            skipped: 1,
+            terminated: vec![description(100), description(101)],
+            killed: vec![description(102)],
+            ..RunReport::default()
+        };
+        let signals = vec!["term".parse().unwrap(), "kill".parse().unwrap()];
+
+        assert_eq!(
+            RunSummary::new(&report, signals, Duration::from_millis(1500)).to_json(),
+            r#"{"matched":4,"skipped":1,"terminated":[100,101],"killed":[102],"failed":[],"alive":[],"elapsed_ms":1500,"signals":["SIGTERM","SIGKILL"]}"#,
+        );
+    }
+
+    #[test]
+    fn it_compares_saved_dry_runs() {
+        let before = r#"{"outcome":"would-send","pid":100,"name":"nginx","cmdline":"nginx","signal":"SIGTERM"}
+{"outcome":"would-send","pid":101,"name":"nginx-helper","cmdline":"nginx-helper","signal":"SIGTERM"}
+"#;
+        let after = r#"{"timestamp":"2018-06-03T14:07:55.123Z","outcome":"would-send","pid":100,"name":"nginx","cmdline":"nginx","signal":"SIGTERM"}
+{"outcome":"failed","pid":1,"name":"init","cmdline":"/sbin/init","signal":"SIGTERM","error":"Insufficient permission"}
+
+{"outcome":"would-send","pid":102,"name":"nginx","cmdline":"nginx -g daemon off;","signal":"SIGKILL"}
+"#;
+        let before = read_dry_run(before.as_bytes()).unwrap();
+        let after = read_dry_run(after.as_bytes()).unwrap();
+
+        let diff = DryRunDiff::between(&before, &after);
+        assert_eq!(pids(&diff.added), vec![102]);
+        assert_eq!(pids(&diff.removed), vec![101]);
+        assert!(DryRunDiff::between(&after, &after).is_empty());
+    }
+
+    #[test]
+    fn it_does_not_count_restarted_processes_as_differences() {
+        let description = |pid, name: &str| ProcessDescription {
+            pid: Pid::from_raw(pid),
+            name: name.to_owned(),
+            cmdline: name.to_owned(),
+        };
+        let before = vec![
+            description(100, "worker"),
+            description(101, "worker"),
+            description(102, "nginx"),
+        ];
+        // nginx was restarted, and one of the workers is gone. The PID tells which one.
+        let after = vec![description(300, "nginx"), description(101, "worker")];
+
+        let diff = DryRunDiff::between(&before, &after);
+        assert!(diff.added.is_empty());
+        assert_eq!(pids(&diff.removed), vec![100]);
+    }
+
+    #[test]
+    fn it_serializes_processes_that_would_no_longer_be_signaled() {
+        let description = ProcessDescription {
+            pid: Pid::from_raw(42),
+            name: String::from("nginx"),
+            cmdline: String::from("nginx -g daemon"),
+        };
+
+        assert_eq!(
+            Event::described(Outcome::WouldNoLongerSend, &description).to_json(),
+            r#"{"outcome":"would-no-longer-send","pid":42,"name":"nginx","cmdline":"nginx -g daemon"}"#,
+        );
+    }
+
+    #[test]
+    fn it_rejects_saved_dry_runs_that_are_not_json() {
+        let error = read_dry_run("Would have sent TERM to process 100 (nginx)".as_bytes());
+        assert_eq!(
+            error.map_err(|err| err.starts_with("Line 1 is not a JSON event")),
+            Err(true)
+        );
+    }
+}
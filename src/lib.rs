@@ -0,0 +1,3662 @@
+//! Terminates processes matching a list of patterns, and waits for them to exit.
+//!
+//! This is the library behind the `graceful-shutdown` command. Build an `Options` (usually from
+//! `CliOptions`) and pass it to `run`.
+
+#[macro_use]
+extern crate structopt;
+#[macro_use]
+extern crate anyhow;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate thiserror;
+
+extern crate libc;
+extern crate nix;
+extern crate regex;
+extern crate serde;
+extern crate serde_json;
+extern crate termion;
+extern crate users;
+
+mod backoff;
+pub mod config;
+mod confirmation;
+pub mod escalation;
+pub mod grace;
+pub mod json;
+pub mod matcher;
+pub mod options;
+pub mod pidfd;
+pub mod processes;
+pub mod reporter;
+mod sender;
+pub mod signal;
+pub mod summary;
+#[cfg(test)]
+mod test_dir;
+pub mod timestamp;
+
+use anyhow::{Context, Error};
+use backoff::Backoff;
+use config::Config;
+use confirmation::Confirmation;
+use grace::GraceMap;
+use matcher::MatchMode;
+use nix::poll::{poll, EventFlags, PollFd};
+use nix::sys::signal::Signal::{SIGCONT, SIGSTOP};
+use nix::unistd::Pid;
+use options::{AgeSelection, SortOrder, UserMode};
+use processes::{Clock, KillError, LoadError};
+use regex::{RegexBuilder, RegexSet, RegexSetBuilder};
+use reporter::{human_process_description, survival_note, Reporter};
+use sender::{NixSender, SignalSender};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+use summary::Summary;
+use users::{gid_t, uid_t};
+
+pub use matcher::Matcher;
+pub use options::{CliCommand, CliOptions, Options};
+pub use processes::Process;
+pub use signal::Signal;
+
+/// A process that was part of a run. Unlike `Process`, this is a plain snapshot that can be kept
+/// around after the run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessDescription {
+    pub pid: Pid,
+    pub name: String,
+    pub cmdline: String,
+}
+
+impl<'a> From<&'a Process> for ProcessDescription {
+    fn from(process: &'a Process) -> ProcessDescription {
+        ProcessDescription {
+            pid: process.pid(),
+            name: process.name().to_owned(),
+            cmdline: process.commandline().to_owned(),
+        }
+    }
+}
+
+/// What happened during a run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RunReport {
+    /// Number of processes that matched, including the ones that were skipped.
+    pub matched: usize,
+    /// Number of matched processes that were never signaled.
+    pub skipped: usize,
+    /// Processes that exited after getting the terminate signal.
+    pub terminated: Vec<ProcessDescription>,
+    /// Processes that had to be sent the final signal of the escalation.
+    pub killed: Vec<ProcessDescription>,
+    /// Processes that could not be signaled.
+    pub failed: Vec<ProcessDescription>,
+    /// Processes that were still alive when giving up.
+    pub alive: Vec<ProcessDescription>,
+    /// Every pattern and how many processes it matched. Only filled in with --stats.
+    pub pattern_counts: Vec<(String, usize)>,
+}
+
+impl RunReport {
+    /// If every signaled process shut down. Dry runs are always successful.
+    pub fn success(&self) -> bool {
+        self.failed.is_empty() && self.alive.is_empty()
+    }
+
+    /// Adds everything that happened in another run to this one.
+    fn merge(&mut self, other: RunReport) {
+        self.matched += other.matched;
+        self.skipped += other.skipped;
+        self.terminated.extend(other.terminated);
+        self.killed.extend(other.killed);
+        self.failed.extend(other.failed);
+        self.alive.extend(other.alive);
+    }
+
+    pub fn summary(&self) -> Summary {
+        Summary {
+            terminated: self.terminated.len(),
+            killed: self.killed.len(),
+            alive: self.alive.len(),
+            failed: self.failed.len(),
+            skipped: self.skipped,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum DeadlineError {
+    #[error("Deadline of {0}ms exceeded")]
+    Exceeded(u64, RunReport),
+}
+
+/// The point in time when the whole run must be over, if any.
+#[derive(Debug, Clone, Copy)]
+struct Deadline(Option<Instant>);
+
+impl Deadline {
+    fn after(duration: Option<Duration>) -> Deadline {
+        Deadline(duration.map(|duration| Instant::now() + duration))
+    }
+
+    fn has_passed(&self) -> bool {
+        self.0.is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// Shortens the waiting time so that it ends at the deadline at the latest.
+    fn cap(&self, wait: Duration) -> Duration {
+        match self.0 {
+            Some(deadline) => wait.min(deadline.saturating_duration_since(Instant::now())),
+            None => wait,
+        }
+    }
+}
+
+/// Finds all processes matching the options and shuts them down, showing what happens the way
+/// the output mode of the options says.
+pub fn run(options: &Options) -> Result<RunReport, Error> {
+    let console = reporter::for_options(options);
+    let mut extra: Vec<Box<dyn Reporter>> = Vec::new();
+
+    if let Some(ref path) = options.log_file {
+        // Open the log before anything happens, so nothing goes unlogged.
+        let log = reporter::LogFileReporter::open(path)
+            .with_context(|| format!("Could not open log file {}", path.display()))?;
+        extra.push(Box::new(log));
+    }
+
+    if let Some(ref path) = options.events_socket {
+        // Nobody listening is no reason to leave processes running.
+        match reporter::SocketReporter::connect(path) {
+            Ok(socket) => extra.push(Box::new(socket)),
+            Err(err) => console.warning(&format!(
+                "Could not connect to events socket {}: {}",
+                path.display(),
+                err
+            )),
+        }
+    }
+
+    if extra.is_empty() {
+        run_with_reporter(options, &*console)
+    } else {
+        let mut reporters = vec![console];
+        reporters.extend(extra);
+        run_with_reporter(options, &reporter::Tee::new(reporters))
+    }
+}
+
+/// Like `run`, but lets the given reporter handle everything that happens.
+pub fn run_with_reporter(options: &Options, reporter: &dyn Reporter) -> Result<RunReport, Error> {
+    let result = run_with_sender(options, reporter, &NixSender);
+    reporter.flush();
+    result
+}
+
+fn run_with_sender(
+    options: &Options,
+    reporter: &dyn Reporter,
+    sender: &dyn SignalSender,
+) -> Result<RunReport, Error> {
+    let deadline = Deadline::after(options.deadline);
+    options.validate()?;
+
+    if options.zero_wait_time {
+        reporter.warning(
+            "--wait-time 0 does not wait for processes to exit, and never kills them. Use --no-wait to do this without a warning.",
+        );
+    }
+
+    if options.include_self {
+        reporter.warning(
+            "--include-self is set. graceful-shutdown might signal itself and stop before every process is shut down.",
+        );
+    }
+
+    let will_signal = !options.dry_run && !options.count;
+    if will_signal && options.proc_root != Path::new(processes::DEFAULT_PROC_ROOT) {
+        reporter.warning(&format!(
+            "Signaling processes found in {}. Make sure it belongs to the same PID namespace as graceful-shutdown, or other processes might get the signals.",
+            options.proc_root.display(),
+        ));
+    }
+
+    if options.conflicting_case_flags {
+        reporter.warning(
+            "Both --case-sensitive and --ignore-case were given. Matching case-sensitively.",
+        );
+    }
+
+    if !options.terminate_signal.is_catchable() {
+        reporter.warning(&format!(
+            "{} cannot be caught, so processes will not be able to shut down gracefully. Use --strict to make this an error.",
+            options.terminate_signal,
+        ));
+    }
+
+    if let Some(signal) = options.weak_kill_signal() {
+        reporter.warning(&format!(
+            "{} is not guaranteed to kill processes, so some might survive the kill step. Use --kill-signal KILL, or --strict to make this an error.",
+            signal,
+        ));
+    }
+
+    let targets = Targets::load(options, reporter)?;
+    let report = match options.watch {
+        Some(watch) => watch_for_matches(options, &targets, watch, deadline, reporter, sender)?,
+        None => shut_down_matches(
+            options,
+            &targets,
+            &mut HashSet::new(),
+            deadline,
+            reporter,
+            sender,
+        )?,
+    };
+    if options.count || options.stats {
+        return Ok(report);
+    }
+    if report.matched == 0 {
+        reporter.nothing_matched();
+        return Ok(report);
+    }
+    if !options.dry_run {
+        reporter.finished(&report.summary());
+        if deadline.has_passed() && !report.alive.is_empty() {
+            return Err(deadline_exceeded(options, report));
+        }
+    }
+
+    Ok(report)
+}
+
+/// Everything that decides which processes to signal, and how long to wait for them. It is only
+/// loaded once per run, as patterns can come from STDIN.
+struct Targets {
+    /// Without a matcher, the processes given with --pid are used.
+    matcher: Option<Matcher>,
+    /// Names of processes that are never signaled, from the config file.
+    deny_set: RegexSet,
+    /// Only the --exclude patterns, for processes that are found without the matcher.
+    exclusions: Matcher,
+    grace_map: GraceMap,
+}
+
+impl Targets {
+    fn load(options: &Options, reporter: &dyn Reporter) -> Result<Targets, Error> {
+        let deny_set = Config::load(options.config.as_deref())?.deny_set()?;
+        let exclude_set = build_regex_set(&options.exclude_patterns, options)
+            .context("Could not load exclude patterns")?;
+        let matcher = if options.pids.is_empty() {
+            Some(build_matcher(options, &exclude_set, &deny_set, reporter)?)
+        } else {
+            None
+        };
+        let exclusions = Matcher::new(RegexSet::empty(), exclude_set, options.match_mode);
+        let grace_map = match options.grace_map {
+            Some(ref path) => GraceMap::load(path)?,
+            None => GraceMap::default(),
+        };
+
+        Ok(Targets {
+            matcher,
+            deny_set,
+            exclusions,
+            grace_map,
+        })
+    }
+}
+
+/// Keeps shutting down new matching processes until the watch time is over. Every process is only
+/// handled once, even if it survives.
+fn watch_for_matches(
+    options: &Options,
+    targets: &Targets,
+    watch: Duration,
+    deadline: Deadline,
+    reporter: &dyn Reporter,
+    sender: &dyn SignalSender,
+) -> Result<RunReport, Error> {
+    let end = Instant::now() + watch;
+    let mut handled = HashSet::new();
+    let mut report = RunReport::default();
+
+    loop {
+        report.merge(shut_down_matches(
+            options,
+            targets,
+            &mut handled,
+            deadline,
+            reporter,
+            sender,
+        )?);
+
+        let now = Instant::now();
+        if now >= end || deadline.has_passed() {
+            return Ok(report);
+        }
+        ::std::thread::sleep(options.poll_interval.min(end - now));
+    }
+}
+
+/// Finds the matching processes that are not handled yet, and shuts them down. Without a matcher,
+/// the processes given with --pid are used.
+fn shut_down_matches(
+    options: &Options,
+    targets: &Targets,
+    handled: &mut HashSet<(Pid, u64)>,
+    deadline: Deadline,
+    reporter: &dyn Reporter,
+    sender: &dyn SignalSender,
+) -> Result<RunReport, Error> {
+    let matcher = targets.matcher.as_ref();
+    let processes = match matcher {
+        Some(matcher) => matching_processes(options, matcher, handled, reporter)?,
+        None => explicit_processes(options, &targets.deny_set, handled, reporter)
+            .context("Could not build process list")?,
+    };
+    // Patterns can still match later when watching. When nothing matched at all, that is
+    // reported anyway.
+    let warn_unmatched = options.unmatched_warning && options.watch.is_none() && !options.stats;
+    if let (true, Some(matcher)) = (warn_unmatched && !processes.is_empty(), matcher) {
+        if let Some(warning) = unmatched_warning(matcher, &processes) {
+            reporter.warning(&warning);
+        }
+    }
+    if let (true, Some(matcher)) = (options.stats, matcher) {
+        let counts = matcher.count_by_pattern(&processes);
+        return Ok(RunReport {
+            matched: processes.len(),
+            pattern_counts: matcher.patterns().iter().cloned().zip(counts).collect(),
+            ..RunReport::default()
+        });
+    }
+    let processes = unique_pids(processes);
+    // --first-match takes the lowest PID, unless another order is asked for.
+    let order = match options.sort {
+        None if options.first_match => Some(SortOrder::Pid),
+        order => order,
+    };
+    let processes = match order {
+        Some(order) => sorted(processes, order),
+        None => processes,
+    };
+    let processes = if options.tree {
+        with_descendants(options, processes, targets, handled, reporter)
+            .context("Could not find child processes")?
+    } else {
+        processes
+    };
+    let processes = if options.leaves_first {
+        let parents = parent_map(options).context("Could not read process tree")?;
+        leaves_first(processes, &parents)
+    } else {
+        processes
+    };
+    let processes = not_handled(processes, handled);
+    let matched_count = processes.len();
+    if options.count {
+        return Ok(RunReport {
+            matched: matched_count,
+            ..RunReport::default()
+        });
+    }
+    let mut processes = skip_protected(options, processes, reporter);
+    let mut skipped = matched_count - processes.len();
+    if options.process_group {
+        processes = one_per_process_group(processes);
+    }
+    // The limit is checked after protected processes are skipped and process groups are merged,
+    // but before --oldest-only, --newest-only and --first-match narrow the list down. That way
+    // --first-match can still give up on overly broad patterns.
+    enforce_limit(options, &processes, reporter)?;
+    if let Some(selection) = options.age_selection {
+        let before = processes.len();
+        processes = one_per_name(processes, selection, reporter);
+        skipped += before - processes.len();
+    }
+    if options.first_match {
+        skipped += processes.len().saturating_sub(1);
+        processes = only_first(processes, reporter);
+    }
+
+    let mut report = RunReport {
+        matched: matched_count,
+        skipped,
+        ..RunReport::default()
+    };
+    // Processes that are gone since the compared dry run are still worth showing.
+    if matched_count == 0 && options.compare.is_none() {
+        return Ok(report);
+    }
+    if deadline.has_passed() {
+        return Err(deadline_exceeded(options, report));
+    }
+
+    // Time to shut them down
+    reporter.flush();
+    if !options.dry_run {
+        open_pidfds(&mut processes, reporter);
+        use_process_groups(options, &mut processes);
+        use_grace_periods(&targets.grace_map, &mut processes);
+    }
+    let (stuck, processes) = split_stuck(options, processes)?;
+    if let (true, Some(ref path)) = (options.dry_run, &options.compare) {
+        compared_dry_run(options, path, &stuck, &processes, reporter)?;
+    } else if options.dry_run {
+        dry_run(options, &stuck, &processes, reporter);
+    } else {
+        real_run(
+            options,
+            stuck,
+            processes,
+            &mut report,
+            deadline,
+            reporter,
+            sender,
+        )?;
+    }
+
+    Ok(report)
+}
+
+/// Lists the patterns that matched none of the processes, as they might have typos or be stale.
+fn unmatched_warning(matcher: &Matcher, processes: &[Process]) -> Option<String> {
+    let unmatched: Vec<String> = matcher
+        .patterns()
+        .iter()
+        .zip(matcher.count_by_pattern(processes))
+        .filter(|&(_, count)| count == 0)
+        .map(|(pattern, _)| format!("{:?}", pattern))
+        .collect();
+
+    if unmatched.is_empty() {
+        None
+    } else {
+        Some(format!(
+            "No process matched {}: {}. Use --no-unmatched-warning to not show this.",
+            if unmatched.len() == 1 {
+                "this pattern"
+            } else {
+                "these patterns"
+            },
+            unmatched.join(", "),
+        ))
+    }
+}
+
+fn deadline_exceeded(options: &Options, report: RunReport) -> Error {
+    let deadline = options.deadline.unwrap_or_default();
+    DeadlineError::Exceeded(deadline.as_millis() as u64, report).into()
+}
+
+fn build_matcher(
+    options: &Options,
+    exclude_set: &RegexSet,
+    deny_set: &RegexSet,
+    reporter: &dyn Reporter,
+) -> Result<Matcher, Error> {
+    // --port can find processes by itself, so do not wait for patterns typed on a terminal.
+    let (texts, patterns) =
+        if can_go_without_patterns(options) && termion::is_tty(&::std::io::stdin()) {
+            (Vec::new(), RegexSet::empty())
+        } else {
+            let input = pattern_input(options, reporter)?;
+            load_patterns(input, options).context("Could not load patterns")?
+        };
+
+    Ok(Matcher::new(
+        or_match_all(options, patterns),
+        exclude_set.clone(),
+        options.match_mode,
+    )
+    .with_pattern_texts(texts)
+    .with_denylist(deny_set.clone()))
+}
+
+/// Checks if every process listening on the --port is a match when no patterns are given, like
+/// when STDIN is empty when run from cron. An empty pattern file still matches nothing.
+fn can_go_without_patterns(options: &Options) -> bool {
+    !options.ports.is_empty() && options.pattern_file.is_none()
+}
+
+fn or_match_all(options: &Options, patterns: RegexSet) -> RegexSet {
+    if patterns.is_empty() && can_go_without_patterns(options) {
+        RegexSet::new([""]).expect("An empty pattern is always valid")
+    } else {
+        patterns
+    }
+}
+
+fn matching_processes(
+    options: &Options,
+    matcher: &Matcher,
+    handled: &mut HashSet<(Pid, u64)>,
+    reporter: &dyn Reporter,
+) -> Result<Vec<Process>, Error> {
+    all_processes(options, matcher, handled, reporter).context("Could not build process list")
+}
+
+fn sorted(mut processes: Vec<Process>, order: SortOrder) -> Vec<Process> {
+    // Ties are broken by PID, so the order is the same on every run.
+    let pid = |process: &Process| i32::from(process.pid());
+    match order {
+        SortOrder::Pid => processes.sort_by_key(pid),
+        SortOrder::Name => {
+            processes.sort_by(|a, b| a.name().cmp(b.name()).then(pid(a).cmp(&pid(b))))
+        }
+        SortOrder::Age => {
+            processes.sort_by_key(|process| (Reverse(process.start_time()), pid(process)))
+        }
+        SortOrder::Startup => processes.sort_by_key(|process| (process.start_time(), pid(process))),
+    }
+    processes
+}
+
+fn pattern_input(options: &Options, reporter: &dyn Reporter) -> Result<Box<dyn BufRead>, Error> {
+    if let Some(ref path) = options.pattern_file {
+        if has_piped_input(&io::stdin()) {
+            return Err(anyhow!(
+                "Patterns were piped to STDIN, but --pattern-file is given too. Use only one of them."
+            ));
+        }
+        let file = File::open(path)
+            .with_context(|| format!("Could not open pattern file {}", path.display()))?;
+        return Ok(Box::new(BufReader::new(file)));
+    }
+
+    if termion::is_tty(&::std::io::stdin()) {
+        reporter.warning(
+            "Reading processlist from TTY stdin. Exit with ^D when you are done, or ^C to abort.",
+        );
+    }
+
+    Ok(Box::new(BufReader::new(io::stdin())))
+}
+
+/// Checks if the input is a pipe or socket with something to read right away. Input that is only
+/// written later is not waited for, since nothing might ever be written to it. Redirected files,
+/// like /dev/null in cron jobs, do not count.
+fn has_piped_input<F: AsRawFd>(input: &F) -> bool {
+    let fd = input.as_raw_fd();
+    let is_pipe = match nix::sys::stat::fstat(fd) {
+        Ok(stat) => {
+            let kind = stat.st_mode & libc::S_IFMT;
+            kind == libc::S_IFIFO || kind == libc::S_IFSOCK
+        }
+        Err(_) => false,
+    };
+    if !is_pipe {
+        return false;
+    }
+
+    // A closed pipe without anything left in it only has POLLHUP.
+    let mut fds = [PollFd::new(fd, EventFlags::POLLIN)];
+    match poll(&mut fds, 0) {
+        Ok(ready) if ready > 0 => fds[0]
+            .revents()
+            .is_some_and(|events| events.contains(EventFlags::POLLIN)),
+        _ => false,
+    }
+}
+
+/// A pattern that could not be compiled.
+#[derive(Debug)]
+pub struct InvalidPattern {
+    /// Position in the list of patterns, starting on 0.
+    pub index: usize,
+    /// Line number in the pattern input, if the pattern was read from one.
+    pub line: Option<usize>,
+    pub error: regex::Error,
+}
+
+#[derive(Debug)]
+pub enum PatternError {
+    /// Every pattern that failed to compile, not only the first one.
+    Invalid(Vec<InvalidPattern>),
+    /// The patterns are valid on their own, but not all together.
+    Set(regex::Error),
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PatternError::Invalid(invalid) => {
+                write!(f, "Found {} invalid pattern(s)", invalid.len())?;
+                for pattern in invalid {
+                    match pattern.line {
+                        Some(line) => write!(f, "\nOn line {}: {}", line, pattern.error)?,
+                        None => write!(f, "\n{}", pattern.error)?,
+                    }
+                }
+                Ok(())
+            }
+            PatternError::Set(_) => write!(f, "Could not combine the patterns"),
+        }
+    }
+}
+
+impl ::std::error::Error for PatternError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PatternError::Invalid(_) => None,
+            PatternError::Set(error) => Some(error),
+        }
+    }
+}
+
+/// Reads the patterns, and compiles them into a set. The patterns are returned as written too, to
+/// show them to the user.
+fn load_patterns(
+    input: Box<dyn BufRead>,
+    options: &Options,
+) -> Result<(Vec<String>, RegexSet), Error> {
+    let mut line_numbers = Vec::new();
+    let mut patterns = Vec::new();
+    for (index, line) in input.lines().enumerate() {
+        let line = line.with_context(|| format!("Could not read line {}", index + 1))?;
+        let pattern = strip_comment(line);
+        if !pattern.is_empty() {
+            line_numbers.push(index + 1);
+            patterns.push(pattern);
+        }
+    }
+
+    let set = build_regex_set(&patterns, options).map_err(|error| match error {
+        PatternError::Invalid(mut invalid) => {
+            for pattern in &mut invalid {
+                pattern.line = Some(line_numbers[pattern.index]);
+            }
+            Error::from(PatternError::Invalid(invalid))
+        }
+        other => other.into(),
+    })?;
+
+    Ok((patterns, set))
+}
+
+/// Compiles the patterns into a single set. If that fails, every pattern is compiled on its own to
+/// report all invalid ones at once.
+fn build_regex_set(patterns: &[String], options: &Options) -> Result<RegexSet, PatternError> {
+    // Plain names only match processes with exactly that name, so "nginx" does not also match
+    // "nginx-helper". The other ways to write patterns decide this by themselves.
+    let exact_names = options.match_mode == MatchMode::Basename
+        && !(options.regex || options.fixed_strings || options.glob || options.exact);
+    let patterns: Vec<String> = patterns
+        .iter()
+        .map(|pattern| {
+            let pattern = if options.fixed_strings {
+                regex::escape(pattern)
+            } else if options.glob {
+                matcher::glob_to_regex(pattern)
+            } else if exact_names && matcher::is_plain_name(pattern) {
+                matcher::anchor(pattern)
+            } else {
+                pattern.clone()
+            };
+
+            if options.exact {
+                matcher::anchor(&pattern)
+            } else {
+                pattern
+            }
+        })
+        .collect();
+
+    RegexSetBuilder::new(&patterns)
+        .case_insensitive(!options.case_mode.is_case_sensitive())
+        .build()
+        .map_err(|set_error| {
+            let invalid: Vec<InvalidPattern> = patterns
+                .iter()
+                .enumerate()
+                .filter_map(|(index, pattern)| {
+                    RegexBuilder::new(pattern)
+                        .case_insensitive(!options.case_mode.is_case_sensitive())
+                        .build()
+                        .err()
+                        .map(|error| InvalidPattern {
+                            index,
+                            line: None,
+                            error,
+                        })
+                })
+                .collect();
+
+            if invalid.is_empty() {
+                PatternError::Set(set_error)
+            } else {
+                PatternError::Invalid(invalid)
+            }
+        })
+}
+
+fn all_processes(
+    options: &Options,
+    matcher: &Matcher,
+    handled: &mut HashSet<(Pid, u64)>,
+    reporter: &dyn Reporter,
+) -> Result<Vec<Process>, Error> {
+    let owners = Owners::find(options)?;
+    let iter = match owners.users {
+        None => Process::all(&options.proc_root, options.jobs)?,
+        Some(ref users) => Process::all_from_users(
+            &options.proc_root,
+            users.clone(),
+            options.user_id_kind,
+            options.jobs,
+        )?,
+    };
+
+    let sockets = if options.ports.is_empty() {
+        None
+    } else {
+        Some(
+            processes::listening_sockets(&options.proc_root, &options.ports)
+                .map_err(|err| anyhow!("{}", err))?,
+        )
+    };
+    let marker = match options.older_than_file {
+        Some(ref path) => Some(
+            path.metadata()
+                .and_then(|metadata| metadata.modified())
+                .with_context(|| {
+                    format!("Could not read modification time of {}", path.display())
+                })?,
+        ),
+        None => None,
+    };
+    let clock = if options.min_age.is_some() || options.max_age.is_some() || marker.is_some() {
+        Some(Clock::system(&options.proc_root).map_err(|err| anyhow!("{}", err))?)
+    } else {
+        None
+    };
+    let processes = iter
+        .filter_map(|result| loaded(result, reporter))
+        .filter(|process| owners.allow(options, process, reporter))
+        .filter(|process| options.include_kernel_threads || !process.is_kernel_thread())
+        .filter(|process| has_matching_state(options, process))
+        .filter(|process| has_matching_tty(options, process))
+        .filter(|process| has_matching_cgroup(options, process))
+        .filter(|process| options.sid.is_none_or(|sid| process.sid() == sid))
+        .filter(|process| options.pgid.is_none_or(|pgid| process.pgid() == pgid))
+        .filter(|process| !is_denied(matcher, process, handled, reporter))
+        .filter(|process| matcher.is_match(process))
+        // Reading the environment and open files is slow, so it is only done for processes that
+        // match otherwise.
+        .filter(|process| has_matching_env(options, process))
+        .filter(|process| has_listening_socket(process, sockets.as_ref()))
+        .filter(|process| {
+            clock.is_none_or(|clock| has_matching_age(options, process, &clock, marker))
+        })
+        .collect::<Vec<_>>();
+
+    Ok(without_pids(processes, &options.exclude_pids))
+}
+
+/// Skips processes that could not be loaded. Processes that exited while being read are expected,
+/// so they are only mentioned in verbose output.
+fn loaded(result: Result<Process, LoadError>, reporter: &dyn Reporter) -> Option<Process> {
+    match result {
+        Ok(process) => Some(process),
+        Err(err @ LoadError::Gone(_)) => {
+            reporter.info(&err.to_string());
+            None
+        }
+        Err(err) => {
+            reporter.warning(&format!("Could not load process: {}", err));
+            None
+        }
+    }
+}
+
+/// Who the processes must belong to, from --user, --mine, --exclude-user and --group.
+struct Owners {
+    /// Without any, processes of every user are allowed.
+    users: Option<Vec<uid_t>>,
+    excluded_users: Vec<uid_t>,
+    group: Option<gid_t>,
+}
+
+impl Owners {
+    fn find(options: &Options) -> Result<Owners, Error> {
+        let users = match &options.user_mode {
+            UserMode::Everybody => None,
+            UserMode::OnlyMe => Some(vec![users::get_current_uid()]),
+            UserMode::OnlyAny(names) => Some(find_users_by_name(names)?),
+        };
+        let group = match options.group {
+            Some(ref name) => Some(find_group_by_name(name)?),
+            None => None,
+        };
+
+        Ok(Owners {
+            users,
+            excluded_users: find_users_by_name(&options.exclude_users)?,
+            group,
+        })
+    }
+
+    fn allow(&self, options: &Options, process: &Process, reporter: &dyn Reporter) -> bool {
+        let user_id = process.user_id_of(options.user_id_kind);
+        self.users
+            .as_ref()
+            .is_none_or(|users| users.contains(&user_id))
+            && self.group.is_none_or(|gid| process.group_id() == gid)
+            && !is_excluded_user(options, process, &self.excluded_users, reporter)
+    }
+}
+
+/// Adds all descendants of the given processes to the list. Parents always come before their
+/// children.
+///
+/// Descendants are spared by the same options that spare matches, like --exclude-pid, and then so
+/// are their own descendants.
+fn with_descendants(
+    options: &Options,
+    processes: Vec<Process>,
+    targets: &Targets,
+    handled: &mut HashSet<(Pid, u64)>,
+    reporter: &dyn Reporter,
+) -> Result<Vec<Process>, Error> {
+    let table: Vec<Process> = Process::all(&options.proc_root, options.jobs)?
+        .flat_map(Result::ok)
+        // Zombies are already gone; waiting for them to be reaped could take forever.
+        .filter(|process| process.state() != 'Z')
+        .collect();
+    let owners = Owners::find(options)?;
+
+    Ok(add_descendants(processes, table, |process| {
+        options.exclude_pids.contains(&process.pid())
+            || targets.exclusions.is_excluded(process)
+            || !owners.allow(options, process, reporter)
+            || is_on_denylist(&targets.deny_set, process, handled, reporter)
+    }))
+}
+
+/// Adds the descendants from the process table to the list, leaving out the spared ones.
+fn add_descendants<F>(
+    mut processes: Vec<Process>,
+    table: Vec<Process>,
+    mut is_spared: F,
+) -> Vec<Process>
+where
+    F: FnMut(&Process) -> bool,
+{
+    let children = children_map(&table);
+    let roots: Vec<Pid> = processes.iter().map(Process::pid).collect();
+
+    let mut table: HashMap<Pid, Process> = table
+        .into_iter()
+        .map(|process| (process.pid(), process))
+        .collect();
+    let found = descendants(&children, &roots, |pid| {
+        table.get(&pid).is_none_or(&mut is_spared)
+    });
+    processes.extend(found.into_iter().filter_map(|pid| table.remove(&pid)));
+
+    processes
+}
+
+/// Maps every PID to the PIDs of its direct children.
+fn children_map(processes: &[Process]) -> HashMap<Pid, Vec<Pid>> {
+    let mut children: HashMap<Pid, Vec<Pid>> = HashMap::new();
+    for process in processes {
+        children
+            .entry(process.parent_pid())
+            .or_default()
+            .push(process.pid());
+    }
+    children
+}
+
+/// Maps every running PID to the PID of its parent.
+fn parent_map(options: &Options) -> Result<HashMap<Pid, Pid>, Error> {
+    Ok(Process::all(&options.proc_root, options.jobs)?
+        .flat_map(Result::ok)
+        .map(|process| (process.pid(), process.parent_pid()))
+        .collect())
+}
+
+/// Orders the processes so that every process comes before all of its ancestors, even when the
+/// processes in between did not match. Processes deeper in the tree are moved first; others keep
+/// their order.
+fn leaves_first(mut processes: Vec<Process>, parents: &HashMap<Pid, Pid>) -> Vec<Process> {
+    let depth = |pid: Pid| {
+        // Bounded by the size of the table, so a bogus cycle cannot loop forever.
+        let mut depth = 0;
+        let mut current = pid;
+        while let Some(&parent) = parents.get(&current) {
+            if depth > parents.len() {
+                break;
+            }
+            depth += 1;
+            current = parent;
+        }
+        depth
+    };
+    processes.sort_by_key(|process| Reverse(depth(process.pid())));
+    processes
+}
+
+/// Finds all descendants of the given PIDs, breadth first, so shallower descendants come first.
+/// Every PID is only visited once, so a bogus cycle in the process table cannot loop forever.
+/// Spared PIDs are left out, and so are their descendants.
+fn descendants<F>(children: &HashMap<Pid, Vec<Pid>>, roots: &[Pid], mut is_spared: F) -> Vec<Pid>
+where
+    F: FnMut(Pid) -> bool,
+{
+    let mut seen: HashSet<Pid> = roots.iter().cloned().collect();
+    let mut queue: VecDeque<Pid> = roots.iter().cloned().collect();
+    let mut found = Vec::new();
+
+    while let Some(pid) = queue.pop_front() {
+        for &child in children.get(&pid).map(Vec::as_slice).unwrap_or(&[]) {
+            if seen.insert(child) && !is_spared(child) {
+                found.push(child);
+                queue.push_back(child);
+            }
+        }
+    }
+
+    found
+}
+
+fn has_matching_state(options: &Options, process: &Process) -> bool {
+    match options.states {
+        Some(ref states) => states.contains(&process.state()),
+        // Zombies have already exited; there is nothing left to signal.
+        None => process.state() != 'Z',
+    }
+}
+
+fn has_matching_tty(options: &Options, process: &Process) -> bool {
+    match options.tty {
+        Some(ref tty) => process.tty() == Some(tty.as_str()),
+        None => true,
+    }
+}
+
+fn has_matching_cgroup(options: &Options, process: &Process) -> bool {
+    match options.cgroup {
+        Some(ref cgroup) => process
+            .cgroups()
+            .iter()
+            .any(|path| path.contains(cgroup.as_str())),
+        None => true,
+    }
+}
+
+/// Checks if the process has one of the listening sockets open, when looking for any.
+fn has_listening_socket(process: &Process, sockets: Option<&HashSet<u64>>) -> bool {
+    match sockets {
+        Some(sockets) => process
+            .socket_inodes()
+            .iter()
+            .any(|inode| sockets.contains(inode)),
+        None => true,
+    }
+}
+
+fn has_matching_env(options: &Options, process: &Process) -> bool {
+    if options.env.is_empty() {
+        return true;
+    }
+
+    match process.environment() {
+        Some(environment) => options.env.iter().all(|var| environment.contains(var)),
+        None => false,
+    }
+}
+
+/// Checks the age limits, and that the process was started before the marker file of
+/// --older-than-file was modified.
+fn has_matching_age(
+    options: &Options,
+    process: &Process,
+    clock: &Clock,
+    marker: Option<SystemTime>,
+) -> bool {
+    let age = process.age(clock);
+    options.min_age.is_none_or(|min_age| age >= min_age)
+        && options.max_age.is_none_or(|max_age| age <= max_age)
+        && marker.is_none_or(|marker| process.started_at(clock) < marker)
+}
+
+fn is_excluded_user(
+    options: &Options,
+    process: &Process,
+    excluded_users: &[uid_t],
+    reporter: &dyn Reporter,
+) -> bool {
+    let is_excluded = excluded_users.contains(&process.user_id_of(options.user_id_kind));
+
+    if is_excluded {
+        reporter.skipped(process, "it is owned by an excluded user");
+    }
+
+    is_excluded
+}
+
+const DENIED: &str = "it is on the denylist of the config file.";
+
+/// Refuses processes that the patterns match, but that the denylist in the config file keeps from
+/// being signaled.
+fn is_denied(
+    matcher: &Matcher,
+    process: &Process,
+    handled: &mut HashSet<(Pid, u64)>,
+    reporter: &dyn Reporter,
+) -> bool {
+    if !matcher.is_denied(process) {
+        return false;
+    }
+
+    refuse_denied(process, handled, reporter);
+    true
+}
+
+/// Refuses processes on the denylist of the config file that were found without patterns, like
+/// with --pid or --tree.
+fn is_on_denylist(
+    deny_set: &RegexSet,
+    process: &Process,
+    handled: &mut HashSet<(Pid, u64)>,
+    reporter: &dyn Reporter,
+) -> bool {
+    if !deny_set.is_match(process.name()) {
+        return false;
+    }
+
+    refuse_denied(process, handled, reporter);
+    true
+}
+
+/// Reports a denied process once, and remembers it as handled. --watch finds it again on every
+/// poll.
+fn refuse_denied(process: &Process, handled: &mut HashSet<(Pid, u64)>, reporter: &dyn Reporter) {
+    if handled.insert((process.pid(), process.start_time())) {
+        reporter.refused(process, DENIED);
+    }
+}
+
+fn without_pids(mut processes: Vec<Process>, excluded_pids: &[Pid]) -> Vec<Process> {
+    processes.retain(|process| !excluded_pids.contains(&process.pid()));
+    processes
+}
+
+fn skip_protected(
+    options: &Options,
+    processes: Vec<Process>,
+    reporter: &dyn Reporter,
+) -> Vec<Process> {
+    let own_pid = Pid::this();
+    let own_pgid = nix::unistd::getpgrp();
+    let parent_pid = if options.protect_parent {
+        Some(nix::unistd::getppid())
+    } else {
+        None
+    };
+
+    processes
+        .into_iter()
+        .filter(|process| {
+            let pid = process.pid();
+
+            if pid == Pid::from_raw(1) && !options.allow_pid1 {
+                reporter.refused(process, "use --allow-pid1 if you really want to.");
+                return false;
+            }
+
+            let reason = if pid == own_pid && !options.include_self {
+                "this is graceful-shutdown itself"
+            } else if Some(pid) == parent_pid {
+                "this is the parent of graceful-shutdown"
+            } else if options.protected_pids.contains(&pid) {
+                "it is protected"
+            } else if options.process_group && process.pgid() == own_pgid && !options.include_self {
+                "it is in the process group of graceful-shutdown"
+            } else if process.is_kernel_thread() && !options.dry_run {
+                "it is a kernel thread"
+            } else {
+                return true;
+            };
+
+            reporter.skipped(process, reason);
+            false
+        })
+        .collect()
+}
+
+/// Keeps only the first occurrence of every process, so none is signaled or counted twice. The
+/// same PID can for example be given several times with --pid.
+fn unique_pids(processes: Vec<Process>) -> Vec<Process> {
+    let mut seen = HashSet::new();
+    processes
+        .into_iter()
+        .filter(|process| seen.insert(process.pid()))
+        .collect()
+}
+
+/// Drops the processes that were already handled, and remembers the rest as handled. Processes are
+/// told apart by their start time too, in case a PID is reused.
+fn not_handled(processes: Vec<Process>, handled: &mut HashSet<(Pid, u64)>) -> Vec<Process> {
+    processes
+        .into_iter()
+        .filter(|process| handled.insert((process.pid(), process.start_time())))
+        .collect()
+}
+
+/// Keeps only the first matched process of every process group, so no group is signaled more than
+/// once.
+fn one_per_process_group(processes: Vec<Process>) -> Vec<Process> {
+    let mut seen = HashSet::new();
+    processes
+        .into_iter()
+        .filter(|process| seen.insert(process.pgid()))
+        .collect()
+}
+
+/// Keeps only the first process for --first-match, and reports the others as skipped.
+fn only_first(processes: Vec<Process>, reporter: &dyn Reporter) -> Vec<Process> {
+    let mut processes = processes.into_iter();
+    let first = processes.next();
+    for process in processes {
+        reporter.skipped(&process, "only the first match is signaled");
+    }
+    first.into_iter().collect()
+}
+
+/// Keeps only the oldest or newest process of every name, and reports the others as skipped. The
+/// kept processes stay in the same order.
+fn one_per_name(
+    processes: Vec<Process>,
+    selection: AgeSelection,
+    reporter: &dyn Reporter,
+) -> Vec<Process> {
+    // Ties are broken by PID, as PIDs are mostly handed out in order.
+    let age = |process: &Process| (process.start_time(), i32::from(process.pid()));
+    let mut kept: HashMap<&str, &Process> = HashMap::new();
+    for process in &processes {
+        let entry = kept.entry(process.name()).or_insert(process);
+        let is_better = match selection {
+            AgeSelection::Oldest => age(process) < age(entry),
+            AgeSelection::Newest => age(process) > age(entry),
+        };
+        if is_better {
+            *entry = process;
+        }
+    }
+    let kept: HashSet<Pid> = kept.values().map(|process| process.pid()).collect();
+
+    let reason = match selection {
+        AgeSelection::Oldest => "an older process with the same name is signaled",
+        AgeSelection::Newest => "a newer process with the same name is signaled",
+    };
+    processes
+        .into_iter()
+        .filter(|process| {
+            let keep = kept.contains(&process.pid());
+            if !keep {
+                reporter.skipped(process, reason);
+            }
+            keep
+        })
+        .collect()
+}
+
+#[derive(Debug, Error)]
+pub enum LimitError {
+    #[error("Matched {0} processes, which is more than the limit of {1}. Nothing was signaled.")]
+    Exceeded(usize, usize),
+}
+
+fn enforce_limit(
+    options: &Options,
+    processes: &[Process],
+    reporter: &dyn Reporter,
+) -> Result<(), LimitError> {
+    let limit = match options.limit {
+        Some(limit) if processes.len() > limit => limit,
+        _ => return Ok(()),
+    };
+
+    for process in processes {
+        reporter.matched(process);
+    }
+
+    Err(LimitError::Exceeded(processes.len(), limit))
+}
+
+fn open_pidfds(processes: &mut [Process], reporter: &dyn Reporter) {
+    if !pidfd::PidFd::is_supported() {
+        reporter.info("This kernel does not support pidfds. Falling back to polling /proc.");
+        return;
+    }
+
+    for process in processes {
+        process.open_pidfd();
+    }
+}
+
+fn use_process_groups(options: &Options, processes: &mut [Process]) {
+    if options.process_group {
+        for process in processes {
+            process.use_process_group();
+        }
+    }
+}
+
+fn use_grace_periods(grace_map: &GraceMap, processes: &mut [Process]) {
+    if grace_map.is_empty() {
+        return;
+    }
+
+    for process in processes {
+        if let Some(wait) = grace_map.wait_time(process) {
+            process.set_grace_period(wait);
+        }
+    }
+}
+
+fn explicit_processes(
+    options: &Options,
+    deny_set: &RegexSet,
+    handled: &mut HashSet<(Pid, u64)>,
+    reporter: &dyn Reporter,
+) -> Result<Vec<Process>, Error> {
+    let processes = options
+        .pids
+        .iter()
+        .map(|&pid| Process::from_pid(&options.proc_root, pid).map_err(|err| anyhow!("{}", err)))
+        .collect::<Result<Vec<Process>, Error>>()?;
+
+    Ok(processes
+        .into_iter()
+        .filter(|process| !is_on_denylist(deny_set, process, handled, reporter))
+        .collect())
+}
+
+#[derive(Debug, Error)]
+pub enum UserError {
+    #[error("Could not find user with name \"{0}\"")]
+    NotFound(String),
+    #[error("\"{0}\" is not a valid user ID")]
+    InvalidUid(String),
+}
+
+/// Finds the UID of a user. Numeric names are used as UIDs directly, unless there is a user with
+/// that name. Prefix the name with "#" to always treat it as a UID.
+fn find_user_by_name(name: &str) -> Result<uid_t, UserError> {
+    if let Some(uid) = name.strip_prefix('#') {
+        return parse_uid(uid);
+    }
+
+    match users::get_user_by_name(name) {
+        Some(user) => Ok(user.uid()),
+        None if looks_numeric(name) => parse_uid(name),
+        None => Err(UserError::NotFound(name.to_owned())),
+    }
+}
+
+fn looks_numeric(name: &str) -> bool {
+    let digits = name.strip_prefix('-').unwrap_or(name);
+    !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+}
+
+fn parse_uid(uid: &str) -> Result<uid_t, UserError> {
+    uid.parse()
+        .map_err(|_| UserError::InvalidUid(uid.to_owned()))
+}
+
+#[derive(Debug, Error)]
+pub enum GroupError {
+    #[error("Could not find group with name \"{0}\"")]
+    NotFound(String),
+}
+
+fn find_group_by_name(name: &str) -> Result<gid_t, GroupError> {
+    users::get_group_by_name(name)
+        .ok_or_else(|| GroupError::NotFound(name.to_owned()))
+        .map(|group| group.gid())
+}
+
+fn find_users_by_name(names: &[String]) -> Result<Vec<uid_t>, UserError> {
+    names.iter().map(|name| find_user_by_name(name)).collect()
+}
+
+fn strip_comment(line: String) -> String {
+    match line.find('#') {
+        Some(index) => line[0..index].trim().to_string(),
+        None => line,
+    }
+}
+
+/// Separates the processes that have been running for longer than --stuck-after from the rest.
+fn split_stuck(
+    options: &Options,
+    processes: Vec<Process>,
+) -> Result<(Vec<Process>, Vec<Process>), Error> {
+    match options.stuck_after {
+        Some(stuck_after) => {
+            let clock = Clock::system(&options.proc_root).map_err(|err| anyhow!("{}", err))?;
+            Ok(partition_by_age(processes, stuck_after, |process| {
+                process.age(&clock)
+            }))
+        }
+        None => Ok((Vec::new(), processes)),
+    }
+}
+
+/// Splits the processes into the ones that are at least `stuck_after` old, and the younger ones.
+fn partition_by_age<F>(
+    processes: Vec<Process>,
+    stuck_after: Duration,
+    age: F,
+) -> (Vec<Process>, Vec<Process>)
+where
+    F: Fn(&Process) -> Duration,
+{
+    processes
+        .into_iter()
+        .partition(|process| age(process) >= stuck_after)
+}
+
+fn dry_run(options: &Options, stuck: &[Process], processes: &[Process], reporter: &dyn Reporter) {
+    for process in stuck {
+        reporter.will_signal(process, options.escalation.last_signal());
+    }
+    for process in processes {
+        reporter.will_signal(process, options.terminate_signal);
+    }
+}
+
+/// Like `dry_run`, but only shows how the processes differ from the dry run saved in the file.
+fn compared_dry_run(
+    options: &Options,
+    path: &Path,
+    stuck: &[Process],
+    processes: &[Process],
+    reporter: &dyn Reporter,
+) -> Result<(), Error> {
+    let file = File::open(path).with_context(|| format!("Could not open {}", path.display()))?;
+    let previous = json::read_dry_run(BufReader::new(file))
+        .map_err(|err| anyhow!("Could not read dry run from {}: {}", path.display(), err))?;
+    let current: Vec<ProcessDescription> = stuck
+        .iter()
+        .chain(processes)
+        .map(ProcessDescription::from)
+        .collect();
+    let diff = json::DryRunDiff::between(&previous, &current);
+    let is_new = |process: &Process| diff.added.contains(&ProcessDescription::from(process));
+
+    for process in stuck.iter().filter(|process| is_new(process)) {
+        reporter.will_signal(process, options.escalation.last_signal());
+    }
+    for process in processes.iter().filter(|process| is_new(process)) {
+        reporter.will_signal(process, options.terminate_signal);
+    }
+    for process in &diff.removed {
+        reporter.would_no_longer_signal(process);
+    }
+    if diff.is_empty() {
+        reporter.info(&format!(
+            "Would signal the same processes as in {}",
+            path.display()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Shuts down the processes. Stuck processes go straight to the last step of the escalation,
+/// before the others get the chance to shut down gracefully.
+fn real_run(
+    options: &Options,
+    mut stuck: Vec<Process>,
+    mut processes: Vec<Process>,
+    report: &mut RunReport,
+    deadline: Deadline,
+    reporter: &dyn Reporter,
+    sender: &dyn SignalSender,
+) -> Result<(), Error> {
+    if options.freeze_first {
+        freeze_all(options, &mut stuck, reporter, sender);
+        freeze_all(options, &mut processes, reporter, sender);
+    }
+
+    let result = if stuck.is_empty() {
+        Ok(())
+    } else {
+        let last_step = options.escalation.steps().len() - 1;
+        terminate_all(
+            options, &mut stuck, last_step, report, deadline, reporter, sender,
+        )
+    };
+    let result = result.and_then(|()| {
+        if processes.is_empty() {
+            return Ok(());
+        }
+        terminate_all(
+            options,
+            &mut processes,
+            0,
+            report,
+            deadline,
+            reporter,
+            sender,
+        )
+    });
+
+    // Never leave processes frozen when giving up.
+    if result.is_err() {
+        for process in stuck.iter_mut().chain(processes.iter_mut()) {
+            resume(options, process, reporter, sender);
+        }
+    }
+
+    result
+}
+
+/// Stops the processes with SIGSTOP, so none of them can start new processes, or restart the
+/// others, while they are shut down one by one.
+fn freeze_all(
+    options: &Options,
+    processes: &mut [Process],
+    reporter: &dyn Reporter,
+    sender: &dyn SignalSender,
+) {
+    let signal = Signal::Standard(SIGSTOP);
+    for process in processes {
+        if send_with_error_handling(options, signal, process, reporter, sender) {
+            reporter.signaled(process, signal);
+            process.set_frozen(true);
+        }
+    }
+    reporter.flush();
+}
+
+/// Continues a process frozen with --freeze-first, so it can handle the signals it got while it
+/// was stopped, or go on running when it is spared.
+fn resume(
+    options: &Options,
+    process: &mut Process,
+    reporter: &dyn Reporter,
+    sender: &dyn SignalSender,
+) {
+    if !process.is_frozen() {
+        return;
+    }
+
+    process.set_frozen(false);
+    let signal = Signal::Standard(SIGCONT);
+    if sender.is_alive(process)
+        && send_with_error_handling(options, signal, process, reporter, sender)
+    {
+        reporter.signaled(process, signal);
+    }
+}
+
+/// Runs the escalation on the processes, starting at the given step.
+fn terminate_all(
+    options: &Options,
+    processes: &mut Vec<Process>,
+    first_step: usize,
+    report: &mut RunReport,
+    deadline: Deadline,
+    reporter: &dyn Reporter,
+    sender: &dyn SignalSender,
+) -> Result<(), Error> {
+    let mut confirmation = if options.interactive {
+        Some(Confirmation::open()?)
+    } else {
+        None
+    };
+
+    let steps = options.escalation.steps();
+    let last_index = steps.len() - 1;
+
+    for (index, step) in steps.iter().enumerate().skip(first_step) {
+        let is_first_step = index == first_step;
+        let is_escalation = index > 0;
+        let is_final_escalation = is_escalation && index == last_index;
+        // Only SIGKILL is sure to kill. Any other final signal is waited on like the ones before.
+        let is_kill = is_final_escalation && step.signal.is_forceful();
+
+        // Once the deadline has passed, no more signals are sent; the remaining processes are
+        // reported as still alive.
+        if !is_first_step && deadline.has_passed() {
+            break;
+        }
+
+        // Kill the deepest descendants first, so no parent gets the chance to replace them. With
+        // --leaves-first, they already are.
+        if is_final_escalation && options.tree && !options.leaves_first {
+            processes.reverse();
+        }
+
+        if !is_first_step {
+            reporter.escalating(step.signal, is_final_escalation);
+        }
+
+        // Try to signal all the processes. If any process failed to receive the signal, then
+        // remove it from the list so the coming waiting part does not wait for any process that
+        // will not be terminated anyway.
+        //
+        // As an example, if a process has a "Permission denied" error, it will fail to get the
+        // terminate signal. Why would we be waiting on this process and then try to kill it when
+        // that too will fail?
+        let mut is_first_send = true;
+        processes.retain_mut(|process| {
+            if let (true, Some(confirmation)) = (is_first_step, confirmation.as_mut()) {
+                let question = format!(
+                    "Send {signal} to process {process}?",
+                    signal = step.signal,
+                    process = human_process_description(options, process),
+                );
+                if !confirmation.ask(&question) {
+                    resume(options, process, reporter, sender);
+                    report.skipped += 1;
+                    return false;
+                }
+            }
+
+            // A killed process cannot do any work on its way out, so there is nothing to spread
+            // out. Delaying SIGKILL would only keep the stragglers running for longer.
+            let delays =
+                !step.signal.is_forceful() && options.signal_delay > Duration::from_secs(0);
+            if !is_first_send && delays {
+                ::std::thread::sleep(options.signal_delay);
+            }
+            is_first_send = false;
+
+            if send_with_error_handling(options, step.signal, process, reporter, sender) {
+                process.mark_signaled(Instant::now());
+                if is_kill {
+                    report.killed.push((&*process).into());
+                    reporter.killed(process, step.signal);
+                } else {
+                    reporter.signaled(process, step.signal);
+                }
+                // A stopped process only handles the signal once it runs again.
+                if !step.signal.is_forceful() {
+                    resume(options, process, reporter, sender);
+                }
+                true
+            } else {
+                resume(options, process, reporter, sender);
+                report.failed.push((&*process).into());
+                false
+            }
+        });
+        reporter.flush();
+
+        if index == 0
+            && processes
+                .iter()
+                .any(|process| process.grace_period().is_some())
+        {
+            wait_with_grace_periods(
+                options, processes, step.wait, report, deadline, reporter, sender,
+            );
+            return Ok(());
+        }
+
+        if step.wait == Duration::from_secs(0) && (is_kill || !is_final_escalation) {
+            if index == last_index {
+                // Not waiting means that every signaled process counts as terminated, unless they
+                // were already counted as killed.
+                if !is_escalation {
+                    report
+                        .terminated
+                        .extend(processes.iter().map(ProcessDescription::from));
+                }
+                return Ok(());
+            }
+            continue;
+        }
+
+        let resend = match options.resend_after {
+            Some(after) if !is_escalation => Some((after, step.signal)),
+            _ => None,
+        };
+        let died = wait_for_exit(
+            options,
+            processes,
+            deadline.cap(step.wait),
+            resend,
+            report,
+            reporter,
+            sender,
+        );
+        if !is_kill {
+            report.terminated.extend(died);
+        }
+
+        if processes.is_empty() {
+            return Ok(());
+        }
+
+        if !is_escalation {
+            for process in processes.iter() {
+                reporter.info(&survival_note(options, process, step.signal));
+            }
+        }
+    }
+
+    // Time is up, and the last step (or the deadline) did not make all processes exit.
+    reporter.timed_out(processes);
+    report
+        .alive
+        .extend(processes.iter().map(ProcessDescription::from));
+
+    Ok(())
+}
+
+/// Waits for processes that have their own waiting time from --grace-map, and escalates each of
+/// them as soon as its time is up while still waiting for the others. Processes without one wait
+/// for the default time.
+///
+/// --grace-map can only be used with the classic escalation, so the only step left after waiting
+/// is the kill signal, if there is one.
+fn wait_with_grace_periods(
+    options: &Options,
+    processes: &mut Vec<Process>,
+    default_wait: Duration,
+    report: &mut RunReport,
+    deadline: Deadline,
+    reporter: &dyn Reporter,
+    sender: &dyn SignalSender,
+) {
+    let start = Instant::now();
+    let wait_time = |process: &Process| process.grace_period().unwrap_or(default_wait);
+    let mut waits: Vec<Duration> = processes.iter().map(wait_time).collect();
+    waits.sort();
+    waits.dedup();
+
+    let first_signal = options.escalation.first_signal();
+    let kill_signal = options.escalation.steps().get(1).map(|step| step.signal);
+
+    for wait in waits {
+        let remaining = wait.checked_sub(start.elapsed()).unwrap_or_default();
+        let died = wait_for_exit(
+            options,
+            processes,
+            deadline.cap(remaining),
+            None,
+            report,
+            reporter,
+            sender,
+        );
+        report.terminated.extend(died);
+
+        let (mut expired, waiting): (Vec<Process>, Vec<Process>) = processes
+            .drain(..)
+            .partition(|process| wait_time(process) <= wait);
+        *processes = waiting;
+        if expired.is_empty() {
+            continue;
+        }
+
+        for process in &expired {
+            reporter.info(&survival_note(options, process, first_signal));
+        }
+
+        match kill_signal {
+            Some(signal) if !deadline.has_passed() => {
+                reporter.escalating(signal, true);
+                if options.tree && !options.leaves_first {
+                    expired.reverse();
+                }
+                expired.retain_mut(|process| {
+                    if send_with_error_handling(options, signal, process, reporter, sender) {
+                        process.mark_signaled(Instant::now());
+                        if signal.is_forceful() {
+                            report.killed.push((&*process).into());
+                            reporter.killed(process, signal);
+                        } else {
+                            reporter.signaled(process, signal);
+                        }
+                        true
+                    } else {
+                        report.failed.push((&*process).into());
+                        false
+                    }
+                });
+                // Only SIGKILL is sure to kill. There is no time left to wait for the others.
+                if !signal.is_forceful() {
+                    let died = wait_for_exit(
+                        options,
+                        &mut expired,
+                        Duration::from_secs(0),
+                        None,
+                        report,
+                        reporter,
+                        sender,
+                    );
+                    report.terminated.extend(died);
+                    if !expired.is_empty() {
+                        reporter.timed_out(&expired);
+                        report
+                            .alive
+                            .extend(expired.iter().map(ProcessDescription::from));
+                    }
+                }
+            }
+            _ => {
+                reporter.timed_out(&expired);
+                report
+                    .alive
+                    .extend(expired.iter().map(ProcessDescription::from));
+            }
+        }
+        reporter.flush();
+    }
+}
+
+/// Waits until all processes have exited, or the waiting time runs out. Exited processes are
+/// removed from the list and returned. If given, the signal is sent once more to the remaining
+/// processes after that much of the waiting time.
+fn wait_for_exit(
+    options: &Options,
+    processes: &mut Vec<Process>,
+    wait_time: Duration,
+    mut resend: Option<(Duration, Signal)>,
+    report: &mut RunReport,
+    reporter: &dyn Reporter,
+    sender: &dyn SignalSender,
+) -> Vec<ProcessDescription> {
+    let start = Instant::now();
+    let total = processes.len();
+    let mut last_progress = start;
+    let mut died = Vec::new();
+    let mut backoff = Backoff::new(Duration::from_millis(10), options.poll_interval);
+
+    // Processes often exit as soon as they are signaled, so look before sleeping the first time.
+    died.extend(remove_dead(
+        processes,
+        Instant::now(),
+        |process| sender.is_alive(process),
+        reporter,
+    ));
+    if processes.is_empty() {
+        return died;
+    }
+
+    while let Some(remaining) = wait_time.checked_sub(start.elapsed()) {
+        let interval = backoff.next().unwrap_or(options.poll_interval);
+        let until_progress = options
+            .progress_interval
+            .checked_sub(last_progress.elapsed())
+            .unwrap_or_default()
+            .max(interval);
+        let until_resend = match resend {
+            Some((after, _)) => after.checked_sub(start.elapsed()).unwrap_or_default(),
+            None => remaining,
+        };
+        let remaining = remaining.min(until_resend);
+        let pidfds: Option<Vec<&pidfd::PidFd>> = processes.iter().map(Process::pidfd).collect();
+        match pidfds {
+            // Sleep until any process exits, if possible. Wake up in time for the next progress
+            // report, though.
+            Some(ref pidfds) if pidfd::wait_any(pidfds, remaining.min(until_progress)).is_ok() => {}
+            _ => ::std::thread::sleep(interval.min(remaining)),
+        }
+        let died_before = died.len();
+
+        died.extend(remove_dead(
+            processes,
+            Instant::now(),
+            |process| sender.is_alive(process),
+            reporter,
+        ));
+
+        if processes.is_empty() {
+            break;
+        }
+
+        // Processes that are exiting tend to do so together, so look closer again.
+        let any_died = died.len() > died_before;
+        if any_died {
+            backoff.reset();
+        }
+
+        if any_died || last_progress.elapsed() >= options.progress_interval {
+            reporter.waiting(processes.len(), total, start.elapsed());
+            last_progress = Instant::now();
+        }
+
+        if let Some((after, signal)) = resend {
+            if start.elapsed() >= after {
+                resend = None;
+                // Like after the first send, there is no point waiting on processes that cannot
+                // be signaled.
+                processes.retain(|process| {
+                    if !sender.is_alive(process) {
+                        true
+                    } else if send_with_error_handling(options, signal, process, reporter, sender) {
+                        reporter.signaled(process, signal);
+                        true
+                    } else {
+                        report.failed.push(process.into());
+                        false
+                    }
+                });
+                if processes.is_empty() {
+                    break;
+                }
+            }
+        }
+    }
+
+    died
+}
+
+/// Removes the processes that have exited, reporting how long each of them took to exit after it
+/// was first signaled.
+fn remove_dead<F>(
+    processes: &mut Vec<Process>,
+    now: Instant,
+    is_alive: F,
+    reporter: &dyn Reporter,
+) -> Vec<ProcessDescription>
+where
+    F: Fn(&Process) -> bool,
+{
+    let mut died = Vec::new();
+
+    processes.retain(|process| {
+        let is_alive = is_alive(process);
+
+        if !is_alive {
+            reporter.died(process, process.time_since_signaled(now));
+            died.push(process.into());
+        }
+
+        is_alive
+    });
+
+    died
+}
+
+#[must_use]
+fn send_with_error_handling(
+    options: &Options,
+    signal: Signal,
+    process: &Process,
+    reporter: &dyn Reporter,
+    sender: &dyn SignalSender,
+) -> bool {
+    send_with_retries(
+        signal,
+        process,
+        options.retries,
+        reporter,
+        |process, signal| {
+            if options.signal_threads {
+                send_to_threads(process, signal, sender)
+            } else {
+                sender.send(process, signal)
+            }
+        },
+    )
+}
+
+/// Sends the signal to each thread of the process in turn.
+fn send_to_threads(
+    process: &Process,
+    signal: Signal,
+    sender: &dyn SignalSender,
+) -> Result<(), KillError> {
+    let threads = sender.threads(process);
+    if threads.is_empty() {
+        return Err(KillError::DoesNotExist);
+    }
+
+    for tid in threads {
+        match sender.send_to_thread(process, tid, signal) {
+            // Threads can exit while the rest of the process lives on.
+            Ok(()) | Err(KillError::DoesNotExist) => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(())
+}
+
+/// Sends the signal using the given function, retrying a few times with a short backoff if it
+/// fails in an unexpected way. Those failures can be transient, unlike missing permissions.
+#[must_use]
+fn send_with_retries<F>(
+    signal: Signal,
+    process: &Process,
+    retries: u32,
+    reporter: &dyn Reporter,
+    send: F,
+) -> bool
+where
+    F: Fn(&Process, Signal) -> Result<(), KillError>,
+{
+    let mut backoff = Backoff::new(Duration::from_millis(10), Duration::from_millis(100));
+    let mut attempts_left = retries;
+
+    loop {
+        match send(process, signal) {
+            Ok(_) => return true,
+            // Process quit before we had time to signal it? That should be fine. The next steps
+            // will verify that it is gone instead.
+            Err(KillError::DoesNotExist) => return true,
+            Err(KillError::UnexpectedError(_)) if attempts_left > 0 => {
+                attempts_left -= 1;
+                ::std::thread::sleep(backoff.next().unwrap_or_default());
+            }
+            Err(error) => {
+                reporter.failed(process, signal, &error);
+                return false;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reporter::NullReporter;
+    use sender::MockSender;
+    use std::cell::RefCell;
+    use structopt::StructOpt;
+
+    /// Remembers a short description of every call, and when it was made, for asserting on later.
+    #[derive(Default)]
+    struct RecordingReporter {
+        events: RefCell<Vec<(String, Instant)>>,
+    }
+
+    impl RecordingReporter {
+        fn events(&self) -> Vec<String> {
+            self.events
+                .borrow()
+                .iter()
+                .map(|(event, _)| event.clone())
+                .collect()
+        }
+
+        /// The events that start with the given text, with how long after the start they came.
+        fn times_of(&self, prefix: &str, start: Instant) -> Vec<(String, Duration)> {
+            self.events
+                .borrow()
+                .iter()
+                .filter(|(event, _)| event.starts_with(prefix))
+                .map(|(event, time)| (event.clone(), time.duration_since(start)))
+                .collect()
+        }
+
+        fn push(&self, event: String) {
+            self.events.borrow_mut().push((event, Instant::now()));
+        }
+
+        fn record(&self, event: &str, process: &Process, detail: &str) {
+            self.push(format!("{} {}: {}", event, process.pid(), detail));
+        }
+    }
+
+    impl Reporter for RecordingReporter {
+        fn warning(&self, _message: &str) {}
+        fn info(&self, _message: &str) {}
+        fn nothing_matched(&self) {
+            self.push(String::from("nothing_matched"));
+        }
+        fn skipped(&self, process: &Process, reason: &str) {
+            self.record("skipped", process, reason);
+        }
+        fn refused(&self, process: &Process, reason: &str) {
+            self.record("refused", process, reason);
+        }
+        fn matched(&self, process: &Process) {
+            self.record("matched", process, "");
+        }
+        fn will_signal(&self, process: &Process, signal: Signal) {
+            self.record("will_signal", process, &signal.to_string());
+        }
+        fn would_no_longer_signal(&self, process: &ProcessDescription) {
+            self.push(format!("would_no_longer_signal {}", process.pid));
+        }
+        fn escalating(&self, _signal: Signal, _is_final: bool) {}
+        fn signaled(&self, process: &Process, signal: Signal) {
+            self.record("signaled", process, &signal.to_string());
+        }
+        fn killed(&self, process: &Process, signal: Signal) {
+            self.record("killed", process, &signal.to_string());
+        }
+        fn died(&self, process: &Process, termination_time: Option<Duration>) {
+            let detail = termination_time
+                .map(|time| format!("{}ms", time.as_millis()))
+                .unwrap_or_default();
+            self.record("died", process, &detail);
+        }
+        fn failed(&self, process: &Process, signal: Signal, _error: &KillError) {
+            self.record("failed", process, &signal.to_string());
+        }
+        fn waiting(&self, _remaining: usize, _total: usize, _elapsed: Duration) {}
+        fn timed_out(&self, _processes: &[Process]) {}
+        fn finished(&self, _summary: &Summary) {}
+        fn flush(&self) {}
+    }
+
+    #[test]
+    fn it_derives_success_from_the_report() {
+        let description = ProcessDescription::from(&Process::fake(42, "sleep", "sleep 60"));
+        let mut report = RunReport {
+            matched: 4,
+            skipped: 1,
+            terminated: vec![description.clone()],
+            killed: vec![description.clone()],
+            ..RunReport::default()
+        };
+        assert!(report.success());
+
+        report.failed.push(description.clone());
+        assert!(!report.success());
+
+        report.failed.clear();
+        report.alive.push(description.clone());
+        assert!(!report.success());
+
+        assert_eq!(
+            report.summary(),
+            Summary {
+                terminated: 1,
+                killed: 1,
+                alive: 1,
+                failed: 0,
+                skipped: 1,
+            }
+        );
+        assert!(RunReport::default().success());
+    }
+
+    #[test]
+    fn it_resolves_multiple_users() {
+        let names = vec![String::from("root"), String::from("root")];
+        assert_eq!(
+            find_users_by_name(&names).expect("Failed to resolve"),
+            vec![0, 0]
+        );
+    }
+
+    #[test]
+    fn it_names_the_unknown_user() {
+        let names = vec![String::from("root"), String::from("no-such-user-for-sure")];
+
+        match find_users_by_name(&names) {
+            Err(UserError::NotFound(ref name)) => assert_eq!(name, "no-such-user-for-sure"),
+            other => panic!("Expected user to not be found, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_excludes_processes_by_owner() {
+        let options = options(&["--exclude-user", "root"]);
+        let excluded_users = find_users_by_name(&options.exclude_users).expect("Failed to resolve");
+        let processes = [
+            Process::fake(100, "node", "node server.js").with_user(0),
+            Process::fake(101, "node", "node server.js").with_user(1000),
+        ];
+
+        let remaining: Vec<i32> = processes
+            .iter()
+            .filter(|process| !is_excluded_user(&options, process, &excluded_users, &NullReporter))
+            .map(|process| process.pid().into())
+            .collect();
+
+        assert_eq!(excluded_users, vec![0]);
+        assert_eq!(remaining, vec![101]);
+    }
+
+    #[test]
+    fn it_resolves_numeric_uids() {
+        assert_eq!(find_user_by_name("root").unwrap(), 0);
+        assert_eq!(find_user_by_name("#0").unwrap(), 0);
+        assert_eq!(find_user_by_name("1000").unwrap(), 1000);
+        assert_eq!(find_user_by_name("#1000").unwrap(), 1000);
+        assert_eq!(find_user_by_name("4294967295").unwrap(), 4294967295);
+    }
+
+    #[test]
+    fn it_rejects_invalid_uids() {
+        for name in &["-1", "#-1", "4294967296", "#abc", "#"] {
+            match find_user_by_name(name) {
+                Err(UserError::InvalidUid(_)) => {}
+                other => panic!("Expected {} to be invalid, got {:?}", name, other),
+            }
+        }
+
+        match find_user_by_name("12abc") {
+            Err(UserError::NotFound(_)) => {}
+            other => panic!("Expected user to not be found, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_resolves_groups() {
+        let options = options(&["--group", "root"]);
+        assert_eq!(options.group, Some(String::from("root")));
+        assert_eq!(find_group_by_name("root").expect("Failed to resolve"), 0);
+
+        match find_group_by_name("no-such-group-for-sure") {
+            Err(GroupError::NotFound(ref name)) => assert_eq!(name, "no-such-group-for-sure"),
+            other => panic!("Expected group to not be found, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_skips_zombies_by_default() {
+        let zombie = Process::fake(100, "defunct", "").with_state('Z');
+        let sleeping = Process::fake(101, "sleep", "sleep 10").with_state('S');
+        let running = Process::fake(102, "yes", "yes").with_state('R');
+
+        let defaults = options(&[]);
+        assert!(!has_matching_state(&defaults, &zombie));
+        assert!(has_matching_state(&defaults, &sleeping));
+
+        let only_running = options(&["--state", "R"]);
+        assert!(!has_matching_state(&only_running, &sleeping));
+        assert!(has_matching_state(&only_running, &running));
+
+        let zombies = options(&["--state", "Z"]);
+        assert!(has_matching_state(&zombies, &zombie));
+    }
+
+    #[test]
+    fn it_filters_by_tty() {
+        let on_tty = Process::fake(100, "bash", "bash").with_tty("pts/3");
+        let on_other_tty = Process::fake(101, "bash", "bash").with_tty("pts/4");
+        let daemon = Process::fake(102, "sshd", "sshd");
+
+        let defaults = options(&[]);
+        assert!(has_matching_tty(&defaults, &daemon));
+
+        for tty in &["pts/3", "/dev/pts/3"] {
+            let filtered = options(&["--tty", tty]);
+            assert!(has_matching_tty(&filtered, &on_tty));
+            assert!(!has_matching_tty(&filtered, &on_other_tty));
+            assert!(!has_matching_tty(&filtered, &daemon));
+        }
+    }
+
+    #[test]
+    fn it_filters_by_cgroup() {
+        let in_unit = Process::fake(100, "nginx", "nginx")
+            .with_cgroup("/system.slice/nginx.service")
+            .with_cgroup("/");
+        let elsewhere = Process::fake(101, "nginx", "nginx").with_cgroup("/user.slice");
+        let without = Process::fake(102, "nginx", "nginx");
+
+        let defaults = options(&[]);
+        assert!(has_matching_cgroup(&defaults, &without));
+
+        let filtered = options(&["--cgroup", "system.slice/nginx.service"]);
+        assert!(has_matching_cgroup(&filtered, &in_unit));
+        assert!(!has_matching_cgroup(&filtered, &elsewhere));
+        assert!(!has_matching_cgroup(&filtered, &without));
+    }
+
+    #[test]
+    fn it_removes_duplicate_processes() {
+        let processes = vec![
+            Process::fake(100, "nginx", "nginx"),
+            Process::fake(101, "nginx", "nginx"),
+            Process::fake(100, "nginx", "nginx"),
+        ];
+
+        let pids: Vec<i32> = unique_pids(processes)
+            .iter()
+            .map(|process| process.pid().into())
+            .collect();
+        assert_eq!(pids, vec![100, 101]);
+    }
+
+    #[test]
+    fn it_signals_every_process_group_once() {
+        let processes = vec![
+            Process::fake(100, "make", "make").with_pgid(100),
+            Process::fake(101, "cc", "cc").with_pgid(100),
+            Process::fake(200, "make", "make").with_pgid(200),
+            Process::fake(102, "ld", "ld").with_pgid(100),
+        ];
+
+        let pids: Vec<i32> = one_per_process_group(processes)
+            .iter()
+            .map(|process| process.pid().into())
+            .collect();
+        assert_eq!(pids, vec![100, 200]);
+    }
+
+    #[test]
+    fn it_only_handles_every_process_once() {
+        let pids = |processes: Vec<Process>| -> Vec<i32> {
+            processes
+                .iter()
+                .map(|process| process.pid().into())
+                .collect()
+        };
+        let mut handled = HashSet::new();
+
+        let first = vec![
+            Process::fake(100, "worker", "worker").with_start_time(10),
+            Process::fake(101, "worker", "worker").with_start_time(10),
+        ];
+        assert_eq!(pids(not_handled(first, &mut handled)), vec![100, 101]);
+
+        let second = vec![
+            Process::fake(100, "worker", "worker").with_start_time(10),
+            Process::fake(101, "worker", "worker").with_start_time(10),
+            Process::fake(102, "worker", "worker").with_start_time(20),
+        ];
+        assert_eq!(pids(not_handled(second, &mut handled)), vec![102]);
+
+        // A new process that got the PID of an old one is not the same process.
+        let third = vec![
+            Process::fake(100, "worker", "worker").with_start_time(30),
+            Process::fake(102, "worker", "worker").with_start_time(20),
+        ];
+        assert_eq!(pids(not_handled(third, &mut handled)), vec![100]);
+    }
+
+    #[test]
+    fn it_adds_up_reports() {
+        let description = ProcessDescription::from(&Process::fake(42, "sleep", "sleep 60"));
+        let mut report = RunReport {
+            matched: 2,
+            skipped: 1,
+            terminated: vec![description.clone()],
+            ..RunReport::default()
+        };
+        report.merge(RunReport {
+            matched: 1,
+            killed: vec![description.clone()],
+            ..RunReport::default()
+        });
+
+        assert_eq!(report.matched, 3);
+        assert_eq!(report.skipped, 1);
+        assert_eq!(report.terminated.len(), 1);
+        assert_eq!(report.killed.len(), 1);
+    }
+
+    #[test]
+    fn it_warns_about_patterns_that_matched_nothing() {
+        let matcher = Matcher::new(
+            RegexSet::new(["^nginx$", "^ngnix$", "^redis"]).unwrap(),
+            RegexSet::empty(),
+            MatchMode::Basename,
+        );
+        let processes = [
+            Process::fake(100, "nginx", "nginx"),
+            Process::fake(101, "redis-server", "redis-server"),
+        ];
+
+        assert_eq!(
+            unmatched_warning(&matcher, &processes),
+            Some(String::from(
+                r#"No process matched this pattern: "^ngnix$". Use --no-unmatched-warning to not show this."#
+            ))
+        );
+        assert!(unmatched_warning(&matcher, &processes[..1])
+            .unwrap()
+            .contains(r#"these patterns: "^ngnix$", "^redis""#));
+
+        let matched = Matcher::new(
+            RegexSet::new(["^nginx$"]).unwrap(),
+            RegexSet::empty(),
+            MatchMode::Basename,
+        );
+        assert_eq!(unmatched_warning(&matched, &processes), None);
+    }
+
+    #[test]
+    fn it_signals_only_the_first_match() {
+        let processes = vec![
+            Process::fake(100, "worker", "worker"),
+            Process::fake(101, "worker", "worker"),
+            Process::fake(102, "worker", "worker"),
+        ];
+        let reporter = RecordingReporter::default();
+
+        let pids: Vec<i32> = only_first(processes, &reporter)
+            .iter()
+            .map(|process| process.pid().into())
+            .collect();
+        assert_eq!(pids, vec![100]);
+        assert_eq!(
+            reporter.events(),
+            vec![
+                "skipped 101: only the first match is signaled",
+                "skipped 102: only the first match is signaled",
+            ]
+        );
+        assert!(only_first(Vec::new(), &reporter).is_empty());
+    }
+
+    #[test]
+    fn it_keeps_one_process_per_name_by_age() {
+        let processes = || {
+            vec![
+                Process::fake(100, "worker", "worker").with_start_time(500),
+                Process::fake(101, "worker", "worker").with_start_time(300),
+                Process::fake(102, "nginx", "nginx").with_start_time(100),
+                Process::fake(103, "worker", "worker").with_start_time(900),
+                // Started in the same tick as 101, so the PID decides.
+                Process::fake(104, "worker", "worker").with_start_time(300),
+            ]
+        };
+        let pids = |processes: Vec<Process>| -> Vec<i32> {
+            processes
+                .iter()
+                .map(|process| process.pid().into())
+                .collect()
+        };
+
+        let reporter = RecordingReporter::default();
+        let oldest = one_per_name(processes(), AgeSelection::Oldest, &reporter);
+        assert_eq!(pids(oldest), vec![101, 102]);
+        assert_eq!(
+            reporter.events(),
+            vec![
+                "skipped 100: an older process with the same name is signaled",
+                "skipped 103: an older process with the same name is signaled",
+                "skipped 104: an older process with the same name is signaled",
+            ]
+        );
+
+        let newest = one_per_name(processes(), AgeSelection::Newest, &NullReporter);
+        assert_eq!(pids(newest), vec![102, 103]);
+        assert!(one_per_name(Vec::new(), AgeSelection::Oldest, &NullReporter).is_empty());
+    }
+
+    #[test]
+    fn it_finds_descendants_breadth_first() {
+        let table = vec![
+            Process::fake(10, "supervisor", "supervisor").with_parent(1),
+            Process::fake(20, "worker", "worker").with_parent(10),
+            Process::fake(21, "worker", "worker").with_parent(10),
+            Process::fake(30, "helper", "helper").with_parent(20),
+            Process::fake(40, "unrelated", "unrelated").with_parent(1),
+        ];
+        let children = children_map(&table);
+
+        let pids = |roots: &[i32]| -> Vec<i32> {
+            let roots: Vec<Pid> = roots.iter().cloned().map(Pid::from_raw).collect();
+            descendants(&children, &roots, |_| false)
+                .into_iter()
+                .map(Into::into)
+                .collect()
+        };
+        assert_eq!(pids(&[10]), vec![20, 21, 30]);
+        assert_eq!(pids(&[20]), vec![30]);
+        assert_eq!(pids(&[30]), Vec::<i32>::new());
+        // Descendants that also matched are not repeated.
+        assert_eq!(pids(&[10, 20]), vec![21, 30]);
+    }
+
+    #[test]
+    fn it_spares_excluded_descendants_and_their_children() {
+        let table = vec![
+            Process::fake(10, "nginx", "nginx").with_parent(1),
+            Process::fake(20, "worker", "worker").with_parent(10),
+            Process::fake(21, "worker", "worker").with_parent(10),
+            Process::fake(30, "helper", "helper").with_parent(21),
+        ];
+        let options = options(&["--tree", "--exclude-pid", "21"]);
+        let roots = vec![Process::fake(10, "nginx", "nginx")];
+
+        let processes = add_descendants(roots, table, |process| {
+            options.exclude_pids.contains(&process.pid())
+        });
+        let pids: Vec<i32> = processes.iter().map(|p| p.pid().into()).collect();
+        assert_eq!(pids, vec![10, 20]);
+    }
+
+    #[test]
+    fn it_survives_cycles_in_the_process_tree() {
+        let table = vec![
+            Process::fake(10, "a", "a").with_parent(30),
+            Process::fake(20, "b", "b").with_parent(10),
+            Process::fake(30, "c", "c").with_parent(20),
+        ];
+
+        let found = descendants(&children_map(&table), &[Pid::from_raw(10)], |_| false);
+        assert_eq!(found, vec![Pid::from_raw(20), Pid::from_raw(30)]);
+    }
+
+    #[test]
+    fn it_orders_descendants_before_ancestors() {
+        let table = vec![
+            Process::fake(10, "supervisor", "supervisor").with_parent(1),
+            Process::fake(20, "worker", "worker").with_parent(10),
+            Process::fake(21, "shell", "shell").with_parent(10),
+            Process::fake(30, "helper", "helper").with_parent(21),
+            Process::fake(40, "unrelated", "unrelated").with_parent(1),
+        ];
+        let parents: HashMap<Pid, Pid> = table
+            .iter()
+            .map(|process| (process.pid(), process.parent_pid()))
+            .collect();
+
+        // The shell (21) in between did not match, but the helper still goes before the supervisor.
+        let matched = vec![
+            Process::fake(10, "supervisor", "supervisor").with_parent(1),
+            Process::fake(40, "unrelated", "unrelated").with_parent(1),
+            Process::fake(20, "worker", "worker").with_parent(10),
+            Process::fake(30, "helper", "helper").with_parent(21),
+        ];
+        let pids: Vec<i32> = leaves_first(matched, &parents)
+            .iter()
+            .map(|process| process.pid().into())
+            .collect();
+        assert_eq!(pids, vec![30, 20, 10, 40]);
+    }
+
+    #[test]
+    fn it_orders_cyclic_process_trees_without_looping() {
+        let parents: HashMap<Pid, Pid> = vec![(10, 20), (20, 10)]
+            .into_iter()
+            .map(|(pid, parent)| (Pid::from_raw(pid), Pid::from_raw(parent)))
+            .collect();
+        let matched = vec![Process::fake(10, "a", "a"), Process::fake(20, "b", "b")];
+
+        assert_eq!(leaves_first(matched, &parents).len(), 2);
+    }
+
+    /// Sends by failing with the given errors in order, then succeeding.
+    fn flaky_sender(errors: Vec<KillError>) -> impl Fn(&Process, Signal) -> Result<(), KillError> {
+        let errors = RefCell::new(errors.into_iter());
+        move |_, _| match errors.borrow_mut().next() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    #[test]
+    fn it_retries_unexpected_send_errors() {
+        let process = Process::fake(100, "nginx", "nginx");
+        let signal: Signal = "term".parse().unwrap();
+        let unexpected = || KillError::UnexpectedError(String::from("EAGAIN"));
+
+        let reporter = RecordingReporter::default();
+        let send = flaky_sender(vec![unexpected(), unexpected()]);
+        assert!(send_with_retries(signal, &process, 2, &reporter, send));
+        assert!(reporter.events().is_empty());
+
+        let reporter = RecordingReporter::default();
+        let send = flaky_sender(vec![unexpected(), unexpected()]);
+        assert!(!send_with_retries(signal, &process, 1, &reporter, send));
+        assert_eq!(reporter.events(), vec!["failed 100: TERM"]);
+    }
+
+    #[test]
+    fn it_does_not_retry_terminal_send_errors() {
+        let process = Process::fake(100, "nginx", "nginx");
+        let signal: Signal = "term".parse().unwrap();
+
+        let reporter = RecordingReporter::default();
+        let send = flaky_sender(vec![KillError::NoPermission]);
+        assert!(!send_with_retries(signal, &process, 5, &reporter, send));
+        assert_eq!(reporter.events(), vec!["failed 100: TERM"]);
+
+        let reporter = RecordingReporter::default();
+        let send = flaky_sender(vec![KillError::DoesNotExist, KillError::NoPermission]);
+        assert!(send_with_retries(signal, &process, 5, &reporter, send));
+        assert!(reporter.events().is_empty());
+    }
+
+    fn terminate(args: &[&str], pids: &[i32], sender: &MockSender) -> RunReport {
+        let mut all_args = vec!["--poll-interval", "10"];
+        all_args.extend_from_slice(args);
+        let mut processes: Vec<Process> = pids
+            .iter()
+            .map(|&pid| Process::fake(pid, "nginx", "nginx"))
+            .collect();
+        let mut report = RunReport::default();
+
+        terminate_all(
+            &options(&all_args),
+            &mut processes,
+            0,
+            &mut report,
+            Deadline::after(None),
+            &NullReporter,
+            sender,
+        )
+        .expect("Failed to terminate");
+        report
+    }
+
+    fn report_pids(descriptions: &[ProcessDescription]) -> Vec<i32> {
+        descriptions.iter().map(|d| d.pid.into()).collect()
+    }
+
+    #[test]
+    fn it_kills_processes_that_ignore_the_terminate_signal() {
+        let sender = MockSender::new()
+            .exits_on(100, "term")
+            .exits_on(101, "kill");
+
+        let report = terminate(&["--wait-time", "0.05"], &[100, 101], &sender);
+        assert_eq!(sender.sent(), vec!["100: TERM", "101: TERM", "101: KILL"]);
+        assert_eq!(report_pids(&report.terminated), vec![100]);
+        assert_eq!(report_pids(&report.killed), vec![101]);
+        assert!(report.success());
+    }
+
+    #[test]
+    fn it_signals_every_thread_when_asked_to() {
+        let sender = MockSender::new()
+            .with_threads(100, &[101, 102])
+            .exits_on(200, "term");
+
+        let report = terminate(&["--threads", "--wait-time", "0.05"], &[100, 200], &sender);
+        assert_eq!(
+            sender.sent(),
+            vec![
+                "100/100: TERM",
+                "100/101: TERM",
+                "100/102: TERM",
+                "200/200: TERM",
+                "100/100: KILL",
+                "100/101: KILL",
+                "100/102: KILL",
+            ]
+        );
+        assert_eq!(report_pids(&report.terminated), vec![200]);
+    }
+
+    #[test]
+    fn it_follows_the_escalation_steps() {
+        let sender = MockSender::new().exits_on(100, "int");
+
+        let report = terminate(&["--escalate", "hup:0.05,int:0.05,kill:0"], &[100], &sender);
+        assert_eq!(sender.sent(), vec!["100: HUP", "100: INT"]);
+        assert_eq!(report_pids(&report.terminated), vec![100]);
+        assert!(report.killed.is_empty());
+    }
+
+    #[test]
+    fn it_only_counts_the_final_step_as_killing_for_sigkill() {
+        let sender = MockSender::new().exits_on(100, "int");
+
+        let report = terminate(&["--escalate", "term:0.05,int"], &[100, 101], &sender);
+        assert_eq!(
+            sender.sent(),
+            vec!["100: TERM", "101: TERM", "100: INT", "101: INT"]
+        );
+        assert_eq!(report_pids(&report.terminated), vec![100]);
+        assert_eq!(report_pids(&report.alive), vec![101]);
+        assert!(report.killed.is_empty());
+        assert!(!report.success());
+    }
+
+    #[test]
+    fn it_resends_the_terminate_signal_once() {
+        let sender = MockSender::new().exits_on(100, "kill");
+
+        let report = terminate(
+            &["--wait-time", "0.1", "--resend-after", "0.03"],
+            &[100],
+            &sender,
+        );
+        assert_eq!(sender.sent(), vec!["100: TERM", "100: TERM", "100: KILL"]);
+        assert_eq!(report_pids(&report.killed), vec![100]);
+    }
+
+    #[test]
+    fn it_does_not_resend_to_processes_that_exited() {
+        let sender = MockSender::new().exits_on(100, "term");
+
+        terminate(
+            &["--wait-time", "0.1", "--resend-after", "0.03"],
+            &[100],
+            &sender,
+        );
+        assert_eq!(sender.sent(), vec!["100: TERM"]);
+    }
+
+    #[test]
+    fn it_records_failed_resends() {
+        let sender = MockSender::new()
+            .fails_after(100, 1, KillError::NoPermission)
+            .exits_on(101, "kill");
+
+        let report = terminate(
+            &["--wait-time", "0.1", "--resend-after", "0.03"],
+            &[100, 101],
+            &sender,
+        );
+        assert_eq!(
+            sender.sent(),
+            vec!["100: TERM", "101: TERM", "101: TERM", "101: KILL"]
+        );
+        assert_eq!(report_pids(&report.failed), vec![100]);
+        assert_eq!(report_pids(&report.killed), vec![101]);
+        assert!(report.alive.is_empty());
+    }
+
+    #[test]
+    fn it_stops_signaling_processes_that_failed() {
+        let sender = MockSender::new()
+            .fails_with(100, KillError::NoPermission)
+            .exits_on(101, "kill");
+
+        let report = terminate(&["--wait-time", "0.05"], &[100, 101], &sender);
+        assert_eq!(sender.sent(), vec!["101: TERM", "101: KILL"]);
+        assert_eq!(report_pids(&report.failed), vec![100]);
+        assert_eq!(report_pids(&report.killed), vec![101]);
+        assert!(!report.success());
+    }
+
+    #[test]
+    fn it_reports_survivors_when_not_killing() {
+        let sender = MockSender::new().exits_on(100, "term");
+
+        let report = terminate(&["--wait-time", "0.05", "--no-kill"], &[100, 101], &sender);
+        assert_eq!(sender.sent(), vec!["100: TERM", "101: TERM"]);
+        assert_eq!(report_pids(&report.terminated), vec![100]);
+        assert_eq!(report_pids(&report.alive), vec![101]);
+    }
+
+    #[test]
+    fn it_does_not_wait_without_a_waiting_time() {
+        let sender = MockSender::new();
+
+        let report = terminate(&["--wait-time", "0"], &[100], &sender);
+        assert_eq!(sender.sent(), vec!["100: TERM"]);
+        assert_eq!(report_pids(&report.terminated), vec![100]);
+    }
+
+    #[test]
+    fn it_kills_processes_when_their_own_grace_period_is_over() {
+        let sender = MockSender::new()
+            .exits_on(100, "kill")
+            .exits_on(101, "kill")
+            .exits_on(102, "term");
+        let grace_map = GraceMap::parse("^fast$ 0.05\n^slow$ 0.3\n").unwrap();
+        let mut processes = vec![
+            Process::fake(100, "slow", "slow"),
+            Process::fake(101, "fast", "fast"),
+            Process::fake(102, "nginx", "nginx"),
+        ];
+        use_grace_periods(&grace_map, &mut processes);
+
+        let start = Instant::now();
+        let reporter = RecordingReporter::default();
+
+        let mut report = RunReport::default();
+        terminate_all(
+            &options(&["--poll-interval", "10", "--wait-time", "10"]),
+            &mut processes,
+            0,
+            &mut report,
+            Deadline::after(None),
+            &reporter,
+            &sender,
+        )
+        .expect("Failed to terminate");
+
+        assert_eq!(
+            sender.sent(),
+            vec![
+                "100: TERM",
+                "101: TERM",
+                "102: TERM",
+                "101: KILL",
+                "100: KILL"
+            ]
+        );
+        assert_eq!(report_pids(&report.terminated), vec![102]);
+        assert_eq!(report_pids(&report.killed), vec![101, 100]);
+
+        // Each one was killed once its own grace period was over.
+        let kills = reporter.times_of("killed", start);
+        assert_eq!(kills[0].0, "killed 101: KILL");
+        assert!(kills[0].1 >= Duration::from_millis(50), "{:?}", kills);
+        assert_eq!(kills[1].0, "killed 100: KILL");
+        assert!(kills[1].1 >= Duration::from_millis(300), "{:?}", kills);
+    }
+
+    #[test]
+    fn it_only_delays_signals_that_can_be_handled() {
+        let sender = MockSender::new()
+            .exits_on(100, "kill")
+            .exits_on(101, "kill");
+        let mut processes = vec![
+            Process::fake(100, "nginx", "nginx"),
+            Process::fake(101, "nginx", "nginx"),
+        ];
+        let start = Instant::now();
+        let reporter = RecordingReporter::default();
+
+        let mut report = RunReport::default();
+        terminate_all(
+            &options(&[
+                "--poll-interval",
+                "10",
+                "--wait-time",
+                "0.05",
+                "--signal-delay",
+                "300",
+            ]),
+            &mut processes,
+            0,
+            &mut report,
+            Deadline::after(None),
+            &reporter,
+            &sender,
+        )
+        .expect("Failed to terminate");
+
+        assert_eq!(report_pids(&report.killed), vec![100, 101]);
+        let terms = reporter.times_of("signaled", start);
+        assert!(
+            terms[1].1 - terms[0].1 >= Duration::from_millis(300),
+            "{:?}",
+            terms
+        );
+        let kills = reporter.times_of("killed", start);
+        assert!(
+            kills[1].1 - kills[0].1 < Duration::from_millis(300),
+            "{:?}",
+            kills
+        );
+    }
+
+    #[test]
+    fn it_does_not_count_a_weak_kill_signal_after_a_grace_period_as_killing() {
+        let sender = MockSender::new().exits_on(100, "int");
+        let grace_map = GraceMap::parse("^fast$ 0.05\n").unwrap();
+        let mut processes = vec![
+            Process::fake(100, "fast", "fast"),
+            Process::fake(101, "fast", "fast"),
+        ];
+        use_grace_periods(&grace_map, &mut processes);
+
+        let mut report = RunReport::default();
+        terminate_all(
+            &options(&[
+                "--poll-interval",
+                "10",
+                "--wait-time",
+                "10",
+                "--kill-signal",
+                "int",
+            ]),
+            &mut processes,
+            0,
+            &mut report,
+            Deadline::after(None),
+            &NullReporter,
+            &sender,
+        )
+        .expect("Failed to terminate");
+
+        assert_eq!(
+            sender.sent(),
+            vec!["100: TERM", "101: TERM", "100: INT", "101: INT"]
+        );
+        assert_eq!(report_pids(&report.terminated), vec![100]);
+        assert_eq!(report_pids(&report.alive), vec![101]);
+        assert!(report.killed.is_empty());
+    }
+
+    #[test]
+    fn it_does_not_sleep_when_every_process_already_exited() {
+        let sender = MockSender::new()
+            .exits_on(100, "term")
+            .exits_on(101, "term");
+        let mut processes = vec![
+            Process::fake(100, "nginx", "nginx"),
+            Process::fake(101, "nginx", "nginx"),
+        ];
+        for process in &processes {
+            sender.send(process, "term".parse().unwrap()).unwrap();
+        }
+
+        let died = wait_for_exit(
+            &options(&["--poll-interval", "1000"]),
+            &mut processes,
+            // Leaves no time to sleep and check again, so only checking first finds them.
+            Duration::from_secs(0),
+            None,
+            &mut RunReport::default(),
+            &NullReporter,
+            &sender,
+        );
+
+        assert_eq!(sender.checks(), 2);
+        assert_eq!(report_pids(&died), vec![100, 101]);
+        assert!(processes.is_empty());
+    }
+
+    #[test]
+    fn it_strips_comments() {
+        assert_eq!(
+            strip_comment(String::from("Foobar")),
+            String::from("Foobar"),
+        );
+
+        assert_eq!(strip_comment(String::from("Foo#bar")), String::from("Foo"),);
+
+        assert_eq!(
+            strip_comment(String::from(" Complicated # oh yes!! # another one")),
+            String::from("Complicated"),
+        );
+
+        assert_eq!(
+            strip_comment(String::from("# Just a comment")),
+            String::from(""),
+        );
+
+        assert_eq!(
+            strip_comment(String::from("  \t# Just a comment")),
+            String::from(""),
+        );
+    }
+
+    fn options(args: &[&str]) -> Options {
+        let mut all_args = vec!["graceful-shutdown"];
+        all_args.extend_from_slice(args);
+        Options::from(CliOptions::from_iter(all_args))
+    }
+
+    fn patterns(input: &'static str, options: &Options) -> RegexSet {
+        load_patterns(Box::new(io::Cursor::new(input)), options)
+            .expect("Failed to load")
+            .1
+    }
+
+    #[test]
+    fn it_loads_patterns_from_any_reader() {
+        let input = "# Browsers\nfirefox # Vanilla\n\nchrom(e|ium)\n";
+        let set = patterns(input, &options(&["--regex"]));
+
+        assert_eq!(set.patterns(), &["firefox", "chrom(e|ium)"]);
+    }
+
+    #[test]
+    fn it_matches_everything_on_the_port_without_patterns() {
+        let port = options(&["--port", "8080"]);
+        assert!(or_match_all(&port, patterns("", &port)).is_match("nginx"));
+        assert!(or_match_all(&port, patterns("# None yet\n", &port)).is_match("nginx"));
+        assert!(!or_match_all(&port, patterns("redis\n", &port)).is_match("nginx"));
+
+        let pattern_file = options(&["--port", "8080", "--pattern-file", "/dev/null"]);
+        assert!(!or_match_all(&pattern_file, patterns("", &pattern_file)).is_match("nginx"));
+
+        let no_port = options(&[]);
+        assert!(!or_match_all(&no_port, patterns("", &no_port)).is_match("nginx"));
+    }
+
+    #[test]
+    fn it_notices_patterns_piped_next_to_a_pattern_file() {
+        use nix::unistd::{close, pipe, write};
+        use std::os::unix::io::FromRawFd;
+
+        let (read_end, write_end) = pipe().unwrap();
+        let input = unsafe { File::from_raw_fd(read_end) };
+        // Nothing written yet, which must not block.
+        assert!(!has_piped_input(&input));
+        write(write_end, b"nginx\n").unwrap();
+        assert!(has_piped_input(&input));
+        // Still there after the writer is gone, like after "echo nginx |".
+        close(write_end).unwrap();
+        assert!(has_piped_input(&input));
+
+        let (read_end, write_end) = pipe().unwrap();
+        let closed = unsafe { File::from_raw_fd(read_end) };
+        close(write_end).unwrap();
+        assert!(!has_piped_input(&closed));
+
+        assert!(!has_piped_input(&File::open("/dev/null").unwrap()));
+    }
+
+    #[test]
+    fn it_matches_plain_names_exactly() {
+        let set = patterns("nginx\nphp-fpm\nredis.*\n", &options(&[]));
+        assert!(set.is_match("nginx"));
+        assert!(set.is_match("NGINX"));
+        assert!(!set.is_match("nginx-helper"));
+        assert!(set.is_match("php-fpm"));
+        assert!(!set.is_match("php-fpm7.4"));
+        // Regular expressions still match any part of the name.
+        assert!(set.is_match("my-redis-server"));
+
+        let regex = patterns("nginx\n", &options(&["--regex"]));
+        assert!(regex.is_match("nginx-helper"));
+
+        // Other match modes look for the name anywhere in the command line or arguments.
+        let commandline = patterns("nginx\n", &options(&["--whole-command"]));
+        assert!(commandline.is_match("/usr/sbin/nginx -g daemon off;"));
+    }
+
+    #[test]
+    fn it_shows_patterns_as_written() {
+        let options = options(&["--stats"]);
+        let (texts, set) = load_patterns(
+            Box::new(io::Cursor::new("nginx\nngnix\nredis.*\n")),
+            &options,
+        )
+        .unwrap();
+        let matcher =
+            Matcher::new(set, RegexSet::empty(), options.match_mode).with_pattern_texts(texts);
+        let processes = [Process::fake(100, "nginx", "nginx")];
+
+        assert_eq!(matcher.patterns(), &["nginx", "ngnix", "redis.*"]);
+        assert!(unmatched_warning(&matcher, &processes)
+            .unwrap()
+            .contains(r#"these patterns: "ngnix", "redis.*""#));
+    }
+
+    #[test]
+    fn it_excludes_plain_names_exactly() {
+        let exclude = |args: &[&str]| {
+            let options = options(args);
+            let exclude_set = build_regex_set(&options.exclude_patterns, &options).unwrap();
+            Matcher::new(
+                RegexSet::new(["nginx"]).unwrap(),
+                exclude_set,
+                options.match_mode,
+            )
+        };
+        let nginx = Process::fake(100, "nginx", "nginx");
+        let helper = Process::fake(101, "nginx-helper", "nginx-helper");
+
+        let plain = exclude(&["--exclude", "nginx"]);
+        assert!(!plain.is_match(&nginx));
+        assert!(plain.is_match(&helper));
+
+        let regex = exclude(&["--regex", "--exclude", "nginx"]);
+        assert!(!regex.is_match(&helper));
+    }
+
+    #[test]
+    fn it_reports_all_invalid_patterns_with_line_numbers() {
+        let input = "# Services\nnginx\nfoo(\n\nbar[ # Typo\nredis\n*sh\n";
+        let error = load_patterns(Box::new(io::Cursor::new(input)), &options(&[]))
+            .expect_err("Patterns should be invalid");
+
+        match error.downcast::<PatternError>() {
+            Ok(PatternError::Invalid(invalid)) => {
+                let lines: Vec<Option<usize>> = invalid.iter().map(|p| p.line).collect();
+                assert_eq!(lines, vec![Some(3), Some(5), Some(7)]);
+            }
+            other => panic!("Expected invalid patterns, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_reports_unreadable_pattern_lines() {
+        let input: &[u8] = b"nginx\n\xff\xfe\nredis\n";
+        let error = load_patterns(Box::new(io::Cursor::new(input)), &options(&[]))
+            .expect_err("Patterns should be unreadable");
+
+        assert_eq!(error.to_string(), "Could not read line 2");
+    }
+
+    #[test]
+    fn it_reports_invalid_patterns_without_lines() {
+        let patterns = vec![String::from("ok"), String::from("(")];
+
+        match build_regex_set(&patterns, &options(&[])) {
+            Err(PatternError::Invalid(invalid)) => {
+                assert_eq!(invalid.len(), 1);
+                assert_eq!(invalid[0].index, 1);
+                assert_eq!(invalid[0].line, None);
+            }
+            other => panic!("Expected invalid patterns, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_matches_case_insensitively_by_default() {
+        let set = patterns("redis", &options(&[]));
+
+        assert!(set.is_match("redis"));
+        assert!(set.is_match("Redis"));
+    }
+
+    #[test]
+    fn it_matches_case_sensitively_when_asked_to() {
+        let set = patterns("redis", &options(&["--case-sensitive"]));
+
+        assert!(set.is_match("redis"));
+        assert!(!set.is_match("Redis"));
+    }
+
+    #[test]
+    fn it_matches_case_insensitively_when_explicitly_asked_to() {
+        let set = patterns("redis", &options(&["--ignore-case"]));
+
+        assert!(set.is_match("redis"));
+        assert!(set.is_match("Redis"));
+    }
+
+    #[test]
+    fn it_matches_case_sensitively_when_both_case_flags_are_given() {
+        let set = patterns("redis", &options(&["-I", "--case-sensitive"]));
+
+        assert!(set.is_match("redis"));
+        assert!(!set.is_match("Redis"));
+    }
+
+    #[test]
+    fn it_respects_inline_case_flags_per_pattern() {
+        let set = patterns("(?-i)Xorg\nredis", &options(&[]));
+        assert!(set.is_match("Xorg"));
+        assert!(!set.is_match("xorg"));
+        assert!(set.is_match("REDIS"));
+
+        let set = patterns("(?i)redis\nXorg", &options(&["--case-sensitive"]));
+        assert!(set.is_match("REDIS"));
+        assert!(set.is_match("Xorg"));
+        assert!(!set.is_match("xorg"));
+    }
+
+    #[test]
+    fn it_keeps_inline_flags_when_matching_exactly() {
+        let set = patterns("(?-i)Xorg", &options(&["--exact"]));
+        assert!(set.is_match("Xorg"));
+        assert!(!set.is_match("xorg"));
+        assert!(!set.is_match("Xorg-server"));
+    }
+
+    #[test]
+    fn it_matches_fixed_strings_literally() {
+        let set = patterns("a.b\nmy-app+worker", &options(&["--fixed-strings"]));
+
+        assert!(set.is_match("a.b"));
+        assert!(!set.is_match("axb"));
+        assert!(set.is_match("my-app+worker"));
+        assert!(!set.is_match("my-appworker"));
+    }
+
+    #[test]
+    fn it_combines_fixed_strings_with_case_sensitivity() {
+        let insensitive = patterns("a.b", &options(&["-F"]));
+        assert!(insensitive.is_match("A.B"));
+
+        let sensitive = patterns("a.b", &options(&["-F", "--case-sensitive"]));
+        assert!(!sensitive.is_match("A.B"));
+        assert!(!sensitive.is_match("axb"));
+    }
+
+    #[test]
+    fn it_combines_exact_matching_with_other_modes() {
+        let set = patterns("a.b", &options(&["--exact", "-F"]));
+        assert!(set.is_match("A.B"));
+        assert!(!set.is_match("a.bc"));
+        assert!(!set.is_match("axb"));
+
+        let set = patterns("sh", &options(&["--exact", "--case-sensitive"]));
+        assert!(set.is_match("sh"));
+        assert!(!set.is_match("SH"));
+        assert!(!set.is_match("bash"));
+    }
+
+    #[test]
+    fn it_refuses_denied_processes_given_by_pid_or_found_as_descendants() {
+        use std::process::Command;
+
+        let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+        let own_pid = Pid::this().to_string();
+        let options = options(&["--pid", &own_pid]);
+        let own_name = Process::from_pid(&options.proc_root, Pid::this())
+            .unwrap()
+            .name()
+            .to_string();
+
+        let reporter = RecordingReporter::default();
+        let deny_self = RegexSet::new([format!("^{}$", regex::escape(&own_name))]).unwrap();
+        let mut handled = HashSet::new();
+        let processes = explicit_processes(&options, &deny_self, &mut handled, &reporter).unwrap();
+        assert!(processes.is_empty());
+        // Like on the next poll of --watch, it is only refused once.
+        explicit_processes(&options, &deny_self, &mut handled, &reporter).unwrap();
+        assert_eq!(
+            reporter.events(),
+            vec![format!("refused {}: {}", own_pid, DENIED)]
+        );
+
+        let reporter = RecordingReporter::default();
+        let deny_sleep = RegexSet::new(["^sleep$"]).unwrap();
+        let mut handled = HashSet::new();
+        let processes = explicit_processes(&options, &deny_sleep, &mut handled, &reporter).unwrap();
+        let targets = Targets {
+            matcher: None,
+            deny_set: deny_sleep,
+            exclusions: Matcher::new(RegexSet::empty(), RegexSet::empty(), options.match_mode),
+            grace_map: GraceMap::default(),
+        };
+        let processes =
+            with_descendants(&options, processes, &targets, &mut handled, &reporter).unwrap();
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        assert!(processes.iter().all(|process| process.name() != "sleep"));
+        assert_eq!(
+            reporter.events(),
+            vec![format!("refused {}: {}", child.id(), DENIED)]
+        );
+    }
+
+    #[test]
+    fn it_spares_excluded_descendants() {
+        use std::process::Command;
+
+        let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+        let own_pid = Pid::this().to_string();
+        let child_pid = child.id().to_string();
+        let descendants_with = |args: &[&str]| -> Vec<Process> {
+            let mut all_args = vec!["--pid", &own_pid, "--tree"];
+            all_args.extend_from_slice(args);
+            let options = options(&all_args);
+            let exclude_set = build_regex_set(&options.exclude_patterns, &options).unwrap();
+            let targets = Targets {
+                matcher: None,
+                deny_set: RegexSet::empty(),
+                exclusions: Matcher::new(RegexSet::empty(), exclude_set, options.match_mode),
+                grace_map: GraceMap::default(),
+            };
+            let mut handled = HashSet::new();
+            let processes =
+                explicit_processes(&options, &targets.deny_set, &mut handled, &NullReporter)
+                    .unwrap();
+            with_descendants(&options, processes, &targets, &mut handled, &NullReporter).unwrap()
+        };
+        let has_child = |processes: &[Process]| {
+            processes
+                .iter()
+                .any(|process| process.pid().to_string() == child_pid)
+        };
+
+        let everything = descendants_with(&[]);
+        let without_pid = descendants_with(&["--exclude-pid", &child_pid]);
+        let without_name = descendants_with(&["--exclude", "sleep"]);
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        assert!(has_child(&everything));
+        assert!(!has_child(&without_pid));
+        assert!(!has_child(&without_name));
+    }
+
+    #[test]
+    fn it_removes_excluded_pids() {
+        let processes = vec![
+            Process::fake(10, "java", "java -jar a.jar"),
+            Process::fake(4242, "java", "java -jar b.jar"),
+            Process::fake(12, "java", "java -jar c.jar"),
+        ];
+
+        let remaining = without_pids(processes, &[Pid::from_raw(4242), Pid::from_raw(1)]);
+        let pids: Vec<i32> = remaining.iter().map(|p| p.pid().into()).collect();
+
+        assert_eq!(pids, vec![10, 12]);
+    }
+
+    #[test]
+    fn it_never_signals_itself() {
+        let own_pid: i32 = Pid::this().into();
+        let processes = vec![
+            Process::fake(own_pid, "graceful-shutdo", "graceful-shutdown"),
+            Process::fake(own_pid + 1, "graceful-shutdo", "graceful-shutdown"),
+        ];
+
+        let remaining = skip_protected(&options(&[]), processes, &NullReporter);
+        let pids: Vec<i32> = remaining.iter().map(|p| p.pid().into()).collect();
+
+        assert_eq!(pids, vec![own_pid + 1]);
+    }
+
+    #[test]
+    fn it_only_includes_itself_when_asked_to() {
+        let own_pid: i32 = Pid::this().into();
+        let processes = || {
+            vec![Process::fake(
+                own_pid,
+                "graceful-shutdo",
+                "graceful-shutdown",
+            )]
+        };
+
+        let reporter = RecordingReporter::default();
+        assert!(skip_protected(&options(&[]), processes(), &reporter).is_empty());
+        assert_eq!(
+            reporter.events(),
+            vec![format!(
+                "skipped {}: this is graceful-shutdown itself",
+                own_pid
+            )]
+        );
+
+        let reporter = RecordingReporter::default();
+        let remaining = skip_protected(&options(&["--include-self"]), processes(), &reporter);
+        assert_eq!(remaining.len(), 1);
+        assert!(reporter.events().is_empty());
+    }
+
+    #[test]
+    fn it_skips_pid1_unless_allowed() {
+        let processes = || vec![Process::fake(1, "systemd", "/sbin/init")];
+
+        let reporter = RecordingReporter::default();
+        assert!(skip_protected(&options(&[]), processes(), &reporter).is_empty());
+        assert_eq!(
+            reporter.events(),
+            vec!["refused 1: use --allow-pid1 if you really want to."]
+        );
+
+        let reporter = RecordingReporter::default();
+        assert_eq!(
+            skip_protected(&options(&["--allow-pid1"]), processes(), &reporter).len(),
+            1
+        );
+        assert!(reporter.events().is_empty());
+    }
+
+    #[test]
+    fn it_skips_protected_pids() {
+        let processes = vec![
+            Process::fake(100, "sshd", "sshd"),
+            Process::fake(101, "sshd", "sshd"),
+        ];
+
+        let reporter = RecordingReporter::default();
+        let remaining = skip_protected(&options(&["--protect", "100"]), processes, &reporter);
+        let pids: Vec<i32> = remaining.iter().map(|p| p.pid().into()).collect();
+
+        assert_eq!(pids, vec![101]);
+        assert_eq!(reporter.events(), vec!["skipped 100: it is protected"]);
+    }
+
+    #[test]
+    fn it_reports_what_a_dry_run_would_do() {
+        let processes = vec![
+            Process::fake(100, "sshd", "sshd"),
+            Process::fake(101, "sshd", "sshd"),
+        ];
+        let reporter = RecordingReporter::default();
+
+        dry_run(&options(&["-s", "hup"]), &[], &processes, &reporter);
+        assert_eq!(
+            reporter.events(),
+            vec!["will_signal 100: HUP", "will_signal 101: HUP"]
+        );
+    }
+
+    #[test]
+    fn it_only_matches_processes_started_before_the_marker_file() {
+        use std::time::UNIX_EPOCH;
+
+        // Booted at 1000s, with 100 ticks per second.
+        let clock = Clock::fake(1_000, 100);
+        let marker = UNIX_EPOCH + Duration::from_secs(1_500);
+        let options = options(&["--older-than-file", "/deploy-marker"]);
+        let started_after_boot =
+            |seconds: u64| Process::fake(42, "server", "server").with_start_time(seconds * 100);
+
+        assert!(has_matching_age(
+            &options,
+            &started_after_boot(100),
+            &clock,
+            Some(marker)
+        ));
+        assert!(has_matching_age(
+            &options,
+            &started_after_boot(499),
+            &clock,
+            Some(marker)
+        ));
+        assert!(!has_matching_age(
+            &options,
+            &started_after_boot(500),
+            &clock,
+            Some(marker)
+        ));
+        assert!(!has_matching_age(
+            &options,
+            &started_after_boot(900),
+            &clock,
+            Some(marker)
+        ));
+        assert!(has_matching_age(
+            &options,
+            &started_after_boot(900),
+            &clock,
+            None
+        ));
+    }
+
+    #[test]
+    fn it_separates_stuck_processes_by_age() {
+        let processes = vec![
+            Process::fake(100, "worker", "worker").with_start_time(30),
+            Process::fake(101, "worker", "worker").with_start_time(7200),
+            Process::fake(102, "worker", "worker").with_start_time(3600),
+        ];
+        let age = |process: &Process| Duration::from_secs(process.start_time());
+
+        let (stuck, young) = partition_by_age(processes, Duration::from_secs(3600), age);
+        let pids = |processes: &[Process]| -> Vec<i32> {
+            processes.iter().map(|p| p.pid().into()).collect()
+        };
+        assert_eq!(pids(&stuck), vec![101, 102]);
+        assert_eq!(pids(&young), vec![100]);
+    }
+
+    #[test]
+    fn it_kills_stuck_processes_right_away() {
+        let sender = MockSender::new()
+            .exits_on(100, "kill")
+            .exits_on(101, "term");
+        let options = options(&["--poll-interval", "10", "--wait-time", "0.05"]);
+        let stuck = vec![Process::fake(100, "worker", "worker")];
+        let young = vec![Process::fake(101, "worker", "worker")];
+        let mut report = RunReport::default();
+
+        real_run(
+            &options,
+            stuck,
+            young,
+            &mut report,
+            Deadline::after(None),
+            &NullReporter,
+            &sender,
+        )
+        .expect("Failed to terminate");
+        assert_eq!(sender.sent(), vec!["100: KILL", "101: TERM"]);
+        assert_eq!(report_pids(&report.killed), vec![100]);
+        assert_eq!(report_pids(&report.terminated), vec![101]);
+    }
+
+    #[test]
+    fn it_freezes_every_process_before_signaling_any() {
+        let sender = MockSender::new()
+            .exits_on(100, "kill")
+            .exits_on(101, "kill")
+            .exits_on(102, "term");
+        let options = options(&[
+            "--poll-interval",
+            "10",
+            "--wait-time",
+            "0.05",
+            "--freeze-first",
+        ]);
+        let stuck = vec![Process::fake(100, "worker", "worker")];
+        let young = vec![
+            Process::fake(101, "worker", "worker"),
+            Process::fake(102, "worker", "worker"),
+        ];
+        let mut report = RunReport::default();
+
+        real_run(
+            &options,
+            stuck,
+            young,
+            &mut report,
+            Deadline::after(None),
+            &NullReporter,
+            &sender,
+        )
+        .expect("Failed to terminate");
+        // Killing works on stopped processes, but the terminate signal is only handled once the
+        // process is continued. Processes that are gone already are not continued.
+        assert_eq!(
+            sender.sent(),
+            vec![
+                "100: STOP",
+                "101: STOP",
+                "102: STOP",
+                "100: KILL",
+                "101: TERM",
+                "101: CONT",
+                "102: TERM",
+                "101: KILL",
+            ]
+        );
+        assert_eq!(report_pids(&report.killed), vec![100, 101]);
+        assert_eq!(report_pids(&report.terminated), vec![102]);
+    }
+
+    #[test]
+    fn it_only_continues_frozen_processes() {
+        let sender = MockSender::new();
+        let options = options(&[]);
+        let mut process = Process::fake(100, "worker", "worker");
+
+        resume(&options, &mut process, &NullReporter, &sender);
+        assert!(sender.sent().is_empty());
+
+        freeze_all(
+            &options,
+            ::std::slice::from_mut(&mut process),
+            &NullReporter,
+            &sender,
+        );
+        resume(&options, &mut process, &NullReporter, &sender);
+        resume(&options, &mut process, &NullReporter, &sender);
+        assert_eq!(sender.sent(), vec!["100: STOP", "100: CONT"]);
+        assert!(!process.is_frozen());
+    }
+
+    #[test]
+    fn it_shows_the_kill_signal_for_stuck_processes_in_dry_runs() {
+        let stuck = vec![Process::fake(100, "worker", "worker")];
+        let young = vec![Process::fake(101, "worker", "worker")];
+        let reporter = RecordingReporter::default();
+
+        dry_run(&options(&[]), &stuck, &young, &reporter);
+        assert_eq!(
+            reporter.events(),
+            vec!["will_signal 100: KILL", "will_signal 101: TERM"]
+        );
+    }
+
+    #[test]
+    fn it_measures_how_long_processes_took_to_exit() {
+        let signaled_at = Instant::now();
+        let mut processes = vec![
+            Process::fake(100, "nginx", "nginx"),
+            Process::fake(101, "nginx", "nginx"),
+            Process::fake(102, "nginx", "nginx"),
+        ];
+        processes[0].mark_signaled(signaled_at);
+        processes[1].mark_signaled(signaled_at);
+        // Escalating must not restart the clock.
+        processes[1].mark_signaled(signaled_at + Duration::from_secs(2));
+
+        let reporter = RecordingReporter::default();
+        let still_running = |process: &Process| process.pid() == Pid::from_raw(101);
+        let died = remove_dead(
+            &mut processes,
+            signaled_at + Duration::from_millis(4200),
+            still_running,
+            &reporter,
+        );
+        assert_eq!(died.len(), 2);
+        assert_eq!(reporter.events(), vec!["died 100: 4200ms", "died 102: "]);
+
+        let reporter = RecordingReporter::default();
+        remove_dead(
+            &mut processes,
+            signaled_at + Duration::from_millis(5000),
+            |_| false,
+            &reporter,
+        );
+        assert!(processes.is_empty());
+        assert_eq!(reporter.events(), vec!["died 101: 5000ms"]);
+    }
+
+    fn sorted_pids(order: SortOrder) -> Vec<i32> {
+        let processes = vec![
+            Process::fake(300, "nginx", "nginx").with_start_time(10),
+            Process::fake(100, "postgres", "postgres").with_start_time(30),
+            Process::fake(200, "nginx", "nginx").with_start_time(20),
+            Process::fake(50, "redis", "redis").with_start_time(20),
+        ];
+        sorted(processes, order)
+            .iter()
+            .map(|process| process.pid().into())
+            .collect()
+    }
+
+    #[test]
+    fn it_sorts_by_pid() {
+        assert_eq!(sorted_pids(SortOrder::Pid), vec![50, 100, 200, 300]);
+    }
+
+    #[test]
+    fn it_sorts_by_name() {
+        assert_eq!(sorted_pids(SortOrder::Name), vec![200, 300, 100, 50]);
+    }
+
+    #[test]
+    fn it_sorts_by_age() {
+        assert_eq!(sorted_pids(SortOrder::Age), vec![100, 50, 200, 300]);
+    }
+
+    #[test]
+    fn it_sorts_by_startup() {
+        assert_eq!(sorted_pids(SortOrder::Startup), vec![300, 50, 200, 100]);
+    }
+
+    #[test]
+    fn it_refuses_to_continue_when_over_the_limit() {
+        let processes = vec![
+            Process::fake(100, "python", "python"),
+            Process::fake(101, "python", "python"),
+            Process::fake(102, "python", "python"),
+        ];
+
+        assert!(enforce_limit(&options(&[]), &processes, &NullReporter).is_ok());
+        assert!(enforce_limit(&options(&["--limit", "3"]), &processes, &NullReporter).is_ok());
+
+        match enforce_limit(&options(&["--limit", "2"]), &processes, &NullReporter) {
+            Err(LimitError::Exceeded(3, 2)) => {}
+            other => panic!("Expected limit to be exceeded, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_signals_nothing_when_over_the_limit() {
+        use std::process::Command;
+
+        let mut children = vec![
+            Command::new("sleep").arg("10").spawn().unwrap(),
+            Command::new("sleep").arg("10").spawn().unwrap(),
+        ];
+        let first = children[0].id().to_string();
+        let second = children[1].id().to_string();
+        let options = options(&["--pid", &first, "--pid", &second, "--limit", "1"]);
+        let sender = MockSender::new();
+
+        let result = run_with_sender(&options, &NullReporter, &sender);
+        for child in &mut children {
+            child.kill().unwrap();
+            child.wait().unwrap();
+        }
+
+        match result.map_err(|error| error.downcast::<LimitError>()) {
+            Err(Ok(LimitError::Exceeded(2, 1))) => {}
+            other => panic!("Expected limit to be exceeded, got {:?}", other),
+        }
+        assert!(sender.sent().is_empty());
+    }
+
+    #[test]
+    fn it_matches_globs() {
+        let set = patterns("*.py\nfire?ox", &options(&["--glob"]));
+
+        assert!(set.is_match("script.py"));
+        assert!(!set.is_match("script.pyc"));
+        assert!(set.is_match("Firefox"));
+        assert!(!set.is_match("firefox-developer-edition"));
+    }
+
+    #[test]
+    fn it_reports_when_nothing_matched() {
+        let options = options(&["--pattern-file", "/dev/null"]);
+        let reporter = RecordingReporter::default();
+        let sender = MockSender::new();
+
+        let report = run_with_sender(&options, &reporter, &sender).expect("Failed to run");
+        assert_eq!(report.matched, 0);
+        assert_eq!(reporter.events(), vec!["nothing_matched"]);
+        assert!(sender.sent().is_empty());
+    }
+
+    #[test]
+    fn it_only_counts_matches_when_asked_to() {
+        // Our own process is never signaled, so this is safe to run for real.
+        let own_pid = ::std::process::id().to_string();
+        let options = options(&["--count", "--pid", &own_pid]);
+        let reporter = RecordingReporter::default();
+        let sender = MockSender::new();
+
+        let report = run_with_sender(&options, &reporter, &sender).expect("Failed to count");
+        assert_eq!(report.matched, 1);
+        assert_eq!(report.skipped, 0);
+        assert!(reporter.events().is_empty());
+        assert!(sender.sent().is_empty());
+    }
+
+    #[test]
+    fn it_counts_processes_given_several_times_once() {
+        // Our own process is never signaled, so this is safe to run for real.
+        let own_pid = ::std::process::id().to_string();
+        let options = options(&["--count", "--pid", &own_pid, "--pid", &own_pid]);
+
+        let report =
+            run_with_sender(&options, &NullReporter, &MockSender::new()).expect("Failed to count");
+        assert_eq!(report.matched, 1);
+    }
+
+    #[test]
+    fn it_caps_waiting_at_the_deadline() {
+        let none = Deadline::after(None);
+        assert!(!none.has_passed());
+        assert_eq!(none.cap(Duration::from_secs(5)), Duration::from_secs(5));
+
+        let later = Deadline::after(Some(Duration::from_secs(60)));
+        assert!(!later.has_passed());
+        assert_eq!(later.cap(Duration::from_secs(5)), Duration::from_secs(5));
+        assert!(later.cap(Duration::from_secs(120)) <= Duration::from_secs(60));
+
+        let now = Deadline::after(Some(Duration::from_secs(0)));
+        assert!(now.has_passed());
+        assert_eq!(now.cap(Duration::from_secs(5)), Duration::from_secs(0));
+    }
+
+    #[test]
+    fn it_aborts_with_a_partial_report_when_the_deadline_passes() {
+        // Our own process is never signaled, so this is safe to run for real.
+        let own_pid = ::std::process::id().to_string();
+        let options = options(&["--deadline", "0", "--pid", &own_pid]);
+
+        let error = run_with_reporter(&options, &NullReporter).unwrap_err();
+        match error.downcast::<DeadlineError>() {
+            Ok(DeadlineError::Exceeded(0, report)) => {
+                assert_eq!(report.matched, 1);
+                assert_eq!(report.skipped, 1);
+            }
+            other => panic!("Expected deadline to be exceeded, got {:?}", other),
+        }
+    }
+}
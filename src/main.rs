@@ -1,31 +1,31 @@
-#[macro_use]
-extern crate structopt;
-#[macro_use]
-extern crate failure;
-
+extern crate anyhow;
+extern crate graceful_shutdown;
 extern crate nix;
-extern crate regex;
+extern crate structopt;
 extern crate termion;
-extern crate users;
-
-mod matcher;
-mod options;
-mod processes;
-mod signal;
-
-use failure::{Error, ResultExt};
-use matcher::Matcher;
-use options::{CliOptions, Options, UserMode};
-use processes::{KillError, Process};
-use regex::{RegexSet, RegexSetBuilder};
-use signal::Signal;
+
+use anyhow::{Context, Error};
+use graceful_shutdown::options::{CompletionShells, OutputFormat, EXIT_STATUS_HELP};
+use graceful_shutdown::{json, timestamp};
+use graceful_shutdown::{run, CliCommand, CliOptions, DeadlineError, Options, RunReport, Signal};
+use std::fs::{self, File};
 use std::io;
-use std::io::BufRead;
-use std::time::{Duration, Instant};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use structopt::clap::{App, AppSettings, Shell};
 use structopt::StructOpt;
-use users::uid_t;
 
-fn list_signals() {
+#[cfg(test)]
+#[path = "test_dir.rs"]
+mod test_dir;
+
+fn list_signals(output_format: OutputFormat) {
+    if output_format.is_json() {
+        println!("{}", json::signals_to_json(Signal::iterator()));
+        return;
+    }
+
     // Print user-centric text if stdout is to a terminal. If piping stdout to some other process,
     // this text will not be shown.
     let is_tty = termion::is_tty(&::std::io::stdout());
@@ -38,316 +38,426 @@ fn list_signals() {
         println!("{}\t{}", signal.number(), signal);
     }
 
+    for (alias, signal) in Signal::aliases() {
+        println!("{}\t{} (alias of {})", signal.number(), alias, signal);
+    }
+
     if is_tty {
         println!("Signal names does not require the SIG prefix, and are case-insensitive.");
     };
 }
 
-fn generate_completions(shell: structopt::clap::Shell) {
-    let mut app = CliOptions::clap();
-    let name = app.get_name().to_string();
-
-    app.gen_completions_to(name, shell, &mut io::stdout());
-}
-
-fn main() {
-    use std::process::exit;
-    let cli_options = CliOptions::from_args();
-
-    if cli_options.list_signals {
-        list_signals();
-        return;
-    }
-
-    if let Some(shell) = cli_options.generate_completions {
-        generate_completions(shell);
-        return;
-    }
-
-    let options = Options::from(cli_options);
-    match run(&options) {
-        Ok(success) => if success {
-            exit(0)
-        } else {
-            exit(1)
-        },
-        Err(err) => {
-            if options.output_mode.show_normal() {
-                eprintln!(
-                    "{red}ERROR: {message}{reset}",
-                    message = err,
-                    red = options.colors.red(),
-                    reset = options.colors.reset(),
-                );
-                for (level, cause) in err.iter_causes().enumerate() {
-                    eprintln!(
-                        "{red}{indent:width$}Caused by: {cause}{reset}",
-                        cause = cause,
-                        indent = "",
-                        width = (level + 1) * 2,
-                        red = options.colors.red(),
-                        reset = options.colors.reset(),
-                    );
-                }
+fn generate_completions(
+    shells: CompletionShells,
+    output_dir: Option<PathBuf>,
+) -> Result<(), Error> {
+    match output_dir {
+        Some(dir) => write_completions(&dir, &shells.shells()).map(|_| ()),
+        None => {
+            let mut app = CliCommand::clap();
+            let name = app.get_name().to_string();
+            for shell in shells.shells() {
+                app.gen_completions_to(name.clone(), shell, &mut io::stdout());
             }
-            exit(1);
+            Ok(())
         }
     }
 }
 
-fn run(options: &Options) -> Result<bool, Error> {
-    let matcher = Matcher::new(
-        load_patterns(options).context("Could not load patterns")?,
-        options.match_mode,
-    );
+/// Writes a completion file for each shell into the directory, creating it if needed. Returns the
+/// paths of the written files.
+fn write_completions(dir: &Path, shells: &[Shell]) -> Result<Vec<PathBuf>, Error> {
+    fs::create_dir_all(dir).with_context(|| format!("Could not create {}", dir.display()))?;
 
-    let processes = all_processes(options, &matcher).context("Could not build process list")?;
+    let mut app = CliCommand::clap();
+    let name = app.get_name().to_string();
+    let mut paths = Vec::with_capacity(shells.len());
+
+    for &shell in shells {
+        let path = dir.join(completion_file_name(&name, shell));
+        let mut file =
+            File::create(&path).with_context(|| format!("Could not write {}", path.display()))?;
+        app.gen_completions_to(name.clone(), shell, &mut file);
+        paths.push(path);
+    }
 
-    // Time to shut them down
-    if options.dry_run {
-        dry_run(options, &processes)
-    } else {
-        real_run(options, processes)
+    Ok(paths)
+}
+
+/// The file name that each shell looks for completions of the command in.
+fn completion_file_name(name: &str, shell: Shell) -> String {
+    match shell {
+        Shell::Bash => format!("{}.bash", name),
+        Shell::Fish => format!("{}.fish", name),
+        Shell::Zsh => format!("_{}", name),
+        Shell::PowerShell => format!("_{}.ps1", name),
+        Shell::Elvish => format!("{}.elv", name),
     }
 }
 
-fn load_patterns(options: &Options) -> Result<RegexSet, Error> {
-    if options.output_mode.show_normal() && termion::is_tty(&::std::io::stdin()) {
-        eprintln!(
-            "{yellow}WARNING: Reading processlist from TTY stdin. Exit with ^D when you are done, or ^C to abort.{reset}",
-            yellow = options.colors.yellow(),
-            reset = options.colors.reset(),
-        );
+fn generate_man(output_file: Option<PathBuf>) -> Result<(), Error> {
+    match output_file {
+        Some(path) => fs::write(&path, man_page())
+            .with_context(|| format!("Could not write {}", path.display())),
+        None => io::stdout()
+            .write_all(man_page().as_bytes())
+            .context("Could not write the man page"),
     }
+}
 
-    let stdin = io::stdin();
-    let patterns: Vec<String> = stdin
-        .lock()
-        .lines()
-        .flat_map(Result::ok)
-        .map(strip_comment)
-        .filter(|s| !s.is_empty())
-        .collect();
-
-    RegexSetBuilder::new(&patterns)
-        .case_insensitive(true)
-        .build()
-        .map_err(|err| err.into())
+/// Renders a man page in roff format from the help of the run options and the subcommands.
+fn man_page() -> String {
+    let name = CliCommand::clap().get_name().to_string();
+    let exit_status = EXIT_STATUS_HELP.splitn(2, '\n').nth(1).unwrap_or_default();
+
+    format!(
+        ".TH {title} 1 \"\" \"{name} {version}\"
+.SH NAME
+{name} \\- {about}
+.SH SYNOPSIS
+.B {name}
+[\\fIOPTIONS\\fR]
+.br
+.B {name}
+\\fISUBCOMMAND\\fR [\\fIARGS\\fR]
+.SH DESCRIPTION
+{about}
+Without a subcommand, all arguments are given to the \\fBrun\\fR subcommand, whose options are
+listed below.
+.SH OPTIONS
+.nf
+{options}
+.fi
+.SH SUBCOMMANDS
+.nf
+{subcommands}
+.fi
+.SH EXIT STATUS
+.nf
+{exit_status}
+.fi
+",
+        title = roff_escape(&name.to_uppercase()),
+        name = roff_escape(&name),
+        version = env!("CARGO_PKG_VERSION"),
+        about = roff_escape(&render_help(CliOptions::clap(), "{about}")),
+        options = roff_escape(&render_help(CliOptions::clap(), "{all-args}")),
+        subcommands = roff_escape(&render_help(CliCommand::clap(), "{subcommands}")),
+        exit_status = roff_escape(exit_status),
+    )
 }
 
-fn all_processes(options: &Options, matcher: &Matcher) -> Result<Vec<Process>, Error> {
-    let iter = match &options.user_mode {
-        UserMode::Everybody => Process::all()?,
-        UserMode::OnlyMe => Process::all_from_user(users::get_current_uid())?,
-        UserMode::Only(name) => Process::all_from_user(find_user_by_name(&name)?)?,
-    };
+/// Renders parts of the help of the app through a clap help template, without any colors.
+fn render_help(app: App<'static, 'static>, template: &'static str) -> String {
+    let mut app = app
+        .setting(AppSettings::ColorNever)
+        .set_term_width(80)
+        .template(template);
+    let mut help = Vec::new();
+    app.write_long_help(&mut help)
+        .expect("Writing to memory cannot fail");
+    String::from_utf8_lossy(&help).trim_end().to_string()
+}
 
-    Ok(iter
-        .flat_map(Result::ok)
-        .filter(|process| matcher.is_match(process))
-        .collect::<Vec<_>>())
+/// Escapes text so roff shows it as it is, instead of reading it as requests or escapes.
+fn roff_escape(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            let line = line.replace('\\', "\\e").replace('-', "\\-");
+            if line.starts_with('.') || line.starts_with('\'') {
+                format!("\\&{}", line)
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
-#[derive(Debug, Fail)]
-pub enum UserError {
-    #[fail(display = "Could not find user with name \"{}\"", _0)]
-    NotFound(String),
+/// How a run ended. Each variant maps to a distinct exit status so scripts can tell them apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RunStatus {
+    /// Every matched process was shut down.
+    Success,
+    /// Some processes could not be signaled or were still alive at the end.
+    PartialFailure,
+    /// No process matched, and that was asked to be a failure.
+    NothingMatched,
 }
 
-fn find_user_by_name(name: &str) -> Result<uid_t, UserError> {
-    users::get_user_by_name(name)
-        .ok_or_else(|| UserError::NotFound(name.to_owned()))
-        .map(|user| user.uid())
+/// Exit status when the command-line arguments or patterns were invalid, or the run could not
+/// start for other reasons.
+const EXIT_ERROR: i32 = 3;
+
+/// Exit status when the --deadline was exceeded before the run was over.
+const EXIT_DEADLINE: i32 = 4;
+
+/// Exits the whole program a little while after the deadline, in case the run is stuck somewhere
+/// it cannot check the deadline by itself, like reading from a hanging /proc entry.
+fn spawn_deadline_watchdog(deadline: Duration, show_errors: bool, timestamps: bool) {
+    ::std::thread::spawn(move || {
+        ::std::thread::sleep(deadline + Duration::from_secs(1));
+        if show_errors {
+            eprintln!("{}ERROR: Deadline exceeded", line_prefix(timestamps));
+        }
+        ::std::process::exit(EXIT_DEADLINE);
+    });
 }
 
-fn strip_comment(line: String) -> String {
-    match line.find('#') {
-        Some(index) => line[0..index].trim().to_string(),
-        None => line,
+/// The start of every line of error output, which is the current time with --timestamps.
+fn line_prefix(timestamps: bool) -> String {
+    if timestamps {
+        format!("{} ", timestamp::rfc3339(SystemTime::now()))
+    } else {
+        String::new()
     }
 }
 
-fn dry_run(options: &Options, processes: &[Process]) -> Result<bool, Error> {
-    // If we're not rendering anything, might as well skip the iteration completely.
-    if !options.output_mode.show_normal() {
-        return Ok(true);
+impl RunStatus {
+    /// How a run ended. Finding nothing is only a failure with --error-if-none.
+    fn from_report(report: &RunReport, error_if_none: bool) -> RunStatus {
+        if report.matched == 0 {
+            if error_if_none {
+                RunStatus::NothingMatched
+            } else {
+                RunStatus::Success
+            }
+        } else if report.success() {
+            RunStatus::Success
+        } else {
+            RunStatus::PartialFailure
+        }
     }
 
-    for process in processes {
-        println!(
-            "Would have sent {signal} to process {process}",
-            signal = options.terminate_signal,
-            process = human_process_description(options, process),
-        );
+    /// How a --count run ended. Finding nothing is only a failure when a match is required.
+    fn from_count(report: &RunReport, requires_match: bool) -> RunStatus {
+        if report.matched == 0 && requires_match {
+            RunStatus::NothingMatched
+        } else {
+            RunStatus::Success
+        }
     }
 
-    Ok(true)
+    fn exit_code(self) -> i32 {
+        match self {
+            RunStatus::Success => 0,
+            RunStatus::PartialFailure => 1,
+            RunStatus::NothingMatched => 2,
+        }
+    }
 }
 
-fn real_run(options: &Options, mut processes: Vec<Process>) -> Result<bool, Error> {
-    let mut success = true;
-
-    // Try to terminate all the processes. If any process failed to receive the signal, then remove
-    // it from the list so the coming waiting part does not wait for any process that will not be
-    // terminated anyway.
-    //
-    // As an example, if a process has a "Permission denied" error, it will fail to get the
-    // terminate signal. Why would we be waiting on this process and then try to kill it when that
-    // too will fail?
-    processes.retain(|process| {
-        verbose_signal_message(options.terminate_signal, options, process);
-        if send_with_error_handling(options.terminate_signal, options, process) {
-            true
-        } else {
-            success = false;
-            false
+fn main() {
+    use std::process::exit;
+    let command = match CliCommand::from_args_with_default(::std::env::args_os()) {
+        Ok(command) => command,
+        Err(ref err) if err.use_stderr() => {
+            eprintln!("{}", err.message);
+            exit(EXIT_ERROR);
         }
-    });
-
-    // Wait for processess to die
-    if let Some(wait_time) = options.wait_time {
-        let start = Instant::now();
-
-        while start.elapsed() < wait_time {
-            ::std::thread::sleep(Duration::from_millis(100));
+        // --help and --version
+        Err(err) => err.exit(),
+    };
 
-            // Remove dead processes
-            processes.retain(|process| {
-                let is_alive = process.is_alive();
+    let cli_options = match command {
+        CliCommand::Run(cli_options) => cli_options,
+        CliCommand::ListSignals { output_format } => return list_signals(output_format),
+        CliCommand::Completions { shell, output_dir } => {
+            if let Err(err) = generate_completions(shell, output_dir) {
+                eprintln!("ERROR: {:#}", err);
+                exit(EXIT_ERROR);
+            }
+            return;
+        }
+        CliCommand::Man { output_file } => {
+            if let Err(err) = generate_man(output_file) {
+                eprintln!("ERROR: {:#}", err);
+                exit(EXIT_ERROR);
+            }
+            return;
+        }
+    };
 
-                if options.output_mode.show_verbose() && !is_alive {
-                    eprintln!(
-                        "Process shut down: {process}",
-                        process = human_process_description(options, process),
-                    );
-                }
+    let options = Options::from(cli_options);
+    if let Some(deadline) = options.deadline {
+        spawn_deadline_watchdog(
+            deadline,
+            options.output_mode.show_errors(),
+            options.timestamps,
+        );
+    }
 
-                is_alive
-            });
+    let started = Instant::now();
+    let result = run(&options);
+    if options.json_summary {
+        let report = match result {
+            Ok(ref report) => Some(report),
+            Err(ref err) => match err.downcast_ref::<DeadlineError>() {
+                Some(DeadlineError::Exceeded(_, report)) => Some(report),
+                None => None,
+            },
+        };
+        if let Some(report) = report {
+            let signals = options.escalation.steps().iter().map(|step| step.signal);
+            println!(
+                "{}",
+                json::RunSummary::new(report, signals, started.elapsed()).to_json()
+            );
+        }
+    }
 
-            if processes.is_empty() {
-                return Ok(success);
+    match result {
+        Ok(report) if options.count => {
+            println!("{}", report.matched);
+            exit(RunStatus::from_count(&report, options.count_requires_match).exit_code())
+        }
+        Ok(report) if options.stats => {
+            for (pattern, count) in report.pattern_counts {
+                println!("{}\t{}", count, pattern);
             }
+            exit(RunStatus::Success.exit_code())
         }
-
-        // Time is up. Kill remaining processes.
-        if options.kill {
-            if options.output_mode.show_verbose() {
+        Ok(report) => exit(RunStatus::from_report(&report, options.error_if_none).exit_code()),
+        Err(err) => {
+            if options.output_mode.show_errors() {
                 eprintln!(
-                    "{red}Timeout reached. Forcefully shutting down processes.{reset}",
+                    "{prefix}{red}ERROR: {message}{reset}",
+                    prefix = line_prefix(options.timestamps),
+                    message = err,
                     red = options.colors.red(),
-                    reset = options.colors.reset()
-                );
-            }
-            for process in &processes {
-                verbose_signal_message(options.kill_signal, options, process);
-                if !send_with_error_handling(options.kill_signal, options, process) {
-                    success = false;
-                }
-            }
-        } else {
-            if options.output_mode.show_normal() {
-                eprintln!(
-                    "{yellow}WARNING: Some processes are still alive.{reset}",
-                    yellow = options.colors.yellow(),
-                    reset = options.colors.reset()
+                    reset = options.colors.reset(),
                 );
-            }
-            if options.output_mode.show_verbose() {
-                for process in &processes {
+                for (level, cause) in err.chain().skip(1).enumerate() {
                     eprintln!(
-                        "Process {process}",
-                        process = human_process_description(options, process)
+                        "{prefix}{red}{indent:width$}Caused by: {cause}{reset}",
+                        prefix = line_prefix(options.timestamps),
+                        cause = cause,
+                        indent = "",
+                        width = (level + 1) * 2,
+                        red = options.colors.red(),
+                        reset = options.colors.reset(),
                     );
                 }
             }
-            success = false;
+            if err.downcast_ref::<DeadlineError>().is_some() {
+                exit(EXIT_DEADLINE);
+            }
+            exit(EXIT_ERROR);
         }
     }
-
-    Ok(success)
-}
-
-fn verbose_signal_message(signal: Signal, options: &Options, process: &Process) {
-    if options.output_mode.show_verbose() {
-        eprintln!(
-            "Sending {signal} to process {process}",
-            signal = signal,
-            process = human_process_description(options, process),
-        );
-    }
 }
 
-#[must_use]
-fn send_with_error_handling(signal: Signal, options: &Options, process: &Process) -> bool {
-    match process.send(signal) {
-        Ok(_) => true,
-        // Process quit before we had time to signal it? That should be fine. The next steps will
-        // verify that it is gone instead.
-        Err(KillError::DoesNotExist) => true,
-        Err(error) => {
-            eprintln!(
-                "{red}Failed to send {signal} to{reset} {process}: {red}{error}{reset}",
-                signal = signal,
-                process = human_process_description(options, process),
-                error = error,
-                red = options.colors.red(),
-                reset = options.colors.reset(),
-            );
-            false
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use graceful_shutdown::ProcessDescription;
+    use nix::unistd::Pid;
+    use test_dir::TestDir;
+
+    fn report(matched: usize, alive: usize) -> RunReport {
+        let description = ProcessDescription {
+            pid: Pid::from_raw(42),
+            name: String::from("sleep"),
+            cmdline: String::from("sleep 60"),
+        };
+
+        RunReport {
+            matched,
+            alive: vec![description; alive],
+            ..RunReport::default()
         }
     }
-}
 
-fn human_process_description(options: &Options, process: &Process) -> String {
-    use matcher::MatchMode;
-
-    match options.match_mode {
-        MatchMode::Basename => format!(
-            "{green}{pid}{reset} ({green}{name}{reset})",
-            pid = process.pid(),
-            name = process.name(),
-            green = options.colors.green(),
-            reset = options.colors.reset()
-        ),
-        MatchMode::Commandline => format!(
-            "{green}{pid}{reset} ({green}{name}{reset}): {faded}{cmdline}{reset}",
-            pid = process.pid(),
-            name = process.name(),
-            cmdline = process.commandline(),
-            green = options.colors.green(),
-            faded = options.colors.faded(),
-            reset = options.colors.reset(),
-        ),
+    #[test]
+    fn it_has_distinct_exit_codes() {
+        assert_eq!(RunStatus::from_report(&report(3, 0), false).exit_code(), 0);
+        assert_eq!(RunStatus::from_report(&report(3, 1), false).exit_code(), 1);
+        assert_eq!(RunStatus::from_report(&report(0, 0), true).exit_code(), 2);
+        assert_eq!(EXIT_ERROR, 3);
+        assert_eq!(EXIT_DEADLINE, 4);
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
 
     #[test]
-    fn it_strips_comments() {
+    fn it_only_fails_on_empty_runs_with_error_if_none() {
         assert_eq!(
-            strip_comment(String::from("Foobar")),
-            String::from("Foobar"),
+            RunStatus::from_report(&report(0, 0), false),
+            RunStatus::Success
         );
-
-        assert_eq!(strip_comment(String::from("Foo#bar")), String::from("Foo"),);
-
         assert_eq!(
-            strip_comment(String::from(" Complicated # oh yes!! # another one")),
-            String::from("Complicated"),
+            RunStatus::from_report(&report(0, 0), true),
+            RunStatus::NothingMatched
         );
+        assert_eq!(
+            RunStatus::from_report(&report(3, 0), true),
+            RunStatus::Success
+        );
+    }
 
+    #[test]
+    fn it_only_fails_counting_when_a_match_is_required() {
         assert_eq!(
-            strip_comment(String::from("# Just a comment")),
-            String::from(""),
+            RunStatus::from_count(&report(3, 0), false),
+            RunStatus::Success
         );
+        assert_eq!(
+            RunStatus::from_count(&report(0, 0), false),
+            RunStatus::Success
+        );
+        assert_eq!(
+            RunStatus::from_count(&report(3, 0), true),
+            RunStatus::Success
+        );
+        assert_eq!(
+            RunStatus::from_count(&report(0, 0), true),
+            RunStatus::NothingMatched
+        );
+    }
+
+    #[test]
+    fn it_writes_completions_for_all_shells() {
+        let parent = TestDir::new("completions");
+        let dir = parent.path().join("nested");
+
+        let paths = write_completions(&dir, &CompletionShells::All.shells()).unwrap();
 
+        let mut names: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
         assert_eq!(
-            strip_comment(String::from("  \t# Just a comment")),
-            String::from(""),
+            names,
+            vec![
+                "_graceful-shutdown",
+                "_graceful-shutdown.ps1",
+                "graceful-shutdown.bash",
+                "graceful-shutdown.elv",
+                "graceful-shutdown.fish",
+            ]
         );
+        for path in paths {
+            assert!(
+                fs::metadata(&path).unwrap().len() > 0,
+                "{:?} is empty",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn it_generates_a_man_page() {
+        let page = man_page();
+
+        assert!(page.starts_with(".TH GRACEFUL\\-SHUTDOWN 1 "));
+        assert!(page.contains("\\-\\-wait\\-time"));
+        assert!(page.contains("Time to wait for processes to terminate"));
+        assert!(page.contains("list\\-signals"));
+        assert!(page.contains("No process matched, and"));
+    }
+
+    #[test]
+    fn it_escapes_roff() {
+        assert_eq!(roff_escape("--pid"), "\\-\\-pid");
+        assert_eq!(roff_escape(".TH\n'x\na\\b"), "\\&.TH\n\\&'x\na\\eb");
     }
 }
@@ -1,27 +1,442 @@
 use processes::Process;
 use regex::RegexSet;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MatchMode {
     Basename,
     Commandline,
+    Args,
+    ExePath,
 }
 
 #[derive(Debug)]
 pub struct Matcher {
     regex_set: RegexSet,
+    texts: Vec<String>,
+    exclude_set: RegexSet,
+    deny_set: RegexSet,
     mode: MatchMode,
 }
 
 impl Matcher {
-    pub fn new(regex_set: RegexSet, mode: MatchMode) -> Self {
-        Matcher { regex_set, mode }
+    pub fn new(regex_set: RegexSet, exclude_set: RegexSet, mode: MatchMode) -> Self {
+        Matcher {
+            texts: regex_set.patterns().to_vec(),
+            regex_set,
+            exclude_set,
+            deny_set: RegexSet::empty(),
+            mode,
+        }
+    }
+
+    /// Shows the patterns as written, like "nginx" instead of the regular expression a plain name
+    /// is turned into. Ignored unless there is one text for every pattern, in the same order.
+    pub fn with_pattern_texts(mut self, texts: Vec<String>) -> Self {
+        if texts.len() == self.regex_set.len() {
+            self.texts = texts;
+        }
+        self
+    }
+
+    /// Never matches processes with names in the denylist, no matter the patterns or match mode.
+    pub fn with_denylist(mut self, deny_set: RegexSet) -> Self {
+        self.deny_set = deny_set;
+        self
     }
 
     pub fn is_match(&self, process: &Process) -> bool {
+        self.matches_patterns(process) && !self.deny_set.is_match(process.name())
+    }
+
+    /// The patterns as written, in the order they were given.
+    pub fn patterns(&self) -> &[String] {
+        &self.texts
+    }
+
+    /// How many of the processes each pattern matches, in the order of the patterns. A process
+    /// counts for every pattern that matches it, and patterns that match nothing count 0.
+    pub fn count_by_pattern(&self, processes: &[Process]) -> Vec<usize> {
+        let mut counts = vec![0; self.regex_set.len()];
+        for process in processes {
+            for index in self.matching_patterns(process) {
+                counts[index] += 1;
+            }
+        }
+        counts
+    }
+
+    fn matching_patterns(&self, process: &Process) -> Vec<usize> {
+        let mut indexes: Vec<usize> = match self.mode {
+            MatchMode::Basename => self.regex_set.matches(process.name()).into_iter().collect(),
+            MatchMode::Commandline => self
+                .regex_set
+                .matches(process.commandline())
+                .into_iter()
+                .collect(),
+            MatchMode::Args => process
+                .args()
+                .iter()
+                .flat_map(|arg| self.regex_set.matches(arg).into_iter())
+                .collect(),
+            MatchMode::ExePath => process
+                .exe()
+                .map(|exe| {
+                    self.regex_set
+                        .matches(&exe.to_string_lossy())
+                        .into_iter()
+                        .collect()
+                })
+                .unwrap_or_default(),
+        };
+        indexes.sort_unstable();
+        indexes.dedup();
+        indexes
+    }
+
+    /// If the exclude patterns spare the process, no matter if the patterns match it.
+    pub fn is_excluded(&self, process: &Process) -> bool {
+        match self.mode {
+            MatchMode::Basename => self.exclude_set.is_match(process.name()),
+            MatchMode::Commandline => self.exclude_set.is_match(process.commandline()),
+            MatchMode::Args => process
+                .args()
+                .iter()
+                .any(|arg| self.exclude_set.is_match(arg)),
+            MatchMode::ExePath => process
+                .exe()
+                .is_some_and(|exe| self.exclude_set.is_match(&exe.to_string_lossy())),
+        }
+    }
+
+    /// If the patterns match the process, but the denylist keeps it from being signaled.
+    pub fn is_denied(&self, process: &Process) -> bool {
+        self.deny_set.is_match(process.name()) && self.matches_patterns(process)
+    }
+
+    fn matches_patterns(&self, process: &Process) -> bool {
         match self.mode {
-            MatchMode::Basename => self.regex_set.is_match(process.name()),
-            MatchMode::Commandline => self.regex_set.is_match(process.commandline()),
+            MatchMode::Basename => self.is_match_str(process.name()),
+            MatchMode::Commandline => self.is_match_str(process.commandline()),
+            MatchMode::Args => {
+                let args = process.args();
+                args.iter().any(|arg| self.regex_set.is_match(arg))
+                    && !args.iter().any(|arg| self.exclude_set.is_match(arg))
+            }
+            MatchMode::ExePath => process
+                .exe()
+                .is_some_and(|exe| self.is_match_str(&exe.to_string_lossy())),
+        }
+    }
+
+    fn is_match_str(&self, haystack: &str) -> bool {
+        self.regex_set.is_match(haystack) && !self.exclude_set.is_match(haystack)
+    }
+}
+
+/// If the pattern is a plain name like "nginx" or "php-fpm", without anything that would make a
+/// difference as a regular expression.
+pub fn is_plain_name(pattern: &str) -> bool {
+    !pattern.is_empty()
+        && pattern
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Wraps a regular expression so that it must match the whole string.
+pub fn anchor(regex: &str) -> String {
+    format!("^(?:{})$", regex)
+}
+
+/// Translates a shell glob into an anchored regular expression.
+///
+/// Supports "*", "?", character classes like "[a-z]" and "[!0-9]", and backslash escapes. Any
+/// other character is matched literally.
+pub fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::from("^");
+    let mut chars = glob.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '\\' => match chars.next() {
+                Some(escaped) => regex.push_str(&escape_char(escaped)),
+                None => regex.push_str(&escape_char('\\')),
+            },
+            '[' => {
+                let rest: String = chars.clone().collect();
+                match translate_class(&rest) {
+                    Some((class, consumed)) => {
+                        regex.push_str(&class);
+                        for _ in 0..consumed {
+                            chars.next();
+                        }
+                    }
+                    // An unterminated class is just a literal bracket.
+                    None => regex.push_str(&escape_char('[')),
+                }
+            }
+            other => regex.push_str(&escape_char(other)),
+        }
+    }
+
+    regex.push('$');
+    regex
+}
+
+/// Translates the inside of a glob character class (everything after the opening bracket) into a
+/// regex class. Returns the class and the number of characters consumed, including the closing
+/// bracket, or None if the class is never closed.
+fn translate_class(glob: &str) -> Option<(String, usize)> {
+    let mut class = String::from("[");
+    let mut chars = glob.chars().enumerate().peekable();
+
+    if let Some(&(_, '!')) = chars.peek() {
+        class.push('^');
+        chars.next();
+    }
+
+    // A closing bracket as the first character of the class is a literal.
+    if let Some(&(_, ']')) = chars.peek() {
+        class.push_str("\\]");
+        chars.next();
+    }
+
+    for (index, c) in chars {
+        match c {
+            ']' => {
+                class.push(']');
+                return Some((class, index + 1));
+            }
+            '\\' | '[' | '^' | '&' | '~' => {
+                class.push('\\');
+                class.push(c);
+            }
+            other => class.push(other),
+        }
+    }
+
+    None
+}
+
+fn escape_char(c: char) -> String {
+    ::regex::escape(&c.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_recognizes_plain_names() {
+        for name in &["nginx", "php-fpm", "redis_server", "Xorg", "kworker"] {
+            assert!(is_plain_name(name), "{} should be a plain name", name);
+        }
+        for pattern in &[
+            "",
+            "^nginx$",
+            "php-fpm7.4",
+            "chrom(e|ium)",
+            "ng.nx",
+            "node server",
+        ] {
+            assert!(
+                !is_plain_name(pattern),
+                "{} should not be a plain name",
+                pattern
+            );
+        }
+    }
+
+    #[test]
+    fn it_matches_full_executable_paths() {
+        let matcher = Matcher::new(
+            RegexSet::new(["^/opt/app/bin/server$"]).unwrap(),
+            RegexSet::empty(),
+            MatchMode::ExePath,
+        );
+
+        assert!(
+            matcher.is_match(&Process::fake(1, "server", "server").with_exe("/opt/app/bin/server"))
+        );
+        assert!(
+            !matcher.is_match(&Process::fake(2, "server", "server").with_exe("/usr/bin/server"))
+        );
+        // Processes of other users might not have a readable executable.
+        assert!(!matcher.is_match(&Process::fake(3, "server", "/opt/app/bin/server")));
+    }
+
+    #[test]
+    fn it_spares_excluded_processes() {
+        let matcher = Matcher::new(
+            RegexSet::new(["python.*"]).unwrap(),
+            RegexSet::new(["celery-beat"]).unwrap(),
+            MatchMode::Basename,
+        );
+
+        assert!(matcher.is_match(&Process::fake(1, "python3", "python3 app.py")));
+        assert!(!matcher.is_match(&Process::fake(2, "python-celery-beat", "python3")));
+        assert!(!matcher.is_match(&Process::fake(3, "ruby", "ruby")));
+    }
+
+    #[test]
+    fn it_excludes_using_the_match_mode() {
+        let matcher = Matcher::new(
+            RegexSet::new(["python.*"]).unwrap(),
+            RegexSet::new(["celery-beat"]).unwrap(),
+            MatchMode::Commandline,
+        );
+
+        assert!(matcher.is_match(&Process::fake(1, "celery-beat", "python3 app.py")));
+        assert!(!matcher.is_match(&Process::fake(2, "python3", "python3 celery-beat")));
+    }
+
+    #[test]
+    fn it_never_matches_denied_processes() {
+        let matcher = Matcher::new(
+            RegexSet::new(["^ssh"]).unwrap(),
+            RegexSet::empty(),
+            MatchMode::Commandline,
+        )
+        .with_denylist(RegexSet::new(["^sshd$"]).unwrap());
+
+        let daemon = Process::fake(1, "sshd", "ssh-daemon");
+        assert!(!matcher.is_match(&daemon));
+        assert!(matcher.is_denied(&daemon));
+
+        let client = Process::fake(2, "ssh", "ssh example.com");
+        assert!(matcher.is_match(&client));
+        assert!(!matcher.is_denied(&client));
+
+        // Denied processes that the patterns do not match are not worth mentioning.
+        let unrelated = Process::fake(3, "sshd", "/usr/sbin/sshd -D");
+        assert!(!matcher.is_match(&unrelated));
+        assert!(!matcher.is_denied(&unrelated));
+    }
+
+    #[test]
+    fn it_counts_matches_by_pattern() {
+        let matcher = Matcher::new(
+            RegexSet::new(["^python", "worker", "^ruby$"]).unwrap(),
+            RegexSet::empty(),
+            MatchMode::Args,
+        );
+        let processes = [
+            Process::fake(1, "python3", "python3 worker.py"),
+            Process::fake(2, "python3", "python3 app.py"),
+            // Matching twice in the same process still counts once.
+            Process::fake(3, "worker", "worker --worker"),
+        ];
+
+        assert_eq!(matcher.patterns(), ["^python", "worker", "^ruby$"]);
+        assert_eq!(matcher.count_by_pattern(&processes), vec![2, 2, 0]);
+    }
+
+    #[test]
+    fn it_matches_everything_included_without_exclusions() {
+        let matcher = Matcher::new(
+            RegexSet::new(["python.*"]).unwrap(),
+            RegexSet::empty(),
+            MatchMode::Basename,
+        );
+
+        assert!(matcher.is_match(&Process::fake(1, "python-celery-beat", "python3")));
+    }
+
+    #[test]
+    fn it_does_not_match_across_argument_boundaries() {
+        let matcher = Matcher::new(
+            RegexSet::new(["foo bar"]).unwrap(),
+            RegexSet::empty(),
+            MatchMode::Args,
+        );
+
+        assert!(!matcher.is_match(&Process::fake(1, "foo", "foo bar")));
+
+        let commandline_matcher = Matcher::new(
+            RegexSet::new(["foo bar"]).unwrap(),
+            RegexSet::empty(),
+            MatchMode::Commandline,
+        );
+        assert!(commandline_matcher.is_match(&Process::fake(1, "foo", "foo bar")));
+    }
+
+    #[test]
+    fn it_matches_any_single_argument() {
+        let matcher = Matcher::new(
+            RegexSet::new([anchor("--type=renderer")]).unwrap(),
+            RegexSet::new([anchor("--no-sandbox")]).unwrap(),
+            MatchMode::Args,
+        );
+
+        assert!(matcher.is_match(&Process::fake(1, "spotify", "spotify --type=renderer")));
+        assert!(!matcher.is_match(&Process::fake(2, "spotify", "spotify --type=zygote")));
+        assert!(!matcher.is_match(&Process::fake(
+            3,
+            "spotify",
+            "spotify --type=renderer --no-sandbox"
+        )));
+    }
+
+    #[test]
+    fn it_anchors_patterns_to_the_whole_string() {
+        let matcher = Matcher::new(
+            RegexSet::new([anchor("sh"), anchor("fire|chrome")]).unwrap(),
+            RegexSet::empty(),
+            MatchMode::Basename,
+        );
+
+        assert!(matcher.is_match(&Process::fake(1, "sh", "sh")));
+        assert!(!matcher.is_match(&Process::fake(2, "bash", "bash")));
+        assert!(!matcher.is_match(&Process::fake(3, "ssh", "ssh")));
+        assert!(!matcher.is_match(&Process::fake(4, "flush-worker", "flush-worker")));
+
+        // Alternations must be anchored as a whole, not only their first and last branches.
+        assert!(matcher.is_match(&Process::fake(5, "chrome", "chrome")));
+        assert!(!matcher.is_match(&Process::fake(6, "firefox", "firefox")));
+        assert!(!matcher.is_match(&Process::fake(7, "google-chrome", "google-chrome")));
+    }
+
+    #[test]
+    fn it_translates_glob_stars() {
+        assert_eq!(glob_to_regex("*.py"), "^.*\\.py$");
+        assert_eq!(glob_to_regex("fire*"), "^fire.*$");
+    }
+
+    #[test]
+    fn it_translates_glob_question_marks() {
+        assert_eq!(glob_to_regex("fire?ox"), "^fire.ox$");
+    }
+
+    #[test]
+    fn it_translates_glob_character_classes() {
+        assert_eq!(glob_to_regex("[abc]d"), "^[abc]d$");
+        assert_eq!(glob_to_regex("[a-z]*"), "^[a-z].*$");
+        assert_eq!(glob_to_regex("[!0-9]"), "^[^0-9]$");
+        assert_eq!(glob_to_regex("[]a]"), "^[\\]a]$");
+        assert_eq!(glob_to_regex("[^&]"), "^[\\^\\&]$");
+    }
+
+    #[test]
+    fn it_treats_unterminated_glob_classes_as_literals() {
+        assert_eq!(glob_to_regex("[abc"), "^\\[abc$");
+    }
+
+    #[test]
+    fn it_escapes_regex_metacharacters_in_globs() {
+        assert_eq!(glob_to_regex("my-app+worker"), "^my\\-app\\+worker$");
+        assert_eq!(glob_to_regex("(a|b)"), "^\\(a\\|b\\)$");
+        assert_eq!(glob_to_regex("\\*"), "^\\*$");
+    }
+
+    #[test]
+    fn it_builds_valid_regexes_from_globs() {
+        for glob in &["*.py", "[!a-c]?", "[]x]", "a.b+c", "[abc", "\\", "^$"] {
+            assert!(
+                ::regex::Regex::new(&glob_to_regex(glob)).is_ok(),
+                "{}",
+                glob
+            );
         }
     }
 }
@@ -2,8 +2,13 @@ extern crate structopt;
 extern crate termion;
 extern crate users;
 
+use escalation::Escalation;
 use matcher::MatchMode;
+use nix::unistd::Pid;
+use processes::UserIdKind;
 use signal::Signal;
+use std::ffi::OsString;
+use std::path::PathBuf;
 use std::time::Duration;
 use structopt::clap::Shell;
 
@@ -12,6 +17,53 @@ pub enum OutputMode {
     Normal,
     Verbose,
     Quiet,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum OutputFormat {
+    Human,
+    Json,
+}
+
+/// The order to signal processes in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    /// Lowest PID first.
+    Pid,
+    /// Alphabetically by name.
+    Name,
+    /// Youngest process first.
+    Age,
+    /// Oldest process first, in the order they were started.
+    Startup,
+}
+
+/// Which process to keep among several with the same name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AgeSelection {
+    /// The one that was started first.
+    Oldest,
+    /// The one that was started last.
+    Newest,
+}
+
+/// How patterns treat upper and lower case letters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaseMode {
+    /// No flag was given, so patterns are case-insensitive.
+    Default,
+    /// Patterns were explicitly made case-insensitive with --ignore-case.
+    Insensitive,
+    /// Patterns were made case-sensitive with --case-sensitive.
+    Sensitive,
+}
+
+/// The shells to generate completions for.
+#[derive(Debug, Clone, Copy)]
+pub enum CompletionShells {
+    One(Shell),
+    All,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -21,14 +73,122 @@ enum ColorMode {
     Never,
 }
 
+/// The names of all subcommands. Arguments that don't start with one of these are given to "run".
+const SUBCOMMANDS: [&str; 5] = ["run", "list-signals", "completions", "man", "help"];
+
+/// What each exit status of the run means, shown after the help of "run" and in the man page.
+pub const EXIT_STATUS_HELP: &str = "EXIT STATUS:
+    0    All matched processes were shut down.
+    1    Some processes could not be signaled, or were still alive at the end.
+    2    No process matched, and --error-if-none was given.
+    3    Invalid arguments or patterns, or some other error prevented the run.
+    4    The --deadline was exceeded.";
+
+#[derive(StructOpt, Debug)]
+#[structopt(
+    name = "graceful-shutdown",
+    raw(global_setting = "structopt::clap::AppSettings::ColoredHelp")
+)]
+/// Gracefully shuts down processes. Without a subcommand, all arguments are given to "run".
+// It is only parsed once, so the size of the run options does not matter.
+#[allow(clippy::large_enum_variant)]
+pub enum CliCommand {
+    /// Reads a list of commands to gracefully terminate from STDIN.
+    #[structopt(name = "run", raw(after_help = "EXIT_STATUS_HELP"))]
+    Run(CliOptions),
+
+    /// List all supported signals and exit.
+    #[structopt(name = "list-signals")]
+    ListSignals {
+        /// Format of the output. "json" prints all signals as a single JSON array.
+        #[structopt(
+            long = "output",
+            default_value = "human",
+            value_name = "FORMAT",
+            raw(possible_values = "&OutputFormat::variants()")
+        )]
+        output_format: OutputFormat,
+    },
+
+    /// Generate completion script for a given shell and output on STDOUT.
+    #[structopt(name = "completions")]
+    Completions {
+        /// The shell to generate completions for. "all" generates them for every shell, and
+        /// requires --output-dir.
+        #[structopt(
+            value_name = "SHELL",
+            raw(possible_values = "&CompletionShells::variants()")
+        )]
+        shell: CompletionShells,
+
+        /// Write the completions into a file per shell in this directory instead of STDOUT. The
+        /// files are named like the shells expect, e.g. "_graceful-shutdown" for zsh. The
+        /// directory is created if it does not exist.
+        #[structopt(
+            long = "output-dir",
+            value_name = "DIR",
+            parse(from_os_str),
+            raw(required_if = r#""shell", "all""#)
+        )]
+        output_dir: Option<PathBuf>,
+    },
+
+    /// Generate a man page in roff format and output on STDOUT.
+    #[structopt(name = "man")]
+    Man {
+        /// Write the man page into this file instead of STDOUT.
+        #[structopt(long = "output-file", value_name = "PATH", parse(from_os_str))]
+        output_file: Option<PathBuf>,
+    },
+}
+
+impl CliCommand {
+    /// Parses the command line like `from_iter_safe`, but picks the "run" subcommand when no other
+    /// subcommand is given. "graceful-shutdown -n" is the same as "graceful-shutdown run -n".
+    ///
+    /// The old --list-signals and --generate-completions flags still work as the first argument.
+    pub fn from_args_with_default<I, T>(args: I) -> Result<CliCommand, structopt::clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString>,
+    {
+        let mut args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+        let first = args.get(1).and_then(|arg| arg.to_str()).map(String::from);
+
+        match first.as_deref() {
+            Some("--list-signals") => args[1] = OsString::from("list-signals"),
+            Some("--generate-completions") => args[1] = OsString::from("completions"),
+            Some(name) if SUBCOMMANDS.contains(&name) => {}
+            Some("-h") | Some("--help") | Some("-V") | Some("--version") => {}
+            _ if args.is_empty() => {}
+            _ => args.insert(1, OsString::from("run")),
+        }
+
+        <CliCommand as structopt::StructOpt>::from_iter_safe(args)
+    }
+}
+
 #[derive(StructOpt, Debug)]
-#[structopt(raw(setting = "structopt::clap::AppSettings::ColoredHelp"))]
 /// Reads a list of commands to gracefully terminate from STDIN.
 pub struct CliOptions {
-    /// Number of seconds to wait for processes to terminate. Use 0 to disable waiting and exit
-    /// immediately with a success status code.
-    #[structopt(short = "w", long = "wait-time", default_value = "5.0", value_name = "SECONDS")]
-    wait_time: f64,
+    /// Time to wait for processes to terminate, in seconds or with a unit like "500ms", "30s",
+    /// "2m" or "1h". 0 works like --no-wait, but gives a warning.
+    #[structopt(
+        short = "w",
+        long = "wait-time",
+        default_value = "5",
+        value_name = "DURATION",
+        parse(try_from_str = "parse_seconds")
+    )]
+    wait_time: Duration,
+
+    /// Only send the terminate signal and exit with a success status code, without checking if
+    /// the processes exit. Nothing is ever killed.
+    #[structopt(
+        long = "no-wait",
+        raw(conflicts_with_all = r#"&["wait_time", "no_kill", "escalate"]"#)
+    )]
+    no_wait: bool,
 
     /// Do not try to kill processes that do not exit within the waiting time, if a waiting time is
     /// set. Exits with an error status code if any matched process was still alive when waiting
@@ -36,18 +196,23 @@ pub struct CliOptions {
     #[structopt(long = "no-kill")]
     no_kill: bool,
 
+    /// Exit with the "no process matched" status when nothing matched. Without it, a run that
+    /// found nothing to shut down is a success.
+    #[structopt(long = "error-if-none")]
+    error_if_none: bool,
+
     /// Signal to use when terminating processes.
     ///
     /// Signals can be specified using signal number or symbolic name (case insensitive, with or
-    /// without the SIG prefix).
+    /// without the SIG prefix). Defaults to the GRACEFUL_SHUTDOWN_SIGNAL environment variable, or
+    /// TERM if it is not set.
     #[structopt(
         short = "s",
         long = "terminate-signal",
-        default_value = "term",
         value_name = "SIGNAL",
         parse(try_from_str = "parse_signal")
     )]
-    terminate_signal: Signal,
+    terminate_signal: Option<Signal>,
 
     /// Signal to use when killing processes that did not quit before the wait time ran out.
     ///
@@ -61,13 +226,368 @@ pub struct CliOptions {
     )]
     kill_signal: Signal,
 
+    /// Refuse to run when the terminate signal cannot be caught by processes, like SIGKILL, or
+    /// when the kill signal can. Without this, only a warning is shown.
+    #[structopt(long = "strict")]
+    strict: bool,
+
+    /// Read patterns from the given file instead of from STDIN. Patterns cannot be piped to STDIN
+    /// at the same time.
+    #[structopt(short = "f", long = "pattern-file", value_name = "PATH", parse(from_os_str))]
+    pattern_file: Option<PathBuf>,
+
+    /// Read the denylist of process names that are never signaled from this JSON file, like
+    /// {"deny": ["^sshd$"]}. Defaults to /etc/graceful-shutdown.json if it exists.
+    #[structopt(long = "config", value_name = "PATH", parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// Append a line of JSON for every action taken to the given file, with a timestamp. This is
+    /// written no matter the output mode, so --quiet runs can still be audited.
+    #[structopt(long = "log-file", value_name = "PATH", parse(from_os_str))]
+    log_file: Option<PathBuf>,
+
+    /// Stream the same JSON objects as --output json to the Unix socket at the given path while
+    /// the run happens, no matter the output mode. The run continues if nothing listens there.
+    #[structopt(long = "events-socket", value_name = "PATH", parse(from_os_str))]
+    events_socket: Option<PathBuf>,
+
+    /// Spare processes matching the given pattern, even if they match the patterns from the
+    /// input. Can be given multiple times.
+    #[structopt(short = "x", long = "exclude", value_name = "REGEX", number_of_values = 1)]
+    exclude: Vec<String>,
+
+    /// Match patterns case-sensitively. Patterns are case-insensitive by default.
+    #[structopt(long = "case-sensitive")]
+    case_sensitive: bool,
+
+    /// Match patterns case-insensitively. This is already the default, but scripts can use this
+    /// to not depend on it.
+    #[structopt(short = "I", long = "ignore-case", visible_alias = "case-insensitive")]
+    ignore_case: bool,
+
+    /// Interpret patterns as literal strings instead of regular expressions.
+    #[structopt(short = "F", long = "fixed-strings")]
+    fixed_strings: bool,
+
+    /// Interpret patterns as shell globs (supporting "*", "?" and "[...]") instead of regular
+    /// expressions. Globs must match the whole name. Cannot be combined with --fixed-strings.
+    #[structopt(long = "glob", conflicts_with = "fixed_strings")]
+    glob: bool,
+
+    /// Require patterns to match the whole name (or commandline) instead of any part of it.
+    #[structopt(long = "exact", visible_alias = "anchored")]
+    exact: bool,
+
+    /// Treat plain names like "nginx" as regular expressions too, so they match any name that
+    /// contains them. Without this, they only match processes with exactly that name.
+    #[structopt(long = "regex", raw(conflicts_with_all = r#"&["fixed_strings", "glob"]"#))]
+    regex: bool,
+
+    /// Target the process with the given PID instead of reading patterns. Can be given multiple
+    /// times, or as a comma-separated list.
+    #[structopt(long = "pid", value_name = "PID", raw(use_delimiter = "true"))]
+    pid: Vec<i32>,
+
+    /// Spare the process with the given PID, even if it matches. Can be given multiple times.
+    #[structopt(long = "exclude-pid", value_name = "PID", number_of_values = 1)]
+    exclude_pid: Vec<i32>,
+
+    /// Send signals to the whole process group of each matched process instead of only the
+    /// process itself. Every group is only signaled once, even if several of its members match.
+    #[structopt(long = "process-group")]
+    process_group: bool,
+
+    /// Send signals to every thread of each matched process separately, using tgkill(2), instead
+    /// of to the process as a whole. Only useful for programs that handle signals differently
+    /// per thread.
+    #[structopt(long = "threads", conflicts_with = "process_group")]
+    signal_threads: bool,
+
+    /// Also signal all descendants of the matched processes. Children are signaled after their
+    /// parents, except when killing, where the deepest descendants go first. Descendants spared by
+    /// --exclude, --exclude-pid, --exclude-user, --user, --mine or --group are left alone, together
+    /// with their own descendants.
+    #[structopt(long = "tree", visible_alias = "kill-children")]
+    tree: bool,
+
+    /// Signal descendants before their ancestors when both match, so no child is orphaned before
+    /// it gets its own signal.
+    #[structopt(long = "leaves-first")]
+    leaves_first: bool,
+
+    /// Stop every matched process with SIGSTOP before signaling any of them, so none of them can
+    /// start new processes during the shutdown. Each process is continued right after it gets
+    /// the terminate signal, or when it is spared.
+    #[structopt(long = "freeze-first")]
+    freeze_first: bool,
+
+    /// Never signal the parent of this process, which is usually the shell that started it. This
+    /// process itself is always protected, unless --include-self is given.
+    #[structopt(long = "protect-parent")]
+    protect_parent: bool,
+
+    /// Never signal the process with the given PID. Can be given multiple times.
+    #[structopt(long = "protect", value_name = "PID", number_of_values = 1)]
+    protect: Vec<i32>,
+
+    /// Allow signaling PID 1 (init). It is skipped by default, as shutting it down usually
+    /// brings down the whole machine or container.
+    #[structopt(long = "allow-pid1")]
+    allow_pid1: bool,
+
+    /// Do not protect this process and its process group from being signaled. Other running
+    /// instances of graceful-shutdown are never protected. This process might shut itself down
+    /// before it is done with the others.
+    #[structopt(long = "include-self", conflicts_with = "protect_parent")]
+    include_self: bool,
+
+    /// Abort without signaling anything if more than this number of processes match. Protects
+    /// against overly broad patterns.
+    #[structopt(long = "limit", value_name = "N")]
+    limit: Option<usize>,
+
+    /// Only signal the first matching process, which is the one with the lowest PID unless
+    /// --sort says otherwise. The other matches are skipped. Combine with --limit to give up
+    /// when a pattern matches more than expected.
+    #[structopt(long = "first-match", conflicts_with = "tree")]
+    first_match: bool,
+
+    /// Of all matching processes with the same name, only signal the one that was started first.
+    /// The others are skipped.
+    #[structopt(long = "oldest-only", raw(conflicts_with_all = r#"&["newest_only", "tree"]"#))]
+    oldest_only: bool,
+
+    /// Of all matching processes with the same name, only signal the one that was started last.
+    /// The others are skipped.
+    #[structopt(long = "newest-only", conflicts_with = "tree")]
+    newest_only: bool,
+
+    /// Ask for confirmation on the terminal before signaling each process.
+    #[structopt(short = "i", long = "interactive")]
+    interactive: bool,
+
+    /// Send a sequence of signals, waiting up to the given number of seconds (or a duration like
+    /// "30s") after each of them for processes to exit before escalating to the next one. The
+    /// default is equivalent to "TERM:5,KILL".
+    ///
+    /// Processes still alive after the waiting time of the last signal are reported as failures.
+    #[structopt(
+        long = "escalate",
+        value_name = "SIGNAL:SECONDS,...",
+        parse(try_from_str = "parse_escalation"),
+        raw(conflicts_with_all = r#"&["terminate_signal", "kill_signal", "wait_time", "no_kill"]"#)
+    )]
+    escalate: Option<Escalation>,
+
+    /// Send the terminate signal again to processes that are still alive after this many seconds
+    /// of the waiting time. Some programs only start shutting down on the second signal. Must be
+    /// shorter than the waiting time.
+    #[structopt(
+        long = "resend-after",
+        value_name = "SECONDS",
+        parse(try_from_str = "parse_seconds")
+    )]
+    resend_after: Option<Duration>,
+
+    /// Read waiting times for specific processes from a file, instead of using --wait-time for
+    /// all of them. Every line has a case-sensitive regex for the process name and a waiting
+    /// time, like "^postgres$ 60". The first matching line wins.
+    #[structopt(
+        long = "grace-map",
+        value_name = "PATH",
+        parse(from_os_str),
+        raw(conflicts_with_all = r#"&["escalate", "no_wait", "resend_after"]"#)
+    )]
+    grace_map: Option<PathBuf>,
+
+    /// Maximum number of seconds for the whole run, including finding processes and waiting for
+    /// them. When exceeded, the run is aborted with a distinct exit status, without sending any
+    /// further signals.
+    #[structopt(long = "deadline", value_name = "SECONDS", parse(try_from_str = "parse_seconds"))]
+    deadline: Option<Duration>,
+
+    /// Keep looking for matching processes for this many seconds, and shut down new ones as they
+    /// show up. Useful for draining a machine where processes might still be started. Every
+    /// process is only handled once.
+    #[structopt(
+        long = "watch",
+        value_name = "SECONDS",
+        parse(try_from_str = "parse_seconds"),
+        raw(conflicts_with_all = r#"&["count", "dry_run", "pid"]"#)
+    )]
+    watch: Option<Duration>,
+
+    /// Maximum time to sleep between each check if processes have exited, in milliseconds or with
+    /// a unit like "1s". Checks start out frequent and back off up to this interval. Must not be
+    /// longer than the total waiting time.
+    #[structopt(
+        long = "poll-interval",
+        default_value = "500",
+        value_name = "DURATION",
+        parse(try_from_str = "parse_poll_interval")
+    )]
+    poll_interval: Duration,
+
+    /// Number of seconds between progress lines while waiting for processes to exit, in verbose
+    /// mode. Progress is also shown whenever a process exits.
+    #[structopt(
+        long = "progress-interval",
+        default_value = "5",
+        value_name = "SECONDS",
+        parse(try_from_str = "parse_seconds")
+    )]
+    progress_interval: Duration,
+
+    /// Time to sleep between signaling each process, in milliseconds or with a unit like "1s", to
+    /// avoid a thundering herd when shutting down many processes at once. Does not count against
+    /// the waiting time. SIGKILL is sent to all remaining processes without any delay.
+    #[structopt(
+        long = "signal-delay",
+        default_value = "0",
+        value_name = "DURATION",
+        parse(try_from_str = "parse_millis")
+    )]
+    signal_delay: Duration,
+
+    /// Number of times to retry sending a signal after an unexpected error. Missing permissions
+    /// and processes that are already gone are never retried.
+    #[structopt(long = "retries", default_value = "2", value_name = "N")]
+    retries: u32,
+
+    /// Signal processes in this order instead of the order they were found in. "age" takes the
+    /// youngest processes first, and "startup" the oldest first.
+    #[structopt(long = "sort", value_name = "KEY", raw(possible_values = "&SortOrder::variants()"))]
+    sort: Option<SortOrder>,
+
+    /// Number of threads to use when reading the process list. Defaults to the number of CPUs.
+    #[structopt(short = "j", long = "jobs", value_name = "N", parse(try_from_str = "parse_jobs"))]
+    jobs: Option<usize>,
+
+    /// Where the proc filesystem is mounted. Processes found in another one, like the /proc of a
+    /// container, are signaled using the PIDs in it, so they must be in the same PID namespace.
+    #[structopt(
+        long = "proc-root",
+        value_name = "PATH",
+        default_value = "/proc",
+        env = "GRACEFUL_SHUTDOWN_PROC_ROOT",
+        parse(from_os_str)
+    )]
+    proc_root: PathBuf,
+
+    /// Include kernel threads when matching. They are never signaled, but are shown in --dry-run.
+    #[structopt(long = "include-kernel-threads")]
+    include_kernel_threads: bool,
+
     /// Match the whole commandline for the process rather than the basename.
     #[structopt(short = "W", long = "whole-command", visible_alias = "whole")]
     match_whole: bool,
 
-    /// Only find processes owned by the user with the given name.
-    #[structopt(short = "u", long = "user", value_name = "USER", overrides_with = "mine")]
-    user: Option<String>,
+    /// Match each argument of the commandline separately, so that patterns never match across
+    /// argument boundaries.
+    #[structopt(long = "match-args", conflicts_with = "match_whole")]
+    match_args: bool,
+
+    /// Match the full path of the executable of the process rather than the basename, like
+    /// "/opt/app/bin/server". Processes whose executable cannot be read never match.
+    #[structopt(
+        long = "match-path",
+        raw(conflicts_with_all = r#"&["match_whole", "match_args"]"#)
+    )]
+    match_path: bool,
+
+    /// Only find processes owned by the user with the given name. Can be given multiple times, or
+    /// as a comma-separated list, to find processes owned by any of the users.
+    ///
+    /// Numeric names are treated as user IDs unless a user with that name exists. Prefix with "#"
+    /// (like "#1000") to always treat it as a user ID.
+    #[structopt(
+        short = "u",
+        long = "user",
+        value_name = "USER",
+        overrides_with = "mine",
+        raw(use_delimiter = "true")
+    )]
+    user: Vec<String>,
+
+    /// Spare processes owned by the user with the given name. Can be given multiple times.
+    #[structopt(long = "exclude-user", value_name = "USER", number_of_values = 1)]
+    exclude_user: Vec<String>,
+
+    /// Match --user, --mine and --exclude-user against the real user ID of processes, which is
+    /// the user that started them. By default the effective user ID is used, which is the user
+    /// they run as. These only differ for setuid programs and services that change user.
+    #[structopt(long = "real-user")]
+    real_user: bool,
+
+    /// Only find processes owned by the group with the given name.
+    #[structopt(short = "g", long = "group", value_name = "GROUP")]
+    group: Option<String>,
+
+    /// Only find processes that have been running for at least this many seconds.
+    #[structopt(long = "min-age", value_name = "SECONDS", parse(try_from_str = "parse_seconds"))]
+    min_age: Option<Duration>,
+
+    /// Only find processes that have been running for at most this many seconds.
+    #[structopt(long = "max-age", value_name = "SECONDS", parse(try_from_str = "parse_seconds"))]
+    max_age: Option<Duration>,
+
+    /// Only find processes that were started before the file was last modified. Useful to only
+    /// restart stale instances after touching a marker file during a deploy.
+    #[structopt(long = "older-than-file", value_name = "PATH", parse(from_os_str))]
+    older_than_file: Option<PathBuf>,
+
+    /// Consider processes that have been running for at least this many seconds to be hung, and
+    /// send them the kill signal right away instead of trying to shut them down gracefully.
+    /// With --escalate, they get the last signal of the escalation.
+    #[structopt(
+        long = "stuck-after",
+        value_name = "SECONDS",
+        parse(try_from_str = "parse_seconds"),
+        raw(conflicts_with_all = r#"&["no_kill", "no_wait"]"#)
+    )]
+    stuck_after: Option<Duration>,
+
+    /// Only find processes in one of the given states, like "RS" for running or sleeping
+    /// processes. See the ps(1) manual for all states. Zombie processes ("Z") are skipped unless
+    /// included here, as they have already exited.
+    #[structopt(long = "state", value_name = "STATES")]
+    state: Option<String>,
+
+    /// Only find processes with the given controlling terminal, like "pts/3". Processes without a
+    /// controlling terminal never match.
+    #[structopt(long = "tty", value_name = "NAME")]
+    tty: Option<String>,
+
+    /// Only find processes in a cgroup whose path contains the given path, like
+    /// "system.slice/nginx.service". Useful for shutting down everything in a systemd unit,
+    /// slice or container.
+    #[structopt(long = "cgroup", value_name = "PATH")]
+    cgroup: Option<String>,
+
+    /// Only find processes with a TCP socket listening on the given port, over IPv4 or IPv6. Can
+    /// be given several times. Patterns are optional with this: without any on STDIN, every
+    /// process listening on the port is found.
+    #[structopt(long = "port", value_name = "PORT", raw(use_delimiter = "true"))]
+    ports: Vec<u16>,
+
+    /// Only find processes that were started with the given environment variable, like
+    /// "ROLE=flush". Can be given multiple times to require all of them. Processes whose
+    /// environment cannot be read, like those of other users, never match.
+    #[structopt(
+        long = "env",
+        value_name = "KEY=VALUE",
+        number_of_values = 1,
+        parse(try_from_str = "parse_env_var")
+    )]
+    env: Vec<(String, String)>,
+
+    /// Only find processes in the session with the given ID.
+    #[structopt(long = "sid", value_name = "ID")]
+    sid: Option<i32>,
+
+    /// Only find processes in the process group with the given ID.
+    #[structopt(long = "pgid", value_name = "ID")]
+    pgid: Option<i32>,
 
     /// Only find processes owned by you. Shortcut for --user "$USER". Has no effect if --user is
     /// specified.
@@ -79,80 +599,191 @@ pub struct CliOptions {
     #[structopt(short = "n", long = "dry-run")]
     dry_run: bool,
 
+    /// Only show how the dry run differs from one saved with --output json in this file: which
+    /// processes would newly be signaled, and which would no longer be.
+    #[structopt(long = "compare", value_name = "PATH", parse(from_os_str), requires = "dry_run")]
+    compare: Option<PathBuf>,
+
+    /// Only print the number of matching processes on STDOUT, without signaling anything. Exits
+    /// successfully even if nothing matched, unless --count-requires-match is given.
+    #[structopt(long = "count", visible_alias = "match-count")]
+    count: bool,
+
+    /// Exit with the "no process matched" status when --count finds nothing.
+    #[structopt(long = "count-requires-match", requires = "count")]
+    count_requires_match: bool,
+
+    /// Only print how many processes each pattern matches on STDOUT, without signaling anything.
+    /// Patterns that match nothing are listed too, with a count of 0.
+    #[structopt(long = "stats", raw(conflicts_with_all = r#"&["count", "pid", "watch"]"#))]
+    stats: bool,
+
+    /// Don't warn about patterns that did not match any process.
+    #[structopt(long = "no-unmatched-warning")]
+    no_unmatched_warning: bool,
+
     /// Show more verbose output.
     #[structopt(short = "v", long = "verbose", overrides_with = "quiet")]
     verbose: bool,
 
-    /// Don't render any output.
-    #[structopt(short = "q", long = "quiet", overrides_with = "verbose")]
+    /// Don't render any output, not even errors. Only the exit status tells how the run went.
+    #[structopt(
+        short = "q",
+        long = "quiet",
+        visible_alias = "silent",
+        overrides_with = "verbose"
+    )]
     quiet: bool,
 
-    /// Show color in command output. "auto" will enable color if output is sent to a TTY.
+    /// Start every line of human-readable output with an RFC 3339 timestamp.
+    #[structopt(long = "timestamps")]
+    timestamps: bool,
+
+    /// Show color in command output. "auto" will enable color for STDOUT and STDERR separately,
+    /// depending on if they are sent to a TTY, unless the CLICOLOR_FORCE or NO_COLOR environment
+    /// variables say otherwise. "force" is the same as "always".
     #[structopt(
         long = "color", default_value = "auto", raw(possible_values = "&ColorMode::variants()")
     )]
     color_mode: ColorMode,
 
-    /// List all supported signals and exit.
-    #[structopt(long = "list-signals")]
-    pub list_signals: bool,
-
-    /// Generate completion script for a given shell and output on STDOUT.
+    /// Format of the output. "json" prints one JSON object per line on STDOUT for every action
+    /// taken, and ignores --verbose and --quiet.
     #[structopt(
-        long = "generate-completions",
-        value_name = "SHELL",
-        raw(possible_values = "&Shell::variants()")
+        long = "output",
+        default_value = "human",
+        value_name = "FORMAT",
+        raw(possible_values = "&OutputFormat::variants()")
     )]
-    pub generate_completions: Option<Shell>,
+    output_format: OutputFormat,
+
+    /// Print a single JSON object on STDOUT at the end, summarizing the run. Human-readable output
+    /// is then shown on STDERR only. Cannot be used with --output json.
+    #[structopt(long = "json-summary", conflicts_with = "count")]
+    json_summary: bool,
 }
 
 #[derive(Debug)]
 pub struct Options {
+    pub allow_pid1: bool,
+    pub include_self: bool,
+    pub case_mode: CaseMode,
+    /// Both --case-sensitive and --ignore-case were given. Matching is case-sensitive then.
+    pub conflicting_case_flags: bool,
+    /// GRACEFUL_SHUTDOWN_SIGNAL is set to something that is not a signal.
+    pub invalid_signal_env: Option<String>,
+    pub deadline: Option<Duration>,
+    pub watch: Option<Duration>,
+    pub resend_after: Option<Duration>,
+    pub grace_map: Option<PathBuf>,
+    pub count: bool,
+    pub count_requires_match: bool,
+    pub error_if_none: bool,
+    pub stats: bool,
+    pub unmatched_warning: bool,
     pub dry_run: bool,
-    pub kill: bool,
-    pub kill_signal: Signal,
+    pub compare: Option<PathBuf>,
+    pub escalation: Escalation,
+    pub exact: bool,
+    pub exclude_patterns: Vec<String>,
+    pub exclude_pids: Vec<Pid>,
+    pub exclude_users: Vec<String>,
+    pub fixed_strings: bool,
+    pub glob: bool,
+    pub regex: bool,
+    pub group: Option<String>,
+    pub include_kernel_threads: bool,
+    pub interactive: bool,
+    pub jobs: usize,
+    pub proc_root: PathBuf,
+    pub limit: Option<usize>,
+    pub first_match: bool,
+    pub age_selection: Option<AgeSelection>,
     pub match_mode: MatchMode,
+    pub max_age: Option<Duration>,
+    pub older_than_file: Option<PathBuf>,
+    pub min_age: Option<Duration>,
+    pub stuck_after: Option<Duration>,
     pub output_mode: OutputMode,
+    pub pattern_file: Option<PathBuf>,
+    pub config: Option<PathBuf>,
+    pub log_file: Option<PathBuf>,
+    pub events_socket: Option<PathBuf>,
+    pub pgid: Option<Pid>,
+    pub pids: Vec<Pid>,
+    pub poll_interval: Duration,
+    pub progress_interval: Duration,
+    pub signal_delay: Duration,
+    pub retries: u32,
+    pub sort: Option<SortOrder>,
+    pub process_group: bool,
+    pub signal_threads: bool,
+    pub protect_parent: bool,
+    pub protected_pids: Vec<Pid>,
+    pub sid: Option<Pid>,
+    pub states: Option<Vec<char>>,
+    pub strict: bool,
     pub terminate_signal: Signal,
+    /// --wait-time 0 was given, which means the same as --no-wait but is easy to misread.
+    pub zero_wait_time: bool,
+    pub timestamps: bool,
+    pub json_summary: bool,
+    pub tree: bool,
+    pub freeze_first: bool,
+    pub leaves_first: bool,
+    pub tty: Option<String>,
+    pub cgroup: Option<String>,
+    pub ports: Vec<u16>,
+    pub env: Vec<(String, String)>,
+    /// Colors for messages on STDERR, which is where most output goes.
     pub colors: Colors,
+    /// Colors for output on STDOUT.
+    pub stdout_colors: Colors,
     pub user_mode: UserMode,
-    pub wait_time: Option<Duration>,
+    pub user_id_kind: UserIdKind,
 }
 
 #[derive(Debug)]
 pub enum UserMode {
     Everybody,
     OnlyMe,
-    Only(String),
+    OnlyAny(Vec<String>),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct Colors {
     enabled: bool,
 }
 
 impl From<CliOptions> for Options {
     fn from(cli_options: CliOptions) -> Options {
-        let wait_time = if cli_options.wait_time > 0.0 {
-            Some(duration_from_secs_float(cli_options.wait_time))
+        let wait_time = if cli_options.wait_time > Duration::from_secs(0) && !cli_options.no_wait {
+            Some(cli_options.wait_time)
         } else {
             None
         };
 
-        let user_mode = match (cli_options.user, cli_options.mine) {
-            (Some(name), false) => UserMode::Only(name),
-            (None, true) => UserMode::OnlyMe,
-            (None, false) => UserMode::Everybody,
-            (Some(_), true) => unreachable!("Should not happen because of overrides_with"),
+        let user_mode = match (cli_options.user.is_empty(), cli_options.mine) {
+            (false, false) => UserMode::OnlyAny(cli_options.user),
+            (true, true) => UserMode::OnlyMe,
+            (true, false) => UserMode::Everybody,
+            (false, true) => unreachable!("Should not happen because of overrides_with"),
         };
 
         let match_mode = if cli_options.match_whole {
             MatchMode::Commandline
+        } else if cli_options.match_args {
+            MatchMode::Args
+        } else if cli_options.match_path {
+            MatchMode::ExePath
         } else {
             MatchMode::Basename
         };
 
         let output_mode = match (cli_options.dry_run, cli_options.verbose, cli_options.quiet) {
+            // JSON output has no levels of verbosity.
+            _ if cli_options.output_format.is_json() => OutputMode::Json,
+
             // dry-run implies --verbose. Ignore the --quiet and --verbose flags!
             (true, _, _) => OutputMode::Verbose,
 
@@ -165,23 +796,194 @@ impl From<CliOptions> for Options {
             (false, true, true) => unreachable!("Should not happen due to overrides_with option"),
         };
 
-        let use_color = match cli_options.color_mode {
-            ColorMode::Never => false,
-            ColorMode::Always => true,
-            ColorMode::Auto => termion::is_tty(&::std::io::stdout()),
+        let (terminate_signal, invalid_signal_env) = match terminate_signal_or_env(
+            cli_options.terminate_signal,
+            ::std::env::var(SIGNAL_ENV).ok().as_deref(),
+        ) {
+            Ok(signal) => (signal, None),
+            Err(value) => (default_terminate_signal(), Some(value)),
+        };
+        let zero_wait_time =
+            cli_options.wait_time == Duration::from_secs(0) && cli_options.escalate.is_none();
+        let escalation = match cli_options.escalate {
+            Some(escalation) => escalation,
+            None if cli_options.no_kill => Escalation::new(terminate_signal, wait_time, None),
+            None => Escalation::new(
+                terminate_signal,
+                wait_time,
+                Some(cli_options.kill_signal),
+            ),
         };
+        let terminate_signal = escalation.first_signal();
+
+        let is_json = cli_options.output_format.is_json();
+        let color_mode = cli_options.color_mode;
+        let color_env = ColorEnv::from_env();
 
         Options {
+            allow_pid1: cli_options.allow_pid1,
+            include_self: cli_options.include_self,
+            case_mode: CaseMode::from_flags(cli_options.case_sensitive, cli_options.ignore_case),
+            conflicting_case_flags: cli_options.case_sensitive && cli_options.ignore_case,
+            invalid_signal_env,
+            deadline: cli_options.deadline,
+            watch: cli_options.watch,
+            resend_after: cli_options.resend_after,
+            grace_map: cli_options.grace_map,
+            count: cli_options.count,
+            count_requires_match: cli_options.count_requires_match,
+            error_if_none: cli_options.error_if_none,
+            stats: cli_options.stats,
+            unmatched_warning: !cli_options.no_unmatched_warning,
             dry_run: cli_options.dry_run,
-            kill: !cli_options.no_kill,
-            kill_signal: cli_options.kill_signal,
+            compare: cli_options.compare,
+            escalation,
+            exact: cli_options.exact,
+            exclude_patterns: cli_options.exclude,
+            exclude_pids: cli_options
+                .exclude_pid
+                .into_iter()
+                .map(Pid::from_raw)
+                .collect(),
+            exclude_users: cli_options.exclude_user,
+            fixed_strings: cli_options.fixed_strings,
+            regex: cli_options.regex,
+            glob: cli_options.glob,
+            group: cli_options.group,
+            include_kernel_threads: cli_options.include_kernel_threads,
+            interactive: cli_options.interactive,
+            proc_root: cli_options.proc_root,
+            jobs: cli_options.jobs.unwrap_or_else(|| {
+                ::std::thread::available_parallelism()
+                    .map(usize::from)
+                    .unwrap_or(1)
+            }),
+            limit: cli_options.limit,
+            first_match: cli_options.first_match,
+            age_selection: match (cli_options.oldest_only, cli_options.newest_only) {
+                (true, _) => Some(AgeSelection::Oldest),
+                (false, true) => Some(AgeSelection::Newest),
+                (false, false) => None,
+            },
             match_mode,
+            max_age: cli_options.max_age,
+            older_than_file: cli_options.older_than_file,
+            min_age: cli_options.min_age,
+            stuck_after: cli_options.stuck_after,
             output_mode,
-            terminate_signal: cli_options.terminate_signal,
-            colors: Colors { enabled: use_color },
+            terminate_signal,
+            zero_wait_time,
+            timestamps: cli_options.timestamps,
+            json_summary: cli_options.json_summary,
+            pattern_file: cli_options.pattern_file,
+            config: cli_options.config,
+            log_file: cli_options.log_file,
+            events_socket: cli_options.events_socket,
+            pgid: cli_options.pgid.map(Pid::from_raw),
+            pids: cli_options.pid.into_iter().map(Pid::from_raw).collect(),
+            poll_interval: cli_options.poll_interval,
+            progress_interval: cli_options.progress_interval,
+            signal_delay: cli_options.signal_delay,
+            retries: cli_options.retries,
+            sort: cli_options.sort,
+            process_group: cli_options.process_group,
+            signal_threads: cli_options.signal_threads,
+            protect_parent: cli_options.protect_parent,
+            protected_pids: cli_options.protect.into_iter().map(Pid::from_raw).collect(),
+            sid: cli_options.sid.map(Pid::from_raw),
+            states: cli_options
+                .state
+                .map(|states| states.chars().collect()),
+            strict: cli_options.strict,
+            tree: cli_options.tree,
+            freeze_first: cli_options.freeze_first,
+            leaves_first: cli_options.leaves_first,
+            tty: cli_options
+                .tty
+                .map(|tty| tty.trim_start_matches("/dev/").to_owned()),
+            cgroup: cli_options.cgroup,
+            ports: cli_options.ports,
+            env: cli_options.env,
+            colors: Colors {
+                enabled: use_color(color_mode, color_env, is_json, termion::is_tty(&::std::io::stderr())),
+            },
+            stdout_colors: Colors {
+                enabled: use_color(color_mode, color_env, is_json, termion::is_tty(&::std::io::stdout())),
+            },
             user_mode,
-            wait_time,
+            user_id_kind: if cli_options.real_user {
+                UserIdKind::Real
+            } else {
+                UserIdKind::Effective
+            },
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum OptionsError {
+    #[error("Poll interval of {0}ms is longer than the total waiting time of {1}ms")]
+    PollIntervalTooLong(u64, u64),
+    #[error("{0} cannot be caught by processes, so they will not get a chance to shut down gracefully")]
+    UncatchableSignal(Signal),
+    #[error("{0} is not guaranteed to kill processes. Use KILL instead")]
+    WeakKillSignal(Signal),
+    #[error("Resending after {0}ms is not within the waiting time of {1}ms")]
+    ResendAfterTooLong(u64, u64),
+    #[error("GRACEFUL_SHUTDOWN_SIGNAL is set to \"{0}\", which is not a signal")]
+    InvalidSignalEnv(String),
+    #[error("--json-summary cannot be used with --output json, as both write JSON to STDOUT")]
+    JsonSummaryWithJsonOutput,
+}
+
+impl Options {
+    /// The signal that is meant to kill the processes still alive at the end, if it might not.
+    /// Without a final escalation step, nothing is meant to be killed.
+    pub fn weak_kill_signal(&self) -> Option<Signal> {
+        let steps = self.escalation.steps();
+        match steps.last() {
+            Some(step) if steps.len() > 1 && !step.signal.is_forceful() => Some(step.signal),
+            _ => None,
+        }
+    }
+
+    /// Checks for combinations of options that cannot be used together.
+    pub fn validate(&self) -> Result<(), OptionsError> {
+        if let Some(ref value) = self.invalid_signal_env {
+            return Err(OptionsError::InvalidSignalEnv(value.clone()));
+        }
+
+        if self.json_summary && matches!(self.output_mode, OutputMode::Json) {
+            return Err(OptionsError::JsonSummaryWithJsonOutput);
+        }
+
+        let total_wait = self.escalation.total_wait();
+        if total_wait > Duration::from_secs(0) && self.poll_interval > total_wait {
+            return Err(OptionsError::PollIntervalTooLong(
+                duration_as_millis(self.poll_interval),
+                duration_as_millis(total_wait),
+            ));
+        }
+
+        if let Some(resend_after) = self.resend_after {
+            let first_wait = self.escalation.steps()[0].wait;
+            if resend_after >= first_wait {
+                return Err(OptionsError::ResendAfterTooLong(
+                    duration_as_millis(resend_after),
+                    duration_as_millis(first_wait),
+                ));
+            }
+        }
+
+        if self.strict && !self.terminate_signal.is_catchable() {
+            return Err(OptionsError::UncatchableSignal(self.terminate_signal));
+        }
+
+        if let (true, Some(signal)) = (self.strict, self.weak_kill_signal()) {
+            return Err(OptionsError::WeakKillSignal(signal));
         }
+
+        Ok(())
     }
 }
 
@@ -189,21 +991,158 @@ impl OutputMode {
     pub fn show_normal(self) -> bool {
         match self {
             OutputMode::Verbose | OutputMode::Normal => true,
-            OutputMode::Quiet => false,
+            OutputMode::Quiet | OutputMode::Json => false,
         }
     }
 
     pub fn show_verbose(self) -> bool {
         match self {
             OutputMode::Verbose => true,
-            OutputMode::Normal | OutputMode::Quiet => false,
+            OutputMode::Normal | OutputMode::Quiet | OutputMode::Json => false,
+        }
+    }
+
+    pub fn show_errors(self) -> bool {
+        match self {
+            OutputMode::Verbose | OutputMode::Normal | OutputMode::Json => true,
+            OutputMode::Quiet => false,
+        }
+    }
+
+    pub fn is_json(self) -> bool {
+        match self {
+            OutputMode::Json => true,
+            OutputMode::Normal | OutputMode::Verbose | OutputMode::Quiet => false,
+        }
+    }
+}
+
+impl OutputFormat {
+    fn variants() -> [&'static str; 2] {
+        ["human", "json"]
+    }
+
+    pub fn is_json(self) -> bool {
+        match self {
+            OutputFormat::Json => true,
+            OutputFormat::Human => false,
+        }
+    }
+}
+
+impl ::std::str::FromStr for OutputFormat {
+    type Err = &'static str;
+
+    fn from_str(string: &str) -> Result<OutputFormat, Self::Err> {
+        match string {
+            "human" => Ok(OutputFormat::Human),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err("Not a valid output format"),
+        }
+    }
+}
+
+impl CaseMode {
+    fn from_flags(case_sensitive: bool, ignore_case: bool) -> CaseMode {
+        if case_sensitive {
+            CaseMode::Sensitive
+        } else if ignore_case {
+            CaseMode::Insensitive
+        } else {
+            CaseMode::Default
+        }
+    }
+
+    pub fn is_case_sensitive(self) -> bool {
+        self == CaseMode::Sensitive
+    }
+}
+
+impl SortOrder {
+    fn variants() -> [&'static str; 4] {
+        ["pid", "name", "age", "startup"]
+    }
+}
+
+impl ::std::str::FromStr for SortOrder {
+    type Err = &'static str;
+
+    fn from_str(string: &str) -> Result<SortOrder, Self::Err> {
+        match string {
+            "pid" => Ok(SortOrder::Pid),
+            "name" => Ok(SortOrder::Name),
+            "age" => Ok(SortOrder::Age),
+            "startup" => Ok(SortOrder::Startup),
+            _ => Err("Not a valid sort order"),
+        }
+    }
+}
+
+impl CompletionShells {
+    fn variants() -> [&'static str; 6] {
+        let shells = Shell::variants();
+        ["all", shells[0], shells[1], shells[2], shells[3], shells[4]]
+    }
+
+    pub fn shells(self) -> Vec<Shell> {
+        match self {
+            CompletionShells::One(shell) => vec![shell],
+            CompletionShells::All => Shell::variants()
+                .iter()
+                .map(|name| name.parse().expect("clap lists a shell it cannot parse"))
+                .collect(),
+        }
+    }
+}
+
+impl ::std::str::FromStr for CompletionShells {
+    type Err = String;
+
+    fn from_str(string: &str) -> Result<CompletionShells, Self::Err> {
+        match string {
+            "all" => Ok(CompletionShells::All),
+            _ => string.parse().map(CompletionShells::One),
         }
     }
 }
 
 impl ColorMode {
-    fn variants() -> [&'static str; 3] {
-        ["auto", "always", "never"]
+    fn variants() -> [&'static str; 4] {
+        ["auto", "always", "force", "never"]
+    }
+}
+
+/// The environment variables that tools commonly use to decide on colors.
+#[derive(Debug, Clone, Copy, Default)]
+struct ColorEnv {
+    /// CLICOLOR_FORCE is set to something other than "0".
+    force: bool,
+    /// NO_COLOR is set to anything non-empty.
+    no_color: bool,
+}
+
+impl ColorEnv {
+    fn from_env() -> ColorEnv {
+        let is_set = |name: &str, off: &str| {
+            ::std::env::var_os(name).is_some_and(|value| !value.is_empty() && value != off)
+        };
+        ColorEnv {
+            force: is_set("CLICOLOR_FORCE", "0"),
+            no_color: is_set("NO_COLOR", ""),
+        }
+    }
+}
+
+/// Decides if output to a single stream should be colored. An explicit --color wins over the
+/// environment, which wins over checking for a TTY.
+fn use_color(mode: ColorMode, env: ColorEnv, is_json: bool, is_tty: bool) -> bool {
+    match mode {
+        _ if is_json => false,
+        ColorMode::Never => false,
+        ColorMode::Always => true,
+        ColorMode::Auto if env.force => true,
+        ColorMode::Auto if env.no_color => false,
+        ColorMode::Auto => is_tty,
     }
 }
 
@@ -213,7 +1152,7 @@ impl ::std::str::FromStr for ColorMode {
     fn from_str(string: &str) -> Result<ColorMode, Self::Err> {
         match string {
             "auto" => Ok(ColorMode::Auto),
-            "always" => Ok(ColorMode::Always),
+            "always" | "force" => Ok(ColorMode::Always),
             "never" => Ok(ColorMode::Never),
             _ => Err("Not a valid color mode"),
         }
@@ -249,6 +1188,14 @@ impl Colors {
         }
     }
 
+    pub fn magenta(&self) -> String {
+        if self.enabled {
+            termion::color::Fg(termion::color::Magenta).to_string()
+        } else {
+            String::new()
+        }
+    }
+
     pub fn green(&self) -> String {
         if self.enabled {
             termion::color::Fg(termion::color::Green).to_string()
@@ -266,14 +1213,570 @@ impl Colors {
     }
 }
 
+/// The environment variable that sets the terminate signal when --terminate-signal is not given.
+const SIGNAL_ENV: &str = "GRACEFUL_SHUTDOWN_SIGNAL";
+
+fn default_terminate_signal() -> Signal {
+    "term".parse().expect("TERM is always a signal")
+}
+
+/// Picks the terminate signal from the flag, then from the value of the environment variable,
+/// then the default. An unset or empty variable counts as not given. A value that is not a signal
+/// is returned as the error.
+fn terminate_signal_or_env(flag: Option<Signal>, env: Option<&str>) -> Result<Signal, String> {
+    match (flag, env) {
+        (Some(signal), _) => Ok(signal),
+        (None, Some(value)) if !value.is_empty() => value.parse().map_err(|_| value.to_owned()),
+        (None, _) => Ok(default_terminate_signal()),
+    }
+}
+
 fn parse_signal(sig: &str) -> Result<Signal, String> {
     sig.parse()
         .map_err(|_| format!("Failed to parse \"{}\" as a signal name.", sig))
 }
 
+fn parse_seconds(seconds: &str) -> Result<Duration, String> {
+    parse_duration(seconds, 1.0).ok_or_else(|| {
+        format!(
+            "\"{}\" is not a valid duration. Use a number of seconds, or add a unit like 500ms, 30s, 2m or 1h.",
+            seconds
+        )
+    })
+}
+
+fn parse_env_var(var: &str) -> Result<(String, String), String> {
+    match var.find('=') {
+        Some(index) if index > 0 => Ok((var[..index].to_owned(), var[index + 1..].to_owned())),
+        _ => Err(format!("\"{}\" is not formatted like KEY=VALUE.", var)),
+    }
+}
+
+fn parse_jobs(jobs: &str) -> Result<usize, String> {
+    match jobs.parse() {
+        Ok(0) => Err(String::from("Number of jobs must be greater than zero.")),
+        Ok(jobs) => Ok(jobs),
+        Err(_) => Err(format!("\"{}\" is not a valid number of jobs.", jobs)),
+    }
+}
+
+fn parse_poll_interval(millis: &str) -> Result<Duration, String> {
+    match parse_millis(millis)? {
+        interval if interval == Duration::from_secs(0) => {
+            Err(String::from("Poll interval must be greater than zero."))
+        }
+        interval => Ok(interval),
+    }
+}
+
+fn parse_millis(millis: &str) -> Result<Duration, String> {
+    parse_duration(millis, 0.001).ok_or_else(|| {
+        format!(
+            "\"{}\" is not a valid duration. Use a number of milliseconds, or add a unit like 500ms, 30s, 2m or 1h.",
+            millis
+        )
+    })
+}
+
+fn parse_escalation(spec: &str) -> Result<Escalation, String> {
+    spec.parse()
+        .map_err(|err| format!("Failed to parse \"{}\" as an escalation: {}", spec, err))
+}
+
+/// Parses a duration with a unit, like "500ms", "30s", "2m" or "1h". Numbers without a unit are
+/// taken to be in the unit given as a number of seconds, like 0.001 for milliseconds.
+pub fn parse_duration(text: &str, bare_unit: f64) -> Option<Duration> {
+    let text = text.trim();
+    let (number, unit) = match text.parse::<f64>() {
+        Ok(number) => (number, bare_unit),
+        Err(_) => {
+            let split = text.find(|c: char| c.is_ascii_alphabetic())?;
+            let unit = match &text[split..] {
+                "ms" => 0.001,
+                "s" => 1.0,
+                "m" => 60.0,
+                "h" => 3600.0,
+                _ => return None,
+            };
+            (text[..split].trim_end().parse::<f64>().ok()?, unit)
+        }
+    };
+
+    let seconds = number * unit;
+    if seconds >= 0.0 && seconds.is_finite() {
+        Some(duration_from_secs_float(seconds))
+    } else {
+        None
+    }
+}
+
 fn duration_from_secs_float(float: f64) -> Duration {
     let whole_seconds = float.floor();
     let sec_frac = float - whole_seconds;
     let nanos = (sec_frac * 1e9).round();
     Duration::new(whole_seconds as u64, nanos as u32)
 }
+
+fn duration_as_millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + u64::from(duration.subsec_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use structopt::StructOpt;
+
+    fn options(args: &[&str]) -> Options {
+        let mut all_args = vec!["graceful-shutdown"];
+        all_args.extend_from_slice(args);
+        Options::from(CliOptions::from_iter(all_args))
+    }
+
+    #[test]
+    fn it_accepts_multiple_users() {
+        let expected = vec![String::from("www"), String::from("db"), String::from("cache")];
+
+        match options(&["--user", "www,db", "-u", "cache"]).user_mode {
+            UserMode::OnlyAny(ref names) => assert_eq!(names, &expected),
+            ref other => panic!("Unexpected user mode {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_matches_users_by_effective_user_id_by_default() {
+        assert_eq!(options(&[]).user_id_kind, UserIdKind::Effective);
+        assert_eq!(options(&["--real-user"]).user_id_kind, UserIdKind::Real);
+    }
+
+    #[test]
+    fn it_never_kills_stuck_processes_without_a_kill_signal() {
+        assert_eq!(
+            options(&["--stuck-after", "3600"]).stuck_after,
+            Some(Duration::from_secs(3600))
+        );
+        for conflicting in &["--no-kill", "--no-wait"] {
+            let result = CliOptions::from_iter_safe(&[
+                "graceful-shutdown",
+                "--stuck-after",
+                "3600",
+                conflicting,
+            ]);
+            assert!(result.is_err());
+        }
+    }
+
+    #[test]
+    fn it_parses_environment_variables() {
+        assert_eq!(
+            options(&["--env", "ROLE=flush", "--env", "OPTS=a=b"]).env,
+            vec![
+                (String::from("ROLE"), String::from("flush")),
+                (String::from("OPTS"), String::from("a=b")),
+            ]
+        );
+        assert!(CliOptions::from_iter_safe(&["graceful-shutdown", "--env", "ROLE"]).is_err());
+        assert!(CliOptions::from_iter_safe(&["graceful-shutdown", "--env", "=flush"]).is_err());
+    }
+
+    #[test]
+    fn it_hides_even_errors_when_quiet() {
+        for flag in &["-q", "--quiet", "--silent"] {
+            let mode = options(&[flag]).output_mode;
+            assert!(!mode.show_normal());
+            assert!(!mode.show_errors());
+        }
+        assert!(options(&[]).output_mode.show_errors());
+    }
+
+    #[test]
+    fn it_passes_the_poll_interval() {
+        assert_eq!(options(&[]).poll_interval, Duration::from_millis(500));
+        assert_eq!(
+            options(&["--poll-interval", "250"]).poll_interval,
+            Duration::from_millis(250)
+        );
+    }
+
+    #[test]
+    fn it_passes_the_progress_interval() {
+        assert_eq!(options(&[]).progress_interval, Duration::from_secs(5));
+        assert_eq!(
+            options(&["--progress-interval", "0.5"]).progress_interval,
+            Duration::from_millis(500)
+        );
+    }
+
+    #[test]
+    fn it_passes_the_signal_delay() {
+        assert_eq!(options(&[]).signal_delay, Duration::from_millis(0));
+        assert_eq!(
+            options(&["--signal-delay", "20"]).signal_delay,
+            Duration::from_millis(20)
+        );
+
+        assert_eq!(
+            options(&["--signal-delay", "1s"]).signal_delay,
+            Duration::from_secs(1)
+        );
+
+        let result = CliOptions::from_iter_safe(&["graceful-shutdown", "--signal-delay", "soon"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn it_parses_durations_with_units() {
+        let seconds = |text| parse_duration(text, 1.0);
+        assert_eq!(seconds("500ms"), Some(Duration::from_millis(500)));
+        assert_eq!(seconds("30s"), Some(Duration::from_secs(30)));
+        assert_eq!(seconds("2m"), Some(Duration::from_secs(120)));
+        assert_eq!(seconds("1h"), Some(Duration::from_secs(3600)));
+        assert_eq!(seconds("1.5m"), Some(Duration::from_secs(90)));
+        assert_eq!(seconds("0.25"), Some(Duration::from_millis(250)));
+        assert_eq!(seconds("10 s"), Some(Duration::from_secs(10)));
+
+        // Numbers without a unit are in the unit of the option.
+        assert_eq!(parse_duration("250", 0.001), Some(Duration::from_millis(250)));
+        assert_eq!(parse_duration("2s", 0.001), Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn it_rejects_invalid_durations() {
+        for text in &["", "ms", "5 minutes", "5d", "5MS", "-1s", "-1", "inf", "NaN", "1e400"] {
+            assert_eq!(parse_duration(text, 1.0), None, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn it_accepts_durations_for_the_waiting_time() {
+        assert_eq!(options(&[]).escalation.total_wait(), Duration::from_secs(5));
+        assert_eq!(
+            options(&["--wait-time", "2m"]).escalation.total_wait(),
+            Duration::from_secs(120)
+        );
+        assert_eq!(
+            options(&["--wait-time", "1.5"]).escalation.total_wait(),
+            Duration::from_millis(1500)
+        );
+        assert!(options(&["--wait-time", "0ms"]).zero_wait_time);
+
+        let result = CliOptions::from_iter_safe(&["graceful-shutdown", "--wait-time", "2y"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn it_passes_the_number_of_retries() {
+        assert_eq!(options(&[]).retries, 2);
+        assert_eq!(options(&["--retries", "0"]).retries, 0);
+    }
+
+    #[test]
+    fn it_decides_colors_per_stream() {
+        let env = ColorEnv::default();
+
+        // Piping STDOUT keeps colors on a STDERR that is still a terminal, and the other way around.
+        assert!(use_color(ColorMode::Auto, env, false, true));
+        assert!(!use_color(ColorMode::Auto, env, false, false));
+
+        assert!(use_color(ColorMode::Always, env, false, false));
+        assert!(!use_color(ColorMode::Never, env, false, true));
+        assert!(!use_color(ColorMode::Always, env, true, true));
+    }
+
+    #[test]
+    fn it_respects_color_environment_variables() {
+        let force = ColorEnv {
+            force: true,
+            no_color: false,
+        };
+        let no_color = ColorEnv {
+            force: false,
+            no_color: true,
+        };
+        let both = ColorEnv {
+            force: true,
+            no_color: true,
+        };
+
+        assert!(use_color(ColorMode::Auto, force, false, false));
+        assert!(!use_color(ColorMode::Auto, no_color, false, true));
+        assert!(use_color(ColorMode::Auto, both, false, false));
+
+        // An explicit --color wins.
+        assert!(!use_color(ColorMode::Never, force, false, true));
+        assert!(use_color(ColorMode::Always, no_color, false, false));
+
+        // JSON is never colored.
+        assert!(!use_color(ColorMode::Auto, force, true, true));
+    }
+
+    #[test]
+    fn it_renders_magenta_only_when_enabled() {
+        assert_eq!(Colors { enabled: true }.magenta(), "\x1b[38;5;5m");
+        assert_eq!(Colors { enabled: false }.magenta(), "");
+    }
+
+    #[test]
+    fn it_accepts_force_as_an_alias_for_always() {
+        let options = options(&["--color", "force"]);
+        assert!(options.colors.enabled);
+        assert!(options.stdout_colors.enabled);
+    }
+
+    #[test]
+    fn it_only_signals_once_without_waiting() {
+        let no_wait = options(&["--no-wait"]);
+        assert_eq!(no_wait.escalation.steps().len(), 1);
+        assert_eq!(no_wait.escalation.total_wait(), Duration::from_secs(0));
+        assert!(!no_wait.zero_wait_time);
+
+        let zero = options(&["--wait-time", "0"]);
+        assert_eq!(zero.escalation.steps().len(), 1);
+        assert_eq!(zero.escalation.total_wait(), Duration::from_secs(0));
+        assert!(zero.zero_wait_time);
+
+        assert!(!options(&[]).zero_wait_time);
+
+        let result = CliOptions::from_iter_safe(&["graceful-shutdown", "--no-wait", "-w", "3"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn it_rejects_resending_outside_the_waiting_time() {
+        assert!(options(&["--resend-after", "2", "-w", "5"]).validate().is_ok());
+
+        match options(&["--resend-after", "5", "-w", "5"]).validate() {
+            Err(OptionsError::ResendAfterTooLong(5000, 5000)) => {}
+            other => panic!("Expected resend to be too late, got {:?}", other),
+        }
+        match options(&["--resend-after", "1", "--no-wait"]).validate() {
+            Err(OptionsError::ResendAfterTooLong(1000, 0)) => {}
+            other => panic!("Expected resend to be too late, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_passes_the_case_mode() {
+        assert_eq!(options(&[]).case_mode, CaseMode::Default);
+        assert_eq!(options(&["-I"]).case_mode, CaseMode::Insensitive);
+        assert_eq!(options(&["--case-insensitive"]).case_mode, CaseMode::Insensitive);
+        assert_eq!(options(&["--case-sensitive"]).case_mode, CaseMode::Sensitive);
+        assert!(!options(&["--case-sensitive"]).conflicting_case_flags);
+
+        let conflicting = options(&["--ignore-case", "--case-sensitive"]);
+        assert_eq!(conflicting.case_mode, CaseMode::Sensitive);
+        assert!(conflicting.conflicting_case_flags);
+    }
+
+    #[test]
+    fn it_passes_the_match_mode() {
+        assert_eq!(options(&[]).match_mode, MatchMode::Basename);
+        assert_eq!(options(&["-W"]).match_mode, MatchMode::Commandline);
+        assert_eq!(options(&["--match-args"]).match_mode, MatchMode::Args);
+        assert_eq!(options(&["--match-path"]).match_mode, MatchMode::ExePath);
+
+        let result = CliOptions::from_iter_safe(&["graceful-shutdown", "--match-path", "-W"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn it_only_takes_the_first_match_without_the_tree() {
+        assert!(!options(&[]).first_match);
+        assert!(options(&["--first-match"]).first_match);
+
+        let result =
+            CliOptions::from_iter_safe(&["graceful-shutdown", "--first-match", "--tree"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn it_selects_the_oldest_or_newest_process() {
+        assert_eq!(options(&[]).age_selection, None);
+        assert_eq!(
+            options(&["--oldest-only"]).age_selection,
+            Some(AgeSelection::Oldest)
+        );
+        assert_eq!(
+            options(&["--newest-only"]).age_selection,
+            Some(AgeSelection::Newest)
+        );
+
+        for conflicting in &[&["--oldest-only", "--newest-only"], &["--newest-only", "--tree"]] {
+            let mut args = vec!["graceful-shutdown"];
+            args.extend_from_slice(*conflicting);
+            assert!(CliOptions::from_iter_safe(args).is_err());
+        }
+    }
+
+    #[test]
+    fn it_passes_the_sort_order() {
+        assert_eq!(options(&[]).sort, None);
+        assert_eq!(options(&["--sort", "pid"]).sort, Some(SortOrder::Pid));
+        assert_eq!(options(&["--sort", "name"]).sort, Some(SortOrder::Name));
+        assert_eq!(options(&["--sort", "age"]).sort, Some(SortOrder::Age));
+        assert_eq!(options(&["--sort", "startup"]).sort, Some(SortOrder::Startup));
+
+        let result = CliOptions::from_iter_safe(&["graceful-shutdown", "--sort", "size"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn it_rejects_zero_poll_intervals() {
+        let result = CliOptions::from_iter_safe(&["graceful-shutdown", "--poll-interval", "0"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn it_rejects_poll_intervals_longer_than_the_waiting_time() {
+        assert!(options(&["--poll-interval", "5000"]).validate().is_ok());
+        assert!(options(&["--poll-interval", "5001"]).validate().is_err());
+        assert!(options(&["--poll-interval", "500", "-w", "0.5"]).validate().is_ok());
+        assert!(options(&["--poll-interval", "600", "-w", "0.5"]).validate().is_err());
+
+        // Without any waiting, polling never happens.
+        assert!(options(&["--poll-interval", "5001", "-w", "0"]).validate().is_ok());
+    }
+
+    #[test]
+    fn it_reads_the_terminate_signal_from_the_environment() {
+        let hup: Signal = "hup".parse().unwrap();
+        let int: Signal = "int".parse().unwrap();
+        let term: Signal = "term".parse().unwrap();
+
+        assert_eq!(terminate_signal_or_env(None, Some("sighup")), Ok(hup));
+        assert_eq!(terminate_signal_or_env(Some(int), Some("sighup")), Ok(int));
+        assert_eq!(terminate_signal_or_env(None, Some("")), Ok(term));
+        assert_eq!(terminate_signal_or_env(None, None), Ok(term));
+        assert_eq!(
+            terminate_signal_or_env(None, Some("nope")),
+            Err(String::from("nope"))
+        );
+        assert_eq!(terminate_signal_or_env(Some(int), Some("nope")), Ok(int));
+    }
+
+    #[test]
+    fn it_rejects_json_summaries_next_to_json_output() {
+        assert!(options(&["--json-summary"]).validate().is_ok());
+        assert!(options(&["--output", "json"]).validate().is_ok());
+        match options(&["--json-summary", "--output", "json"]).validate() {
+            Err(OptionsError::JsonSummaryWithJsonOutput) => {}
+            other => panic!("Expected a conflict, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_rejects_uncatchable_signals_when_strict() {
+        assert!(options(&["-s", "kill"]).validate().is_ok());
+        assert!(options(&["-s", "kill", "--strict"]).validate().is_err());
+        assert!(options(&["--escalate", "stop,kill", "--strict"]).validate().is_err());
+        assert!(options(&["-s", "int", "--strict"]).validate().is_ok());
+    }
+
+    #[test]
+    fn it_finds_kill_signals_that_might_not_kill() {
+        let weak = |args: &[&str]| options(args).weak_kill_signal().map(|s| s.to_string());
+
+        assert_eq!(weak(&[]), None);
+        assert_eq!(weak(&["--kill-signal", "9"]), None);
+        assert_eq!(weak(&["--kill-signal", "hup"]), Some(String::from("HUP")));
+        assert_eq!(weak(&["--kill-signal", "int"]), Some(String::from("INT")));
+        assert_eq!(weak(&["--kill-signal", "stop"]), Some(String::from("STOP")));
+        assert_eq!(weak(&["--escalate", "hup:2,term"]), Some(String::from("TERM")));
+        assert_eq!(weak(&["--escalate", "hup:2,kill"]), None);
+
+        // Nothing is killed with only one step.
+        assert_eq!(weak(&["--kill-signal", "hup", "--no-kill"]), None);
+        assert_eq!(weak(&["--escalate", "term:5"]), None);
+
+        assert!(options(&["--kill-signal", "hup"]).validate().is_ok());
+        match options(&["--kill-signal", "hup", "--strict"]).validate() {
+            Err(OptionsError::WeakKillSignal(_)) => {}
+            other => panic!("Expected the kill signal to be rejected, got {:?}", other),
+        }
+    }
+
+    fn command(args: &[&str]) -> CliCommand {
+        let mut all_args = vec!["graceful-shutdown"];
+        all_args.extend_from_slice(args);
+        CliCommand::from_args_with_default(all_args).expect("Invalid command in test")
+    }
+
+    #[test]
+    fn it_parses_the_run_subcommand() {
+        match command(&["run", "-n", "--pid", "42"]) {
+            CliCommand::Run(cli_options) => {
+                assert!(cli_options.dry_run);
+                assert_eq!(cli_options.pid, vec![42]);
+            }
+            other => panic!("Expected the run subcommand, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_runs_without_a_subcommand() {
+        match command(&[]) {
+            CliCommand::Run(_) => {}
+            other => panic!("Expected the run subcommand, got {:?}", other),
+        }
+        match command(&["-n", "--pid", "42"]) {
+            CliCommand::Run(cli_options) => assert_eq!(cli_options.pid, vec![42]),
+            other => panic!("Expected the run subcommand, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_parses_the_list_signals_subcommand() {
+        match command(&["list-signals", "--output", "json"]) {
+            CliCommand::ListSignals { output_format } => assert!(output_format.is_json()),
+            other => panic!("Expected the list-signals subcommand, got {:?}", other),
+        }
+        match command(&["--list-signals"]) {
+            CliCommand::ListSignals { output_format } => assert!(!output_format.is_json()),
+            other => panic!("Expected the list-signals subcommand, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_parses_the_completions_subcommand() {
+        match command(&["completions", "zsh"]) {
+            CliCommand::Completions {
+                shell: CompletionShells::One(Shell::Zsh),
+                output_dir: None,
+            } => {}
+            other => panic!("Expected zsh completions, got {:?}", other),
+        }
+        match command(&["--generate-completions", "fish"]) {
+            CliCommand::Completions {
+                shell: CompletionShells::One(Shell::Fish),
+                ..
+            } => {}
+            other => panic!("Expected fish completions, got {:?}", other),
+        }
+        match command(&["completions", "all", "--output-dir", "completions"]) {
+            CliCommand::Completions {
+                shell: CompletionShells::All,
+                output_dir: Some(ref dir),
+            } if dir == Path::new("completions") => {}
+            other => panic!("Expected completions for all shells, got {:?}", other),
+        }
+        assert!(CliCommand::from_args_with_default(&["graceful-shutdown", "completions"]).is_err());
+        assert!(
+            CliCommand::from_args_with_default(&["graceful-shutdown", "completions", "all"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn it_parses_the_man_subcommand() {
+        match command(&["man"]) {
+            CliCommand::Man { output_file: None } => {}
+            other => panic!("Expected the man subcommand, got {:?}", other),
+        }
+        match command(&["man", "--output-file", "graceful-shutdown.1"]) {
+            CliCommand::Man {
+                output_file: Some(ref path),
+            } if path == Path::new("graceful-shutdown.1") => {}
+            other => panic!("Expected the man subcommand, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use libc;
+use nix;
+use nix::errno::Errno;
+use nix::poll::{poll, EventFlags, PollFd};
+use nix::unistd::Pid;
+use signal::Signal;
+use std::os::unix::io::RawFd;
+use std::ptr;
+use std::time::Duration;
+
+/// A file descriptor referring to a process, as returned by `pidfd_open(2)`.
+///
+/// Unlike a PID, a pidfd can never start referring to another process if the original process
+/// exits and its PID is reused. It also becomes readable when the process exits, so it can be
+/// waited on without polling `/proc`.
+#[derive(Debug)]
+pub struct PidFd(RawFd);
+
+impl PidFd {
+    pub fn open(pid: Pid) -> nix::Result<PidFd> {
+        let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, libc::pid_t::from(pid), 0) };
+        Errno::result(fd).map(|fd| PidFd(fd as RawFd))
+    }
+
+    /// Checks if the running kernel supports pidfds (Linux 5.3 and later).
+    pub fn is_supported() -> bool {
+        PidFd::open(Pid::this()).is_ok()
+    }
+
+    pub fn send(&self, signal: Signal) -> nix::Result<()> {
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_pidfd_send_signal,
+                self.0,
+                signal.number(),
+                ptr::null::<libc::siginfo_t>(),
+                0,
+            )
+        };
+        Errno::result(result).map(drop)
+    }
+
+    pub fn has_exited(&self) -> bool {
+        let mut fds = [PollFd::new(self.0, EventFlags::POLLIN)];
+        match poll(&mut fds, 0) {
+            Ok(ready) => ready > 0,
+            Err(_) => false,
+        }
+    }
+}
+
+impl Drop for PidFd {
+    fn drop(&mut self) {
+        let _ = nix::unistd::close(self.0);
+    }
+}
+
+/// Blocks until any of the processes exits, or the timeout runs out.
+pub fn wait_any(pidfds: &[&PidFd], timeout: Duration) -> nix::Result<()> {
+    let mut fds: Vec<PollFd> = pidfds
+        .iter()
+        .map(|pidfd| PollFd::new(pidfd.0, EventFlags::POLLIN))
+        .collect();
+    let millis = timeout.as_secs() * 1000 + u64::from(timeout.subsec_millis());
+    let millis = millis.min(libc::c_int::MAX as u64) as libc::c_int;
+
+    match poll(&mut fds, millis) {
+        Ok(_) | Err(nix::Error::Sys(Errno::EINTR)) => Ok(()),
+        Err(error) => Err(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn it_waits_for_processes_to_exit() {
+        if !PidFd::is_supported() {
+            return;
+        }
+
+        let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+        let pidfd = PidFd::open(Pid::from_raw(child.id() as i32)).unwrap();
+        assert!(!pidfd.has_exited());
+
+        pidfd.send("kill".parse().unwrap()).unwrap();
+        wait_any(&[&pidfd], Duration::from_secs(5)).unwrap();
+        assert!(pidfd.has_exited());
+
+        child.wait().unwrap();
+    }
+}
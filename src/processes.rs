@@ -1,22 +1,75 @@
 extern crate users;
 
-use failure::Error;
-use nix::sys::signal::kill;
 use nix::unistd::Pid;
+use pidfd::PidFd;
 use signal::Signal;
-use std::fs::{read_dir, DirEntry, File, ReadDir};
+use std::collections::HashSet;
+use std::fs::{read, read_dir, read_link, read_to_string, DirEntry, File, ReadDir};
+use std::io;
 use std::io::Read;
 use std::path::{Path, PathBuf};
-use users::uid_t;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use users::{gid_t, uid_t};
 
-pub type ProcIter = Box<Iterator<Item = Result<Process, String>>>;
+pub type ProcIter = Box<Iterator<Item = Result<Process, LoadError>>>;
+
+/// Where the proc filesystem is normally mounted.
+pub const DEFAULT_PROC_ROOT: &str = "/proc";
 
 #[derive(Debug)]
 pub struct Process {
     pid: Pid,
-    user_id: uid_t,
+    /// The directory of the process in the proc filesystem.
+    path: PathBuf,
+    uids: Uids,
+    group_id: gid_t,
     name: String,
     cmdline: String,
+    args: Vec<String>,
+    exe: Option<PathBuf>,
+    start_time: u64,
+    state: char,
+    tty: Option<String>,
+    cgroups: Vec<String>,
+    parent_pid: Pid,
+    pgid: Pid,
+    sid: Pid,
+    is_kernel_thread: bool,
+    signal_process_group: bool,
+    pidfd: Option<PidFd>,
+    signaled_at: Option<Instant>,
+    grace_period: Option<Duration>,
+    is_frozen: bool,
+}
+
+/// The user IDs of a process. See credentials(7) for what each of them means.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Uids {
+    pub real: uid_t,
+    pub effective: uid_t,
+    pub saved: uid_t,
+    pub filesystem: uid_t,
+}
+
+impl Uids {
+    fn all(uid: uid_t) -> Uids {
+        Uids {
+            real: uid,
+            effective: uid,
+            saved: uid,
+            filesystem: uid,
+        }
+    }
+}
+
+/// Which of the user IDs of a process decides who owns it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserIdKind {
+    /// The user the process is running as, like ps shows it.
+    Effective,
+    /// The user that started the process. Differs for setuid programs.
+    Real,
 }
 
 pub struct ProcessIterator {
@@ -24,8 +77,9 @@ pub struct ProcessIterator {
 }
 
 pub struct UserFilter {
-    user: uid_t,
-    process_iter: ProcessIterator,
+    users: Vec<uid_t>,
+    kind: UserIdKind,
+    process_iter: ProcIter,
 }
 
 fn is_dir(entry: &DirEntry) -> bool {
@@ -41,16 +95,15 @@ fn has_numeric_name(entry: &DirEntry) -> bool {
 }
 
 impl ProcessIterator {
-    fn new() -> Result<ProcessIterator, Error> {
+    fn new(proc_root: &Path) -> Result<ProcessIterator, ScanError> {
         Ok(ProcessIterator {
-            read_dir: read_dir("/proc")
-                .map_err(|err| format_err!("Failed to open /proc: {}", err))?,
+            read_dir: open_proc_root(proc_root)?,
         })
     }
 }
 
 impl Iterator for ProcessIterator {
-    type Item = Result<Process, String>;
+    type Item = Result<Process, LoadError>;
 
     fn next(&mut self) -> Option<Self::Item> {
         // Read next dir entry. If it's not a directory, then skip to the next one again.
@@ -70,12 +123,12 @@ impl Iterator for ProcessIterator {
 }
 
 impl Iterator for UserFilter {
-    type Item = Result<Process, String>;
+    type Item = Result<Process, LoadError>;
 
     fn next(&mut self) -> Option<Self::Item> {
         match self.process_iter.next() {
             Some(Ok(process)) => {
-                if process.user_id == self.user {
+                if self.users.contains(&process.user_id_of(self.kind)) {
                     Some(Ok(process))
                 } else {
                     self.next()
@@ -86,40 +139,288 @@ impl Iterator for UserFilter {
     }
 }
 
+/// Knows how to translate process start times, which are in clock ticks since boot, to wall
+/// clock time.
+#[derive(Debug, Clone, Copy)]
+pub struct Clock {
+    /// Seconds since the Unix epoch when the system booted.
+    boot_time: u64,
+    ticks_per_second: u64,
+}
+
+impl Clock {
+    pub fn system(proc_root: &Path) -> Result<Clock, String> {
+        let boot_time = parse_boot_time(&read_file(&proc_root.join("stat"))?)?;
+        let ticks_per_second = match unsafe { ::libc::sysconf(::libc::_SC_CLK_TCK) } {
+            ticks if ticks > 0 => ticks as u64,
+            _ => return Err(String::from("Could not determine clock ticks per second")),
+        };
+
+        Ok(Clock {
+            boot_time,
+            ticks_per_second,
+        })
+    }
+
+    #[cfg(test)]
+    pub fn fake(boot_time: u64, ticks_per_second: u64) -> Clock {
+        Clock {
+            boot_time,
+            ticks_per_second,
+        }
+    }
+
+    /// The wall clock time of the given clock tick.
+    fn time_of(&self, start_time: u64) -> SystemTime {
+        UNIX_EPOCH
+            + Duration::from_secs(self.boot_time)
+            + Duration::from_secs(start_time / self.ticks_per_second)
+            + Duration::from_nanos(
+                (start_time % self.ticks_per_second) * 1_000_000_000 / self.ticks_per_second,
+            )
+    }
+
+    /// How long a process started at the given clock tick has been running at the given time.
+    fn age_at(&self, start_time: u64, now: SystemTime) -> Duration {
+        // Clock skew could make processes appear to have started in the future.
+        now.duration_since(self.time_of(start_time))
+            .unwrap_or_default()
+    }
+}
+
+/// Loads all processes using the given number of threads. Results are returned in the same order
+/// as the serial ProcessIterator would, once all threads are done.
+fn scan_parallel(proc_root: &Path, jobs: usize) -> Result<ProcIter, ScanError> {
+    let paths: Vec<PathBuf> = open_proc_root(proc_root)?
+        .flat_map(Result::ok)
+        .filter(|entry| is_dir(entry) && has_numeric_name(entry))
+        .map(|entry| entry.path())
+        .collect();
+
+    let chunk_size = paths.len().div_ceil(jobs).max(1);
+    let threads: Vec<_> = paths
+        .chunks(chunk_size)
+        .map(|chunk| {
+            let chunk = chunk.to_vec();
+            thread::spawn(move || {
+                chunk
+                    .iter()
+                    .map(|path| Process::from_path(path))
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect();
+
+    let mut processes = Vec::with_capacity(paths.len());
+    for thread in threads {
+        let results = thread
+            .join()
+            .map_err(|_| ScanError::ThreadCrashed(proc_root.display().to_string()))?;
+        processes.extend(results);
+    }
+
+    Ok(Box::new(processes.into_iter()))
+}
+
 impl Process {
-    pub fn all() -> Result<ProcIter, Error> {
-        ProcessIterator::new().map(|iter| Box::new(iter) as ProcIter)
+    /// Loads every process in the proc filesystem at `proc_root`, usually "/proc".
+    pub fn all(proc_root: &Path, jobs: usize) -> Result<ProcIter, ScanError> {
+        if jobs > 1 {
+            scan_parallel(proc_root, jobs)
+        } else {
+            ProcessIterator::new(proc_root).map(|iter| Box::new(iter) as ProcIter)
+        }
     }
 
-    pub fn all_from_user(user: uid_t) -> Result<ProcIter, Error> {
-        ProcessIterator::new().map(|iter| {
+    pub fn all_from_users(
+        proc_root: &Path,
+        users: Vec<uid_t>,
+        kind: UserIdKind,
+        jobs: usize,
+    ) -> Result<ProcIter, ScanError> {
+        Process::all(proc_root, jobs).map(|iter| {
             Box::new(UserFilter {
-                user,
+                users,
+                kind,
                 process_iter: iter,
             }) as ProcIter
         })
     }
 
-    fn from_entry(entry: &DirEntry) -> Result<Process, String> {
-        let path = entry.path();
-        let name = read_file(&path.join("comm"))?.trim_right().to_string();
-        let cmdline = parse_cmdline(&read_file(&path.join("cmdline"))?);
+    pub fn from_pid(proc_root: &Path, pid: Pid) -> Result<Process, String> {
+        let path = proc_root.join(pid.to_string());
+        if !path.is_dir() {
+            return Err(format!("Could not find process with PID {}", pid));
+        }
+
+        Process::from_path(&path).map_err(|err| match err {
+            LoadError::Gone(_) => format!("Could not find process with PID {}", pid),
+            LoadError::Failed(message) => message,
+        })
+    }
+
+    fn from_entry(entry: &DirEntry) -> Result<Process, LoadError> {
+        Process::from_path(&entry.path())
+    }
+
+    fn from_path(path: &Path) -> Result<Process, LoadError> {
+        Process::load(path, |path| read_to_string(path))
+    }
+
+    /// Loads the process from its directory in /proc, reading the files in it with `read`.
+    fn load<F>(path: &Path, read: F) -> Result<Process, LoadError>
+    where
+        F: Fn(&Path) -> io::Result<String>,
+    {
+        use std::os::linux::fs::MetadataExt;
+
         let pid = {
-            let basename = entry.file_name();
-            let basename = basename.to_string_lossy();
-            basename
-                .parse()
-                .map_err(|e| format!("Failed to parse PID in {}: {}", basename, e))?
+            let basename = path.file_name().unwrap_or_default().to_string_lossy();
+            Pid::from_raw(
+                basename
+                    .parse()
+                    .map_err(|e| format!("Failed to parse PID in {}: {}", basename, e))?,
+            )
         };
+        let read_file = |name: &str| {
+            let path = path.join(name);
+            read(&path).map_err(|err| LoadError::from_io(pid, &path, err))
+        };
+
+        let metadata = path
+            .metadata()
+            .map_err(|err| LoadError::from_io(pid, path, err))?;
+        let stat = parse_stat(&read_file("stat")?)?;
+        let status = read_file("status")
+            .ok()
+            .and_then(|status| parse_status(&status).ok());
+        // The names in status and stat are the same as in comm, so they can be used if comm
+        // cannot be read.
+        let name = match (read_file("comm"), &status) {
+            (Ok(comm), _) => comm.trim_end().to_string(),
+            (Err(_), Some(status)) => status.name.clone(),
+            (Err(_), None) => stat.name.clone(),
+        };
+        let uids = match status {
+            Some(status) => status.uids,
+            // The directory is owned by the effective user, the best guess for all of them.
+            None => Uids::all(metadata.st_uid()),
+        };
+        let is_kernel_thread = stat.flags & PF_KTHREAD != 0;
+        let (cmdline, args) = if is_kernel_thread {
+            // Kernel threads have no commandline. Show them the same way as ps does.
+            (format!("[{}]", name), Vec::new())
+        } else {
+            let raw_cmdline = read_file("cmdline")?;
+            (parse_cmdline(&raw_cmdline), parse_args(&raw_cmdline))
+        };
+        // Only readable for processes of the same user, unless running as root.
+        let exe = read_link(path.join("exe")).ok();
+        // Missing when the kernel is built without cgroups.
+        let cgroups = read_file("cgroup")
+            .map(|cgroup| parse_cgroups(&cgroup))
+            .unwrap_or_default();
 
         Ok(Process {
             name,
             cmdline,
-            pid: Pid::from_raw(pid),
-            user_id: uid_of_file(&path)?,
+            args,
+            exe,
+            pid,
+            path: path.to_owned(),
+            uids,
+            group_id: metadata.st_gid(),
+            start_time: stat.start_time,
+            state: stat.state,
+            tty: tty_name(stat.tty_nr),
+            cgroups,
+            parent_pid: Pid::from_raw(stat.parent_pid),
+            pgid: Pid::from_raw(stat.pgid),
+            sid: Pid::from_raw(stat.sid),
+            is_kernel_thread,
+            signal_process_group: false,
+            pidfd: None,
+            signaled_at: None,
+            grace_period: None,
+            is_frozen: false,
         })
     }
 
+    #[cfg(test)]
+    pub fn fake(pid: i32, name: &str, cmdline: &str) -> Process {
+        Process {
+            pid: Pid::from_raw(pid),
+            path: Path::new(DEFAULT_PROC_ROOT).join(pid.to_string()),
+            uids: Uids::all(0),
+            group_id: 0,
+            name: name.to_owned(),
+            cmdline: cmdline.to_owned(),
+            args: cmdline.split(' ').map(String::from).collect(),
+            exe: None,
+            start_time: 0,
+            state: 'S',
+            tty: None,
+            cgroups: Vec::new(),
+            parent_pid: Pid::from_raw(1),
+            pgid: Pid::from_raw(pid),
+            sid: Pid::from_raw(pid),
+            is_kernel_thread: false,
+            signal_process_group: false,
+            pidfd: None,
+            signaled_at: None,
+            grace_period: None,
+            is_frozen: false,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn with_start_time(mut self, start_time: u64) -> Process {
+        self.start_time = start_time;
+        self
+    }
+
+    #[cfg(test)]
+    pub fn with_exe(mut self, exe: &str) -> Process {
+        self.exe = Some(PathBuf::from(exe));
+        self
+    }
+
+    #[cfg(test)]
+    pub fn with_state(mut self, state: char) -> Process {
+        self.state = state;
+        self
+    }
+
+    #[cfg(test)]
+    pub fn with_tty(mut self, tty: &str) -> Process {
+        self.tty = Some(tty.to_owned());
+        self
+    }
+
+    #[cfg(test)]
+    pub fn with_cgroup(mut self, cgroup: &str) -> Process {
+        self.cgroups.push(cgroup.to_owned());
+        self
+    }
+
+    #[cfg(test)]
+    pub fn with_parent(mut self, parent_pid: i32) -> Process {
+        self.parent_pid = Pid::from_raw(parent_pid);
+        self
+    }
+
+    #[cfg(test)]
+    pub fn with_pgid(mut self, pgid: i32) -> Process {
+        self.pgid = Pid::from_raw(pgid);
+        self
+    }
+
+    #[cfg(test)]
+    pub fn with_user(mut self, user_id: uid_t) -> Process {
+        self.uids = Uids::all(user_id);
+        self
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
@@ -128,48 +429,359 @@ impl Process {
         &self.cmdline
     }
 
+    /// The individual arguments of the commandline, including the program name.
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// The full path of the executable, if it could be read.
+    pub fn exe(&self) -> Option<&Path> {
+        self.exe.as_deref()
+    }
+
+    /// The effective user ID, which decides what the process is allowed to do.
+    pub fn user_id(&self) -> uid_t {
+        self.uids.effective
+    }
+
+    /// The real user ID, which is the user that started the process. Differs from the effective
+    /// user ID for setuid programs.
+    pub fn real_user_id(&self) -> uid_t {
+        self.uids.real
+    }
+
+    pub fn user_id_of(&self, kind: UserIdKind) -> uid_t {
+        match kind {
+            UserIdKind::Effective => self.uids.effective,
+            UserIdKind::Real => self.uids.real,
+        }
+    }
+
+    pub fn uids(&self) -> Uids {
+        self.uids
+    }
+
+    pub fn group_id(&self) -> gid_t {
+        self.group_id
+    }
+
     pub fn pid(&self) -> Pid {
         self.pid
     }
 
+    /// When the process started, in clock ticks after boot.
+    pub fn start_time(&self) -> u64 {
+        self.start_time
+    }
+
+    /// How long the process has been running.
+    pub fn age(&self, clock: &Clock) -> Duration {
+        clock.age_at(self.start_time, SystemTime::now())
+    }
+
+    /// When the process was started.
+    pub fn started_at(&self, clock: &Clock) -> SystemTime {
+        clock.time_of(self.start_time)
+    }
+
+    /// State of the process, like "R" for running or "Z" for zombie. See proc(5).
+    pub fn state(&self) -> char {
+        self.state
+    }
+
+    /// Name of the controlling terminal, like "pts/3", if the process has one.
+    pub fn tty(&self) -> Option<&str> {
+        self.tty.as_deref()
+    }
+
+    /// The paths of the cgroups the process is in, one per hierarchy. With cgroup v2 there is
+    /// only one, like "/system.slice/nginx.service".
+    pub fn cgroups(&self) -> &[String] {
+        &self.cgroups
+    }
+
+    pub fn parent_pid(&self) -> Pid {
+        self.parent_pid
+    }
+
+    /// ID of the process group the process belongs to.
+    pub fn pgid(&self) -> Pid {
+        self.pgid
+    }
+
+    /// ID of the session the process belongs to.
+    pub fn sid(&self) -> Pid {
+        self.sid
+    }
+
+    pub fn is_kernel_thread(&self) -> bool {
+        self.is_kernel_thread
+    }
+
+    /// Opens a pidfd for this process, which makes signaling and waiting on it immune to PID
+    /// reuse. Returns false if it could not be opened, for example because the process has
+    /// already exited.
+    pub fn open_pidfd(&mut self) -> bool {
+        self.pidfd = PidFd::open(self.pid).ok();
+        self.pidfd.is_some()
+    }
+
+    /// Makes signals go to the whole process group of this process instead of only to the process
+    /// itself.
+    pub fn use_process_group(&mut self) {
+        self.signal_process_group = true;
+    }
+
+    /// Remembers when the process was first signaled, so the time it took to exit can be
+    /// reported. Later signals do not change it.
+    pub fn mark_signaled(&mut self, at: Instant) {
+        self.signaled_at.get_or_insert(at);
+    }
+
+    /// Gives the process its own time to exit after the first signal, instead of the waiting
+    /// time of the run.
+    pub fn set_grace_period(&mut self, wait: Duration) {
+        self.grace_period = Some(wait);
+    }
+
+    pub fn grace_period(&self) -> Option<Duration> {
+        self.grace_period
+    }
+
+    /// Remembers if the process was stopped with SIGSTOP, so it can be continued again.
+    pub fn set_frozen(&mut self, is_frozen: bool) {
+        self.is_frozen = is_frozen;
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.is_frozen
+    }
+
+    /// How long the process has had to exit since it was first signaled.
+    pub fn time_since_signaled(&self, now: Instant) -> Option<Duration> {
+        self.signaled_at
+            .map(|signaled_at| now.saturating_duration_since(signaled_at))
+    }
+
+    pub fn pidfd(&self) -> Option<&PidFd> {
+        self.pidfd.as_ref()
+    }
+
     pub fn is_alive(&self) -> bool {
-        let mut proc_path = PathBuf::new();
-        proc_path.push("/");
-        proc_path.push("proc");
-        proc_path.push(self.pid.to_string());
+        if let Some(ref pidfd) = self.pidfd {
+            return !pidfd.has_exited();
+        }
 
-        proc_path.exists()
+        self.path.exists() && !self.pid_was_reused()
     }
 
-    pub fn send(&self, signal: Signal) -> Result<(), KillError> {
-        use nix::errno::Errno;
-        use nix::Error;
+    /// The environment variables the process was started with, or None if they cannot be read.
+    /// Only the owner of a process, or root, can read them.
+    ///
+    /// They are read when asked for, as they are seldom needed and can be large.
+    pub fn environment(&self) -> Option<Vec<(String, String)>> {
+        // Variables are not required to be valid UTF-8.
+        read(self.path.join("environ"))
+            .ok()
+            .map(|environ| parse_environ(&String::from_utf8_lossy(&environ)))
+    }
+
+    /// The inodes of the sockets the process has open, from the links in its fd directory. Empty
+    /// if they cannot be read, which takes the same permissions as signaling the process.
+    pub fn socket_inodes(&self) -> Vec<u64> {
+        read_dir(self.path.join("fd"))
+            .map(|entries| {
+                entries
+                    .flat_map(Result::ok)
+                    .filter_map(|entry| read_link(entry.path()).ok())
+                    .filter_map(|target| parse_socket_inode(target.to_str()?))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
 
-        match kill(self.pid, signal) {
-            Ok(()) => Ok(()),
-            Err(Error::Sys(Errno::EINVAL)) => Err(KillError::InvalidSignal),
-            Err(Error::Sys(Errno::EPERM)) => Err(KillError::NoPermission),
-            Err(Error::Sys(Errno::ESRCH)) => Err(KillError::DoesNotExist),
+    /// Checks if the PID now belongs to another process, after this one exited. The new process
+    /// will have a different start time.
+    fn pid_was_reused(&self) -> bool {
+        match read_file(&self.path.join("stat")).and_then(|stat| parse_stat(&stat)) {
+            Ok(stat) => stat.start_time != self.start_time,
+            // If the stat file cannot be read, the process has probably exited.
+            Err(_) => true,
+        }
+    }
 
-            Err(Error::Sys(errno)) => Err(KillError::UnexpectedError(format!("errno {}", errno))),
+    /// The IDs of the threads of the process, from its task directory. Empty if the process is
+    /// gone.
+    pub fn threads(&self) -> Vec<Pid> {
+        read_dir(self.path.join("task"))
+            .map(|entries| {
+                entries
+                    .flat_map(Result::ok)
+                    .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+                    .map(Pid::from_raw)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
 
-            Err(error) => Err(KillError::UnexpectedError(format!("{}", error))),
+    /// Sends the signal to a single thread of the process with tgkill(2), which fails if the
+    /// thread does not belong to this process anymore.
+    pub fn send_to_thread(&self, tid: Pid, signal: Signal) -> Result<(), KillError> {
+        if !self.is_alive() {
+            return Err(KillError::DoesNotExist);
         }
+        kill_result(tgkill(self.pid, tid, signal))
+    }
+
+    pub fn send(&self, signal: Signal) -> Result<(), KillError> {
+        let result = match self.pidfd {
+            // A pidfd only refers to a single process, so it cannot signal the whole group.
+            Some(ref pidfd) if !self.signal_process_group => pidfd.send(signal),
+            // Never signal an imposter that got this PID after the original process exited.
+            _ if !self.is_alive() => return Err(KillError::DoesNotExist),
+            _ if self.signal_process_group => killpg(self.pgid, signal),
+            _ => kill(self.pid, signal),
+        };
+
+        kill_result(result)
+    }
+}
+
+fn kill_result(result: ::nix::Result<()>) -> Result<(), KillError> {
+    use nix::errno::Errno;
+    use nix::Error;
+
+    match result {
+        Ok(()) => Ok(()),
+        Err(Error::Sys(Errno::EINVAL)) => Err(KillError::InvalidSignal),
+        Err(Error::Sys(Errno::EPERM)) => Err(KillError::NoPermission),
+        Err(Error::Sys(Errno::ESRCH)) => Err(KillError::DoesNotExist),
+
+        Err(Error::Sys(errno)) => Err(KillError::UnexpectedError(format!("errno {}", errno))),
+
+        Err(error) => Err(KillError::UnexpectedError(format!("{}", error))),
     }
 }
 
-#[derive(Debug, Clone, Fail)]
+#[derive(Debug, Clone, Error)]
 pub enum KillError {
-    #[fail(display = "Invalid signal")]
+    #[error("Invalid signal")]
     InvalidSignal,
-    #[fail(display = "Insufficient permission to send signal to this process")]
+    #[error("Insufficient permission to send signal to this process")]
     NoPermission,
-    #[fail(display = "Cannot find process")]
+    #[error("Cannot find process")]
     DoesNotExist,
-    #[fail(display = "Unexpected error: {}", _0)]
+    #[error("Unexpected error: {0}")]
     UnexpectedError(String),
 }
 
+/// Why a process could not be loaded from /proc.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum LoadError {
+    /// The process exited before everything about it could be read. This is common on busy
+    /// systems, as processes can exit at any time between listing /proc and reading from it.
+    #[error("Process {0} exited before it could be read")]
+    Gone(Pid),
+    #[error("{0}")]
+    Failed(String),
+}
+
+/// Why the list of processes could not be read.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ScanError {
+    /// There is no proc filesystem, like on other systems than Linux, or in containers that do
+    /// not mount it.
+    #[error("No proc filesystem is mounted at {0}. It is needed to find processes, and is only available on Linux. Mount it, or use --proc-root to point to where it is.")]
+    NotMounted(String),
+    #[error("Failed to open {0}: {1}")]
+    Unreadable(String, String),
+    #[error("Thread reading {0} crashed")]
+    ThreadCrashed(String),
+}
+
+/// Opens the proc filesystem for listing the processes in it.
+fn open_proc_root(proc_root: &Path) -> Result<ReadDir, ScanError> {
+    let not_mounted = || ScanError::NotMounted(proc_root.display().to_string());
+    // A proc filesystem is never empty, but the directory it is usually mounted on is.
+    let is_empty = || {
+        read_dir(proc_root)
+            .map(|mut entries| entries.next().is_none())
+            .unwrap_or(false)
+    };
+
+    match read_dir(proc_root) {
+        Ok(_) if is_empty() => Err(not_mounted()),
+        Ok(entries) => Ok(entries),
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Err(not_mounted()),
+        Err(err) => Err(ScanError::Unreadable(
+            proc_root.display().to_string(),
+            err.to_string(),
+        )),
+    }
+}
+
+impl LoadError {
+    fn from_io(pid: Pid, path: &Path, error: io::Error) -> LoadError {
+        // The files of a process disappear when it exits, and ones that are already open fail
+        // with ESRCH instead.
+        if error.kind() == io::ErrorKind::NotFound || error.raw_os_error() == Some(::libc::ESRCH) {
+            LoadError::Gone(pid)
+        } else {
+            LoadError::Failed(format!("Could not read {}: {}", path.display(), error))
+        }
+    }
+}
+
+impl From<String> for LoadError {
+    fn from(message: String) -> LoadError {
+        LoadError::Failed(message)
+    }
+}
+
+/// Finds the inodes of the TCP sockets listening on any of the ports, over both IPv4 and IPv6.
+pub fn listening_sockets(proc_root: &Path, ports: &[u16]) -> Result<HashSet<u64>, String> {
+    let mut inodes = HashSet::new();
+    for table in &["net/tcp", "net/tcp6"] {
+        let path = proc_root.join(table);
+        let contents = match read_to_string(&path) {
+            Ok(contents) => contents,
+            // There is no IPv6 table when IPv6 is disabled.
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => continue,
+            Err(error) => return Err(format!("Could not read {}: {}", path.display(), error)),
+        };
+        inodes.extend(
+            contents
+                .lines()
+                .filter_map(parse_socket)
+                .filter(|socket| socket.state == TCP_LISTEN && ports.contains(&socket.port))
+                .map(|socket| socket.inode),
+        );
+    }
+    Ok(inodes)
+}
+
+fn kill(pid: Pid, signal: Signal) -> ::nix::Result<()> {
+    let result = unsafe { ::libc::kill(pid.into(), signal.number()) };
+    ::nix::errno::Errno::result(result).map(drop)
+}
+
+fn tgkill(tgid: Pid, tid: Pid, signal: Signal) -> ::nix::Result<()> {
+    let result = unsafe {
+        ::libc::syscall(
+            ::libc::SYS_tgkill,
+            i32::from(tgid),
+            i32::from(tid),
+            signal.number(),
+        )
+    };
+    ::nix::errno::Errno::result(result).map(drop)
+}
+
+fn killpg(pgid: Pid, signal: Signal) -> ::nix::Result<()> {
+    let result = unsafe { ::libc::killpg(pgid.into(), signal.number()) };
+    ::nix::errno::Errno::result(result).map(drop)
+}
+
 fn read_file(path: &Path) -> Result<String, String> {
     // In Rust 1.26 we can use Path::read_to_string instead.
     let mut string = String::new();
@@ -180,20 +792,232 @@ fn read_file(path: &Path) -> Result<String, String> {
     Ok(string)
 }
 
-fn uid_of_file(path: &Path) -> Result<uid_t, String> {
-    use std::os::linux::fs::MetadataExt;
-    path.metadata()
-        .map_err(|err| format!("Could not stat {}: {}", path.display(), err))
-        .map(|metadata| metadata.st_uid())
+/// Process flag for kernel threads, from include/linux/sched.h.
+const PF_KTHREAD: u64 = 0x0020_0000;
+
+#[derive(Debug, PartialEq, Eq)]
+struct Stat {
+    name: String,
+    state: char,
+    parent_pid: i32,
+    pgid: i32,
+    sid: i32,
+    tty_nr: u32,
+    flags: u64,
+    start_time: u64,
+}
+
+/// Parses the interesting fields of `/proc/<pid>/stat`. See proc(5) for the format.
+fn parse_stat(stat: &str) -> Result<Stat, String> {
+    // The second field is the command name in parenthesis, which may itself contain both spaces
+    // and parenthesis. The last closing parenthesis is where it ends.
+    let (name, after_comm) = match (stat.find('('), stat.rfind(')')) {
+        (Some(start), Some(end)) if start < end => (&stat[start + 1..end], &stat[end + 1..]),
+        _ => return Err(format!("Could not find command name in stat: {}", stat)),
+    };
+
+    // Fields after the command name start at field 3.
+    let fields: Vec<&str> = after_comm.split_whitespace().collect();
+    let field = |number: usize| {
+        fields
+            .get(number - 3)
+            .ok_or_else(|| format!("Missing field {} in stat: {}", number, stat))
+    };
+
+    let state = field(3)?
+        .chars()
+        .next()
+        .ok_or_else(|| format!("Missing state in stat: {}", stat))?;
+    let parent_pid = field(4)?
+        .parse()
+        .map_err(|e| format!("Failed to parse parent PID in stat: {}", e))?;
+    let pgid = field(5)?
+        .parse()
+        .map_err(|e| format!("Failed to parse process group in stat: {}", e))?;
+    let sid = field(6)?
+        .parse()
+        .map_err(|e| format!("Failed to parse session in stat: {}", e))?;
+    let tty_nr = field(7)?
+        .parse::<i32>()
+        .map_err(|e| format!("Failed to parse tty in stat: {}", e))? as u32;
+    let flags = field(9)?
+        .parse()
+        .map_err(|e| format!("Failed to parse flags in stat: {}", e))?;
+    let start_time = field(22)?
+        .parse()
+        .map_err(|e| format!("Failed to parse start time in stat: {}", e))?;
+
+    Ok(Stat {
+        name: name.to_owned(),
+        state,
+        parent_pid,
+        pgid,
+        sid,
+        tty_nr,
+        flags,
+        start_time,
+    })
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Status {
+    name: String,
+    uids: Uids,
+}
+
+/// Parses the interesting fields of `/proc/<pid>/status`. See proc(5) for the format.
+///
+/// The name is the same as in comm, truncated to 15 bytes. Unlike comm, special characters are
+/// escaped, like "\n" for a newline.
+fn parse_status(status: &str) -> Result<Status, String> {
+    let field = |name: &str| {
+        status
+            .lines()
+            .filter_map(|line| line.strip_prefix(name))
+            .filter_map(|rest| rest.strip_prefix(':'))
+            .next()
+            .ok_or_else(|| format!("Missing {} in status", name))
+    };
+
+    Ok(Status {
+        name: field("Name")?.trim().to_owned(),
+        uids: parse_uids(field("Uid")?)?,
+    })
+}
+
+/// Parses the value of the Uid line in `/proc/<pid>/status`, which lists the real, effective,
+/// saved set and filesystem UIDs, in that order.
+fn parse_uids(line: &str) -> Result<Uids, String> {
+    let uids = line
+        .split_whitespace()
+        .map(|uid| {
+            uid.parse()
+                .map_err(|e| format!("Failed to parse UID in status: {}", e))
+        })
+        .collect::<Result<Vec<uid_t>, String>>()?;
+
+    match uids.as_slice() {
+        &[real, effective, saved, filesystem] => Ok(Uids {
+            real,
+            effective,
+            saved,
+            filesystem,
+        }),
+        _ => Err(format!("Expected four UIDs in status: {}", line.trim())),
+    }
+}
+
+/// Translates the packed device number of a controlling terminal to its name under `/dev`, the
+/// same way as ps does. Returns None if there is no controlling terminal.
+fn tty_name(tty_nr: u32) -> Option<String> {
+    if tty_nr == 0 {
+        return None;
+    }
+
+    let major = (tty_nr >> 8) & 0xfff;
+    let minor = (tty_nr & 0xff) | ((tty_nr >> 12) & 0xfff00);
+
+    Some(match major {
+        4 if minor < 64 => format!("tty{}", minor),
+        4 => format!("ttyS{}", minor - 64),
+        // Unix98 pseudo-terminals use a range of majors, each holding 256 minors.
+        136..=143 => format!("pts/{}", (major - 136) * 256 + minor),
+        _ => format!("{}:{}", major, minor),
+    })
+}
+
+/// TCP state of listening sockets, from include/net/tcp_states.h.
+const TCP_LISTEN: u8 = 0x0A;
+
+#[derive(Debug, PartialEq, Eq)]
+struct Socket {
+    port: u16,
+    state: u8,
+    inode: u64,
+}
+
+/// Parses a line of `/proc/net/tcp` or `/proc/net/tcp6`. Addresses, ports and states are in hex,
+/// like "0100007F:1F90" for 127.0.0.1:8080. The header line is not a socket.
+fn parse_socket(line: &str) -> Option<Socket> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() < 10 {
+        return None;
+    }
+    let port = fields[1].rsplit(':').next()?;
+
+    Some(Socket {
+        port: u16::from_str_radix(port, 16).ok()?,
+        state: u8::from_str_radix(fields[3], 16).ok()?,
+        inode: fields[9].parse().ok()?,
+    })
+}
+
+/// Parses the target of a link in `/proc/<pid>/fd`, like "socket:[123456]", into the inode of the
+/// socket. Links to anything else are not sockets.
+fn parse_socket_inode(target: &str) -> Option<u64> {
+    target
+        .strip_prefix("socket:[")?
+        .strip_suffix(']')?
+        .parse()
+        .ok()
+}
+
+/// Finds the boot time in `/proc/stat`, in seconds since the Unix epoch.
+fn parse_boot_time(stat: &str) -> Result<u64, String> {
+    stat.lines()
+        .filter_map(|line| line.strip_prefix("btime "))
+        .next()
+        .ok_or_else(|| String::from("Could not find boot time in /proc/stat"))?
+        .trim()
+        .parse()
+        .map_err(|e| format!("Failed to parse boot time in /proc/stat: {}", e))
+}
+
+/// Parses the cgroup paths in `/proc/<pid>/cgroup`. Every line is formatted like
+/// "hierarchy-ID:controllers:path". The cgroup v2 hierarchy has ID 0 and no controllers.
+fn parse_cgroups(cgroup: &str) -> Vec<String> {
+    cgroup
+        .lines()
+        // The path may itself contain colons.
+        .filter_map(|line| line.splitn(3, ':').nth(2))
+        .map(String::from)
+        .collect()
+}
+
+/// Parses the NUL-separated "KEY=value" pairs in `/proc/<pid>/environ`. Values may contain "=",
+/// so only the first one separates the key from the value.
+fn parse_environ(environ: &str) -> Vec<(String, String)> {
+    environ
+        .split('\0')
+        .filter_map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) if !key.is_empty() => {
+                    Some((key.to_owned(), value.to_owned()))
+                }
+                _ => None,
+            }
+        })
+        .collect()
 }
 
 fn parse_cmdline(cmdline: &str) -> String {
-    cmdline.replace("\0", " ").trim_right().to_owned()
+    cmdline.replace("\0", " ").trim_end().to_owned()
+}
+
+fn parse_args(cmdline: &str) -> Vec<String> {
+    // Every argument is terminated by a NUL byte, including the last one.
+    let mut args: Vec<String> = cmdline.split('\0').map(String::from).collect();
+    if args.last().map(String::is_empty).unwrap_or(false) {
+        args.pop();
+    }
+    args
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use test_dir::TestDir;
 
     #[test]
     fn it_parses_cmdlines() {
@@ -202,4 +1026,612 @@ mod tests {
 
         assert_eq!(&parse_cmdline(input), expected_output);
     }
+
+    #[test]
+    fn it_parses_cgroups() {
+        let v2 = "0::/system.slice/nginx.service\n";
+        assert_eq!(parse_cgroups(v2), vec!["/system.slice/nginx.service"]);
+
+        let v1 = "12:pids:/system.slice/cron.service\n\
+                  4:cpu,cpuacct:/system.slice/cron.service\n\
+                  1:name=systemd:/system.slice/cron.service\n\
+                  0::/system.slice/cron.service\n";
+        assert_eq!(parse_cgroups(v1).len(), 4);
+        assert!(parse_cgroups(v1)
+            .iter()
+            .all(|path| path == "/system.slice/cron.service"));
+
+        assert_eq!(parse_cgroups("0::/odd:name\n"), vec!["/odd:name"]);
+        assert!(parse_cgroups("").is_empty());
+    }
+
+    #[test]
+    fn it_parses_environments() {
+        let environ = "ROLE=flush\0OPTS=--level=3 --fast\0EMPTY=\0";
+
+        assert_eq!(
+            parse_environ(environ),
+            vec![
+                (String::from("ROLE"), String::from("flush")),
+                (String::from("OPTS"), String::from("--level=3 --fast")),
+                (String::from("EMPTY"), String::new()),
+            ]
+        );
+        assert!(parse_environ("").is_empty());
+    }
+
+    #[test]
+    fn it_reads_the_environment_of_real_processes() {
+        let process = Process::from_pid(Path::new(DEFAULT_PROC_ROOT), Pid::this())
+            .expect("Failed to load own process");
+        let environment = process
+            .environment()
+            .expect("Failed to read own environment");
+
+        assert!(!environment.is_empty());
+    }
+
+    #[test]
+    fn it_parses_args() {
+        let input = "/usr/bin/bash\0-c\0echo hello world\0";
+
+        assert_eq!(
+            parse_args(input),
+            vec!["/usr/bin/bash", "-c", "echo hello world"]
+        );
+        assert_eq!(parse_args(""), Vec::<String>::new());
+    }
+
+    const STAT: &str = "1234 (my (weird) proc) S 1 1234 1234 0 -1 4194560 1065 0 0 0 2 1 0 0 20 \
+                        0 1 0 987654 5750784 1085 18446744073709551615 1 1 0 0 0 0 0 0 0 0 0 0 \
+                        17 3 0 0 0 0 0 0 0 0 0 0 0 0 0\n";
+
+    #[test]
+    fn it_parses_stat_start_time() {
+        assert_eq!(parse_stat(STAT).map(|stat| stat.start_time), Ok(987654));
+    }
+
+    #[test]
+    fn it_parses_stat_name() {
+        assert_eq!(
+            parse_stat(STAT).map(|stat| stat.name),
+            Ok(String::from("my (weird) proc"))
+        );
+        assert_eq!(
+            parse_stat(&STAT.replace("my (weird) proc", "kworker/0:1")).map(|stat| stat.name),
+            Ok(String::from("kworker/0:1"))
+        );
+    }
+
+    #[test]
+    fn it_fails_to_parse_truncated_stats() {
+        assert!(parse_stat("1234 (proc) S 1 1234").is_err());
+        assert!(parse_stat("1234 proc S").is_err());
+        assert!(parse_stat("1234 )proc( S").is_err());
+        assert!(parse_stat("").is_err());
+    }
+
+    #[test]
+    fn it_parses_boot_time() {
+        let stat = "cpu  1 2 3 4\nintr 12345\nctxt 999\nbtime 1536000000\nprocesses 42\n";
+
+        assert_eq!(parse_boot_time(stat), Ok(1536000000));
+        assert!(parse_boot_time("cpu  1 2 3 4\n").is_err());
+    }
+
+    #[test]
+    fn it_computes_process_age() {
+        let clock = Clock {
+            boot_time: 1_000,
+            ticks_per_second: 100,
+        };
+        let now = UNIX_EPOCH + Duration::from_secs(1_600);
+
+        // Started 250.5 seconds after boot, which is 349.5 seconds ago.
+        assert_eq!(clock.age_at(25_050, now), Duration::from_millis(349_500));
+        assert_eq!(clock.age_at(0, now), Duration::from_secs(600));
+
+        // Started in the future according to the clock.
+        assert_eq!(clock.age_at(100_000, now), Duration::from_secs(0));
+    }
+
+    #[test]
+    fn it_computes_process_start_time() {
+        let clock = Clock::fake(1_000, 100);
+        let process = Process::fake(42, "sleep", "sleep 60").with_start_time(25_050);
+
+        assert_eq!(
+            process.started_at(&clock),
+            UNIX_EPOCH + Duration::from_millis(1_250_500)
+        );
+    }
+
+    #[test]
+    fn it_computes_the_age_of_real_processes() {
+        let clock = Clock::system(Path::new(DEFAULT_PROC_ROOT)).expect("Failed to read clock");
+        let process = Process::from_pid(Path::new(DEFAULT_PROC_ROOT), Pid::this())
+            .expect("Failed to load own process");
+
+        assert!(process.age(&clock) < Duration::from_secs(60 * 60));
+    }
+
+    #[test]
+    fn it_parses_stat_state() {
+        assert_eq!(parse_stat(STAT).map(|stat| stat.state), Ok('S'));
+
+        let zombie = "1234 (a) Z) Z 1 1234 1234 0 -1 4194560 1065 0 0 0 2 1 0 0 20 0 1 0 987654";
+        assert_eq!(parse_stat(zombie).map(|stat| stat.state), Ok('Z'));
+
+        let spaces = "1234 (Web Content) R 1 1234 1234 0 -1 4194560 1065 0 0 0 2 1 0 0 20 0 1 0 9";
+        let stat = parse_stat(spaces).unwrap();
+        assert_eq!(stat.state, 'R');
+        assert_eq!(stat.name, "Web Content");
+    }
+
+    #[test]
+    fn it_parses_stat_session_and_process_group() {
+        let stat = parse_stat(STAT).unwrap();
+        assert_eq!((stat.pgid, stat.sid), (1234, 1234));
+
+        // Spaces and parenthesis in the name must not shift the fields.
+        let shell_job = "4321 (sh -c (sleep) 10) S 4000 4100 3900 34819 4100 4194560 0 0 0 0 \
+                         0 0 0 0 20 0 1 0 555";
+        let stat = parse_stat(shell_job).unwrap();
+        assert_eq!((stat.pgid, stat.sid, stat.tty_nr), (4100, 3900, 34819));
+        assert_eq!(stat.parent_pid, 4000);
+    }
+
+    #[test]
+    fn it_decodes_tty_numbers() {
+        assert_eq!(parse_stat(STAT).map(|stat| stat.tty_nr), Ok(0));
+        assert_eq!(tty_name(0), None);
+
+        // Major 136, minor 3.
+        assert_eq!(tty_name(34819), Some(String::from("pts/3")));
+        // Major 136, minor 300, where the upper bits of the minor are packed above the major.
+        assert_eq!(
+            tty_name((1 << 20) | (136 << 8) | 44),
+            Some(String::from("pts/300"))
+        );
+        // Major 137, minor 4.
+        assert_eq!(tty_name((137 << 8) | 4), Some(String::from("pts/260")));
+        assert_eq!(tty_name((4 << 8) | 2), Some(String::from("tty2")));
+        assert_eq!(tty_name((4 << 8) | 65), Some(String::from("ttyS1")));
+        assert_eq!(tty_name((5 << 8) | 1), Some(String::from("5:1")));
+    }
+
+    #[test]
+    fn it_parses_stat_flags() {
+        let stat = parse_stat(STAT).unwrap();
+        assert_eq!(stat.flags, 4194560);
+        assert_eq!(stat.flags & PF_KTHREAD, 0);
+
+        let kernel_stat = parse_stat(&STAT.replace("4194560", "69238880")).unwrap();
+        assert_ne!(kernel_stat.flags & PF_KTHREAD, 0);
+    }
+
+    #[test]
+    fn it_falls_back_to_stat_name_without_comm() {
+        let fixture = ProcFixture::new("stat-name");
+        let path = fixture.process("1234", &[("stat", STAT), ("cmdline", "weird\0--flag\0")]);
+
+        let process = Process::from_path(&path).expect("Failed to load process");
+        assert_eq!(process.name(), "my (weird) proc");
+        assert_eq!(process.commandline(), "weird --flag");
+        assert_eq!(process.pid(), Pid::from_raw(1234));
+        assert!(!process.is_kernel_thread());
+    }
+
+    const STATUS: &str = "Name:\tmy (weird) proc\n\
+                          Umask:\t0022\n\
+                          State:\tS (sleeping)\n\
+                          Tgid:\t1234\n\
+                          Pid:\t1234\n\
+                          PPid:\t1\n\
+                          Uid:\t1000\t0\t0\t0\n\
+                          Gid:\t1000\t1000\t1000\t1000\n";
+
+    #[test]
+    fn it_parses_status() {
+        assert_eq!(
+            parse_status(STATUS),
+            Ok(Status {
+                name: String::from("my (weird) proc"),
+                uids: Uids {
+                    real: 1000,
+                    effective: 0,
+                    saved: 0,
+                    filesystem: 0,
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn it_parses_uid_lines() {
+        assert_eq!(
+            parse_uids("\t1000\t33\t33\t1000"),
+            Ok(Uids {
+                real: 1000,
+                effective: 33,
+                saved: 33,
+                filesystem: 1000,
+            })
+        );
+        assert!(parse_uids("\t1000\t33").is_err());
+    }
+
+    #[test]
+    fn it_selects_the_user_id_to_match_on() {
+        let mut process = Process::fake(100, "passwd", "passwd");
+        process.uids = parse_uids("\t1000\t0\t0\t0").unwrap();
+
+        assert_eq!(process.user_id_of(UserIdKind::Effective), 0);
+        assert_eq!(process.user_id_of(UserIdKind::Real), 1000);
+    }
+
+    #[test]
+    fn it_fails_to_parse_incomplete_statuses() {
+        assert!(parse_status("Umask:\t0022\nUid:\t1000\t1000\t1000\t1000\n").is_err());
+        assert!(parse_status("Name:\tsleep\nUid:\t1000\n").is_err());
+        assert!(parse_status("Name:\tsleep\nUid:\tme\tme\n").is_err());
+    }
+
+    #[test]
+    fn it_falls_back_to_status_without_comm() {
+        let fixture = ProcFixture::new("status-name");
+        let path = fixture.process(
+            "1234",
+            &[
+                ("stat", &STAT.replace("my (weird) proc", "stale")),
+                ("status", STATUS),
+                ("cmdline", "weird\0--flag\0"),
+            ],
+        );
+
+        let process = Process::from_path(&path).expect("Failed to load process");
+        assert_eq!(process.name(), "my (weird) proc");
+        assert_eq!(process.user_id(), 0);
+        assert_eq!(process.real_user_id(), 1000);
+    }
+
+    #[test]
+    fn it_treats_processes_that_exit_while_being_read_as_gone() {
+        let fixture = ProcFixture::new("gone");
+        let path = fixture.process("1234", &[("stat", STAT)]);
+
+        let missing = Process::from_path(&path);
+        let exited = Process::load(&path, |file| {
+            if file.ends_with("cmdline") {
+                Err(io::Error::from_raw_os_error(::libc::ESRCH))
+            } else {
+                read_to_string(file)
+            }
+        });
+        let unreadable = Process::load(&path, |file| {
+            if file.ends_with("cmdline") {
+                Err(io::Error::from(io::ErrorKind::PermissionDenied))
+            } else {
+                read_to_string(file)
+            }
+        });
+
+        assert_eq!(missing.unwrap_err(), LoadError::Gone(Pid::from_raw(1234)));
+        assert_eq!(exited.unwrap_err(), LoadError::Gone(Pid::from_raw(1234)));
+        match unreadable.unwrap_err() {
+            LoadError::Failed(message) => assert!(message.contains("cmdline"), "{}", message),
+            other => panic!("Expected a failure, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_finds_kernel_threads() {
+        // kthreadd is always PID 2 on Linux, unless running in a PID namespace.
+        if let Ok(process) = Process::from_pid(Path::new(DEFAULT_PROC_ROOT), Pid::from_raw(2)) {
+            if process.name() == "kthreadd" {
+                assert!(process.is_kernel_thread());
+                assert_eq!(process.commandline(), "[kthreadd]");
+            }
+        }
+    }
+
+    #[test]
+    fn it_reads_start_time_of_real_processes() {
+        let process = Process::from_pid(Path::new(DEFAULT_PROC_ROOT), Pid::this())
+            .expect("Failed to load own process");
+
+        assert!(process.start_time > 0);
+        assert!(process.is_alive());
+        assert_eq!(process.user_id(), users::get_effective_uid());
+        assert_eq!(process.real_user_id(), users::get_current_uid());
+    }
+
+    #[test]
+    fn it_finds_the_same_processes_in_parallel() {
+        let own_pid = Pid::this();
+        let find_self = |jobs| {
+            Process::all(Path::new(DEFAULT_PROC_ROOT), jobs)
+                .expect("Failed to scan /proc")
+                .flat_map(Result::ok)
+                .any(|process| process.pid() == own_pid)
+        };
+
+        assert!(find_self(1));
+        assert!(find_self(4));
+    }
+
+    #[test]
+    fn it_loads_processes_by_pid() {
+        let own_pid = Pid::this();
+        let process = Process::from_pid(Path::new(DEFAULT_PROC_ROOT), own_pid)
+            .expect("Failed to load own process");
+
+        assert_eq!(process.pid(), own_pid);
+    }
+
+    #[test]
+    fn it_scans_other_proc_roots() {
+        let fixture = ProcFixture::new("proc");
+        for pid in &["1234", "1235"] {
+            fixture.process(pid, &[("stat", STAT), ("cmdline", "fixture\0")]);
+        }
+        fixture.process("sys", &[]);
+
+        let scan = |jobs| {
+            let mut pids: Vec<i32> = Process::all(&fixture.root(), jobs)
+                .expect("Failed to scan fixture")
+                .flat_map(Result::ok)
+                .map(|process| process.pid().into())
+                .collect();
+            pids.sort();
+            pids
+        };
+        assert_eq!(scan(1), vec![1234, 1235]);
+        assert_eq!(scan(2), vec![1234, 1235]);
+
+        let process = Process::from_pid(&fixture.root(), Pid::from_raw(1235));
+        assert_eq!(process.expect("Failed to load").commandline(), "fixture");
+    }
+
+    #[test]
+    fn it_explains_when_there_is_no_proc_filesystem() {
+        let missing = Path::new("/nonexistent/graceful-shutdown/proc");
+        for &jobs in &[1, 4] {
+            match Process::all(missing, jobs) {
+                Err(ScanError::NotMounted(path)) => {
+                    assert_eq!(path, "/nonexistent/graceful-shutdown/proc")
+                }
+                Err(other) => panic!("Expected a missing proc filesystem, got {:?}", other),
+                Ok(_) => panic!("Expected a missing proc filesystem"),
+            }
+        }
+
+        // Nothing mounted on the mount point.
+        let empty = ProcFixture::new("empty");
+        match Process::all(empty.root(), 1) {
+            Err(ScanError::NotMounted(_)) => {}
+            Err(other) => panic!("Expected a missing proc filesystem, got {:?}", other),
+            Ok(_) => panic!("Expected a missing proc filesystem"),
+        }
+    }
+
+    #[test]
+    fn it_parses_listening_sockets() {
+        let header = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode";
+        let ipv4 = "   0: 0100007F:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 31337 1 0000000000000000 100 0 0 10 0";
+        let ipv6 = "   1: 00000000000000000000000000000000:0050 00000000000000000000000000000000:0000 01 00000000:00000000 00:00000000 00000000     0        0 4242 1 0000000000000000 20 4 30 10 -1";
+
+        assert_eq!(parse_socket(header), None);
+        assert_eq!(
+            parse_socket(ipv4),
+            Some(Socket {
+                port: 8080,
+                state: TCP_LISTEN,
+                inode: 31337,
+            })
+        );
+        assert_eq!(
+            parse_socket(ipv6),
+            Some(Socket {
+                port: 80,
+                state: 0x01,
+                inode: 4242,
+            })
+        );
+        assert_eq!(parse_socket(""), None);
+    }
+
+    #[test]
+    fn it_parses_socket_inodes_of_open_files() {
+        assert_eq!(parse_socket_inode("socket:[31337]"), Some(31337));
+        assert_eq!(parse_socket_inode("pipe:[31337]"), None);
+        assert_eq!(parse_socket_inode("/dev/null"), None);
+        assert_eq!(parse_socket_inode("socket:[]"), None);
+    }
+
+    #[test]
+    fn it_finds_processes_listening_on_a_port() {
+        use std::os::unix::fs::symlink;
+
+        let fixture = ProcFixture::new("ports");
+        fixture.process(
+            "net",
+            &[
+                (
+                    "tcp",
+                    "  sl  local_address rem_address   st\n\
+                     0: 00000000:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000 0 0 100 1\n\
+                     1: 0100007F:1F90 0100007F:D431 01 00000000:00000000 00:00000000 00000000 0 0 101 1\n\
+                     2: 00000000:0050 00000000:0000 0A 00000000:00000000 00:00000000 00000000 0 0 102 1\n",
+                ),
+            ],
+        );
+        assert_eq!(
+            listening_sockets(&fixture.root(), &[8080]),
+            Ok(vec![100].into_iter().collect())
+        );
+        assert_eq!(
+            listening_sockets(&fixture.root(), &[80, 8080]),
+            Ok(vec![100, 102].into_iter().collect())
+        );
+
+        let path = fixture.process(
+            "42",
+            &[
+                ("stat", &stat_line(42, "server", 'S', 1, 0)),
+                ("status", "Name:\tserver\nUid:\t0\t0\t0\t0\n"),
+                ("cmdline", "server\0"),
+            ],
+        );
+        ::std::fs::create_dir_all(path.join("fd")).unwrap();
+        symlink("/dev/null", path.join("fd/0")).unwrap();
+        symlink("socket:[100]", path.join("fd/3")).unwrap();
+        symlink("pipe:[200]", path.join("fd/4")).unwrap();
+
+        let processes = fixture.scan();
+        assert_eq!(processes[0].socket_inodes(), vec![100]);
+    }
+
+    /// A temporary directory laid out like /proc. It is removed again when dropped.
+    struct ProcFixture {
+        dir: TestDir,
+    }
+
+    impl ProcFixture {
+        fn new(name: &str) -> ProcFixture {
+            ProcFixture {
+                dir: TestDir::new(name),
+            }
+        }
+
+        fn root(&self) -> &Path {
+            self.dir.path()
+        }
+
+        /// Adds a process directory with the given files in it.
+        fn process(&self, pid: &str, files: &[(&str, &str)]) -> PathBuf {
+            let path = self.root().join(pid);
+            ::std::fs::create_dir_all(&path).unwrap();
+            for &(name, contents) in files {
+                ::std::fs::write(path.join(name), contents).unwrap();
+            }
+            path
+        }
+
+        fn scan(&self) -> Vec<Process> {
+            let mut processes: Vec<Process> = ProcessIterator::new(self.root())
+                .expect("Failed to scan fixture")
+                .map(|result| result.expect("Failed to load process"))
+                .collect();
+            processes.sort_by_key(|process| i32::from(process.pid()));
+            processes
+        }
+    }
+
+    /// A stat line with the fields that are parsed set, and the rest zeroed.
+    fn stat_line(pid: i32, name: &str, state: char, parent_pid: i32, tty_nr: u32) -> String {
+        format!(
+            "{pid} ({name}) {state} {parent} {pid} {pid} {tty} -1 4194560 0 0 0 0 0 0 0 0 20 0 1 0 \
+             {start} 0 0\n",
+            pid = pid,
+            name = name,
+            state = state,
+            parent = parent_pid,
+            tty = tty_nr,
+            start = pid * 100,
+        )
+    }
+
+    #[test]
+    fn it_discovers_processes_in_a_proc_tree() {
+        use std::os::unix::fs::symlink;
+
+        let fixture = ProcFixture::new("discovery");
+        let master = fixture.process(
+            "200",
+            &[
+                ("stat", &stat_line(200, "nginx", 'S', 1, 34816)),
+                ("status", "Name:\tnginx\nUid:\t33\t33\t33\t33\n"),
+                ("comm", "nginx\n"),
+                ("cmdline", "nginx: master process\0"),
+            ],
+        );
+        symlink("/usr/sbin/nginx", master.join("exe")).unwrap();
+        fixture.process(
+            "201",
+            &[
+                ("stat", &stat_line(201, "worker", 'R', 200, 0)),
+                ("cmdline", "worker\0--id\0"),
+            ],
+        );
+        // Neither of these are processes.
+        fixture.process("sys", &[("stat", &stat_line(300, "sys", 'S', 1, 0))]);
+        ::std::fs::write(fixture.root().join("301"), "").unwrap();
+
+        let processes = fixture.scan();
+        assert_eq!(processes.len(), 2);
+
+        let master = &processes[0];
+        assert_eq!(master.pid(), Pid::from_raw(200));
+        assert_eq!(master.name(), "nginx");
+        assert_eq!(master.commandline(), "nginx: master process");
+        assert_eq!(master.exe(), Some(Path::new("/usr/sbin/nginx")));
+        assert_eq!(master.state(), 'S');
+        assert_eq!(master.tty(), Some("pts/0"));
+        assert_eq!(master.parent_pid(), Pid::from_raw(1));
+        assert_eq!(master.start_time(), 20_000);
+        assert_eq!(master.user_id(), 33);
+
+        // Without exe, comm and status, the rest of the files are enough.
+        let worker = &processes[1];
+        assert_eq!(worker.pid(), Pid::from_raw(201));
+        assert_eq!(worker.name(), "worker");
+        assert_eq!(worker.args(), &["worker", "--id"]);
+        assert_eq!(worker.exe(), None);
+        assert_eq!(worker.state(), 'R');
+        assert_eq!(worker.tty(), None);
+        assert_eq!(worker.parent_pid(), Pid::from_raw(200));
+        assert_eq!(worker.user_id(), users::get_effective_uid());
+    }
+
+    #[test]
+    fn it_fails_to_load_processes_with_broken_stat_files() {
+        let fixture = ProcFixture::new("broken");
+        fixture.process("400", &[("stat", "400 garbage"), ("cmdline", "x\0")]);
+
+        let results: Vec<_> = ProcessIterator::new(fixture.root()).unwrap().collect();
+        assert_eq!(results.len(), 1);
+        match results[0] {
+            Err(LoadError::Failed(ref message)) => assert!(message.contains("command name")),
+            ref other => panic!("Expected a failure, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_signals_its_own_threads() {
+        let process = Process::from_pid(Path::new(DEFAULT_PROC_ROOT), Pid::this())
+            .expect("Failed to load own process");
+        let threads = process.threads();
+        let winch: Signal = "winch".parse().unwrap();
+
+        // The main thread always has the same ID as the process.
+        assert!(threads.contains(&Pid::this()));
+        // WINCH is ignored by default, so this is safe to send to ourselves.
+        assert!(process.send_to_thread(Pid::this(), winch).is_ok());
+        match process.send_to_thread(Pid::from_raw(i32::MAX), winch) {
+            Err(KillError::DoesNotExist) => {}
+            other => panic!("Expected a missing thread, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_errors_on_nonexistent_pids() {
+        // PIDs are capped at 2^22 on Linux, so this can never exist.
+        let pid = Pid::from_raw(i32::MAX);
+
+        assert_eq!(
+            Process::from_pid(Path::new(DEFAULT_PROC_ROOT), pid).unwrap_err(),
+            format!("Could not find process with PID {}", pid),
+        );
+    }
 }
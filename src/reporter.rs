@@ -0,0 +1,869 @@
+use json::{Event, Outcome};
+use matcher::MatchMode;
+use options::{Colors, Options, OutputMode};
+use processes::{KillError, Process};
+use signal::Signal;
+use std::cell::{Cell, RefCell};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use summary::Summary;
+use timestamp;
+use ProcessDescription;
+
+/// Receives everything that happens during a run, so it can be shown to the user.
+pub trait Reporter {
+    /// Something looks wrong, but the run can continue anyway.
+    fn warning(&self, message: &str);
+
+    /// Details that are only interesting when looking closely at what happens.
+    fn info(&self, message: &str);
+
+    /// No process matched, so there is nothing to do.
+    fn nothing_matched(&self);
+
+    /// The process matched, but will not be signaled.
+    fn skipped(&self, process: &Process, reason: &str);
+
+    /// The process matched, but signaling it would be dangerous.
+    fn refused(&self, process: &Process, reason: &str);
+
+    /// The process matched. Only reported when the run is aborted before signaling anything, to
+    /// show why.
+    fn matched(&self, process: &Process);
+
+    /// The signal would have been sent to the process, if this was not a dry run.
+    fn will_signal(&self, process: &Process, signal: Signal);
+
+    /// The process was signaled in the dry run that this one is compared with, but would not be
+    /// anymore.
+    fn would_no_longer_signal(&self, process: &ProcessDescription);
+
+    /// The waiting time ran out, and the next signal will be sent to the remaining processes.
+    fn escalating(&self, signal: Signal, is_final: bool);
+
+    /// The signal was sent to the process.
+    fn signaled(&self, process: &Process, signal: Signal);
+
+    /// The final signal of the escalation was sent to the process.
+    fn killed(&self, process: &Process, signal: Signal);
+
+    /// The process has exited, taking the given time after it was first signaled.
+    fn died(&self, process: &Process, termination_time: Option<Duration>);
+
+    /// The signal could not be sent to the process.
+    fn failed(&self, process: &Process, signal: Signal, error: &KillError);
+
+    /// Some processes are still being waited for. Reported whenever a process exits, and
+    /// periodically while nothing happens.
+    fn waiting(&self, remaining: usize, total: usize, elapsed: Duration);
+
+    /// The last waiting time ran out, and these processes are still alive.
+    fn timed_out(&self, processes: &[Process]);
+
+    /// The run is over.
+    fn finished(&self, summary: &Summary);
+
+    /// Makes sure everything reported so far has been written. Called at the end of every phase
+    /// of the run, like before waiting for processes to exit.
+    fn flush(&self);
+}
+
+/// Picks the reporter that renders the output mode of the options.
+pub fn for_options<'a>(options: &'a Options) -> Box<dyn Reporter + 'a> {
+    match options.output_mode {
+        // STDOUT is reserved for the summary.
+        OutputMode::Normal | OutputMode::Verbose if options.json_summary => {
+            Box::new(TtyReporter::new(options).with_output(io::stderr(), io::stderr()))
+        }
+        OutputMode::Normal | OutputMode::Verbose => Box::new(TtyReporter::new(options)),
+        OutputMode::Json => Box::new(JsonReporter::stdout()),
+        OutputMode::Quiet => Box::new(NullReporter),
+    }
+}
+
+/// Human-readable output on STDERR, with colors if enabled. Dry runs are shown on STDOUT.
+pub struct TtyReporter<'a> {
+    options: &'a Options,
+    clock: fn() -> SystemTime,
+    out: RefCell<Box<dyn Write + 'a>>,
+    err: RefCell<Box<dyn Write + 'a>>,
+}
+
+impl<'a> TtyReporter<'a> {
+    pub fn new(options: &'a Options) -> TtyReporter<'a> {
+        TtyReporter {
+            options,
+            clock: SystemTime::now,
+            out: RefCell::new(Box::new(io::stdout())),
+            err: RefCell::new(Box::new(io::stderr())),
+        }
+    }
+
+    /// Uses another source of time for --timestamps.
+    pub fn with_clock(mut self, clock: fn() -> SystemTime) -> TtyReporter<'a> {
+        self.clock = clock;
+        self
+    }
+
+    /// Writes what would go to STDOUT and STDERR somewhere else.
+    pub fn with_output<O, E>(mut self, out: O, err: E) -> TtyReporter<'a>
+    where
+        O: Write + 'a,
+        E: Write + 'a,
+    {
+        self.out = RefCell::new(Box::new(out));
+        self.err = RefCell::new(Box::new(err));
+        self
+    }
+
+    /// Starts every line with the current time, if --timestamps is given.
+    fn prefix(&self) -> String {
+        if self.options.timestamps {
+            format!("{} ", timestamp::rfc3339((self.clock)()))
+        } else {
+            String::new()
+        }
+    }
+
+    fn print_err(&self, line: &str) {
+        self.write_line(&self.err, line);
+    }
+
+    fn print_out(&self, line: &str) {
+        self.write_line(&self.out, line);
+    }
+
+    /// Writes the whole line at once. Formatting straight into STDERR would write it piece by
+    /// piece, and output from the processes being shut down could end up in the middle of it.
+    fn write_line(&self, writer: &RefCell<Box<dyn Write + 'a>>, line: &str) {
+        let line = format!("{}{}\n", self.prefix(), line);
+        // Like println!, but a closed terminal should not stop processes from being shut down.
+        let _ = writer.borrow_mut().write_all(line.as_bytes());
+    }
+
+    fn is_verbose(&self) -> bool {
+        self.options.output_mode.show_verbose()
+    }
+
+    fn describe(&self, process: &Process) -> String {
+        human_process_description(self.options, process)
+    }
+}
+
+impl<'a> Reporter for TtyReporter<'a> {
+    fn warning(&self, message: &str) {
+        self.print_err(&format!(
+            "{yellow}WARNING: {message}{reset}",
+            message = message,
+            yellow = self.options.colors.yellow(),
+            reset = self.options.colors.reset(),
+        ));
+    }
+
+    fn info(&self, message: &str) {
+        if self.is_verbose() {
+            self.print_err(message);
+        }
+    }
+
+    fn nothing_matched(&self) {
+        self.print_err("No processes matched.");
+    }
+
+    fn skipped(&self, process: &Process, reason: &str) {
+        if self.is_verbose() {
+            self.print_err(&format!(
+                "Skipping process {process}: {reason}",
+                process = self.describe(process),
+                reason = reason,
+            ));
+        }
+    }
+
+    fn refused(&self, process: &Process, reason: &str) {
+        self.print_err(&format!(
+            "{red}Refusing to signal{reset} {process}{red}; {reason}{reset}",
+            process = self.describe(process),
+            reason = reason,
+            red = self.options.colors.red(),
+            reset = self.options.colors.reset(),
+        ));
+    }
+
+    fn matched(&self, process: &Process) {
+        self.print_err(&format!(
+            "Matched process {process}",
+            process = self.describe(process)
+        ));
+    }
+
+    fn will_signal(&self, process: &Process, signal: Signal) {
+        self.print_out(&format!(
+            "Would have sent {signal} to process {process}",
+            signal = signal,
+            process = describe_process(self.options, &self.options.stdout_colors, process),
+        ));
+    }
+
+    fn would_no_longer_signal(&self, process: &ProcessDescription) {
+        self.print_out(&format!(
+            "Would no longer signal process {green}{pid}{reset} ({green}{name}{reset})",
+            pid = process.pid,
+            name = process.name,
+            green = self.options.colors.green(),
+            reset = self.options.colors.reset(),
+        ));
+    }
+
+    fn escalating(&self, signal: Signal, is_final: bool) {
+        if !self.is_verbose() {
+            return;
+        }
+
+        if is_final {
+            self.print_err(&format!(
+                "{magenta}Timeout reached. Forcefully shutting down processes.{reset}",
+                magenta = self.options.colors.magenta(),
+                reset = self.options.colors.reset()
+            ));
+        } else {
+            self.print_err(&format!(
+                "{yellow}Timeout reached. Escalating to {signal}.{reset}",
+                signal = signal,
+                yellow = self.options.colors.yellow(),
+                reset = self.options.colors.reset()
+            ));
+        }
+    }
+
+    fn signaled(&self, process: &Process, signal: Signal) {
+        if self.is_verbose() {
+            self.print_err(&format!(
+                "Sending {signal} to process {process}",
+                signal = signal,
+                process = self.describe(process),
+            ));
+        }
+    }
+
+    fn killed(&self, process: &Process, signal: Signal) {
+        if self.is_verbose() {
+            self.print_err(&format!(
+                "{magenta}Sending {signal} to process{reset} {process}",
+                signal = signal,
+                process = self.describe(process),
+                magenta = self.options.colors.magenta(),
+                reset = self.options.colors.reset(),
+            ));
+        }
+    }
+
+    fn died(&self, process: &Process, termination_time: Option<Duration>) {
+        if self.is_verbose() {
+            let after = termination_time
+                .map(|time| format!(" after {:.1}s", time.as_secs_f64()))
+                .unwrap_or_default();
+            self.print_err(&format!(
+                "{green}Process shut down{after}:{reset} {process}",
+                after = after,
+                process = self.describe(process),
+                green = self.options.colors.green(),
+                reset = self.options.colors.reset(),
+            ));
+        }
+    }
+
+    fn failed(&self, process: &Process, signal: Signal, error: &KillError) {
+        self.print_err(&format!(
+            "{red}Failed to send {signal} to{reset} {process}: {red}{error}{reset}",
+            signal = signal,
+            process = self.describe(process),
+            error = error,
+            red = self.options.colors.red(),
+            reset = self.options.colors.reset(),
+        ));
+    }
+
+    fn waiting(&self, remaining: usize, total: usize, elapsed: Duration) {
+        if self.is_verbose() {
+            self.print_err(&format!(
+                "Still waiting for {remaining}/{total} processes ({elapsed}s elapsed)",
+                remaining = remaining,
+                total = total,
+                elapsed = elapsed.as_secs(),
+            ));
+        }
+    }
+
+    fn timed_out(&self, processes: &[Process]) {
+        self.warning("Some processes are still alive.");
+        if self.is_verbose() {
+            for process in processes {
+                self.print_err(&format!(
+                    "Process {process}",
+                    process = self.describe(process)
+                ));
+            }
+        }
+    }
+
+    fn finished(&self, summary: &Summary) {
+        self.print_err(&summary.to_string());
+    }
+
+    fn flush(&self) {
+        let _ = self.out.borrow_mut().flush();
+        let _ = self.err.borrow_mut().flush();
+    }
+}
+
+/// One JSON object per line for every action taken on a process, written to STDOUT, a log file
+/// or a socket.
+pub struct JsonLinesReporter<W: Write> {
+    out: RefCell<W>,
+    timestamps: bool,
+    /// Writing failed once, so nothing more is written.
+    is_broken: Cell<bool>,
+}
+
+/// Writes to STDOUT, for --output json.
+pub type JsonReporter = JsonLinesReporter<io::Stdout>;
+
+/// Appends to a file, with a timestamp. Used for auditing, next to the reporter for the console.
+pub type LogFileReporter = JsonLinesReporter<File>;
+
+/// Streams to a Unix socket, so another program can follow a run as it happens.
+pub type SocketReporter = JsonLinesReporter<UnixStream>;
+
+/// How long writing to the events socket may block before giving up on it, so a listener that
+/// stops reading cannot hold up the shutdown.
+const SOCKET_WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+
+impl<W: Write> JsonLinesReporter<W> {
+    pub fn new(out: W) -> JsonLinesReporter<W> {
+        JsonLinesReporter {
+            out: RefCell::new(out),
+            timestamps: false,
+            is_broken: Cell::new(false),
+        }
+    }
+
+    /// Adds the current time to every object.
+    pub fn with_timestamps(mut self) -> JsonLinesReporter<W> {
+        self.timestamps = true;
+        self
+    }
+
+    fn emit(&self, event: Event) {
+        let event = if self.timestamps {
+            event.timestamp(SystemTime::now())
+        } else {
+            event
+        };
+        // Failing to write should not stop processes from being shut down, and trying again
+        // would only waste more time.
+        if !self.is_broken.get() && writeln!(self.out.borrow_mut(), "{}", event.to_json()).is_err()
+        {
+            self.is_broken.set(true);
+        }
+    }
+}
+
+impl JsonReporter {
+    pub fn stdout() -> JsonReporter {
+        JsonLinesReporter::new(io::stdout())
+    }
+}
+
+impl LogFileReporter {
+    pub fn open(path: &Path) -> io::Result<LogFileReporter> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(JsonLinesReporter::new(file).with_timestamps())
+    }
+}
+
+impl SocketReporter {
+    pub fn connect(path: &Path) -> io::Result<SocketReporter> {
+        let stream = UnixStream::connect(path)?;
+        stream.set_write_timeout(Some(SOCKET_WRITE_TIMEOUT))?;
+        Ok(JsonLinesReporter::new(stream))
+    }
+}
+
+impl<W: Write> Reporter for JsonLinesReporter<W> {
+    fn warning(&self, _message: &str) {}
+
+    fn info(&self, _message: &str) {}
+
+    fn nothing_matched(&self) {}
+
+    fn skipped(&self, _process: &Process, _reason: &str) {}
+
+    fn refused(&self, _process: &Process, _reason: &str) {}
+
+    fn matched(&self, _process: &Process) {}
+
+    fn will_signal(&self, process: &Process, signal: Signal) {
+        self.emit(Event::new(Outcome::WouldSend, process).signal(signal));
+    }
+
+    fn would_no_longer_signal(&self, process: &ProcessDescription) {
+        self.emit(Event::described(Outcome::WouldNoLongerSend, process));
+    }
+
+    fn escalating(&self, _signal: Signal, _is_final: bool) {}
+
+    fn signaled(&self, process: &Process, signal: Signal) {
+        self.emit(Event::new(Outcome::Sent, process).signal(signal));
+    }
+
+    fn killed(&self, process: &Process, signal: Signal) {
+        self.emit(Event::new(Outcome::Killed, process).signal(signal));
+    }
+
+    fn died(&self, process: &Process, termination_time: Option<Duration>) {
+        let event = Event::new(Outcome::Died, process);
+        match termination_time {
+            Some(time) => self.emit(event.termination_time(time)),
+            None => self.emit(event),
+        }
+    }
+
+    fn failed(&self, process: &Process, signal: Signal, error: &KillError) {
+        self.emit(
+            Event::new(Outcome::Failed, process)
+                .signal(signal)
+                .error(error),
+        );
+    }
+
+    fn waiting(&self, _remaining: usize, _total: usize, _elapsed: Duration) {}
+
+    fn timed_out(&self, processes: &[Process]) {
+        for process in processes {
+            self.emit(Event::new(Outcome::Survived, process));
+        }
+    }
+
+    fn finished(&self, _summary: &Summary) {}
+
+    fn flush(&self) {
+        let _ = self.out.borrow_mut().flush();
+    }
+}
+
+/// Passes everything on to several reporters, in order.
+pub struct Tee<'a> {
+    reporters: Vec<Box<dyn Reporter + 'a>>,
+}
+
+impl<'a> Tee<'a> {
+    pub fn new(reporters: Vec<Box<dyn Reporter + 'a>>) -> Tee<'a> {
+        Tee { reporters }
+    }
+}
+
+impl<'a> Reporter for Tee<'a> {
+    fn warning(&self, message: &str) {
+        self.reporters.iter().for_each(|r| r.warning(message));
+    }
+
+    fn info(&self, message: &str) {
+        self.reporters.iter().for_each(|r| r.info(message));
+    }
+
+    fn nothing_matched(&self) {
+        self.reporters.iter().for_each(|r| r.nothing_matched());
+    }
+
+    fn skipped(&self, process: &Process, reason: &str) {
+        self.reporters
+            .iter()
+            .for_each(|r| r.skipped(process, reason));
+    }
+
+    fn refused(&self, process: &Process, reason: &str) {
+        self.reporters
+            .iter()
+            .for_each(|r| r.refused(process, reason));
+    }
+
+    fn matched(&self, process: &Process) {
+        self.reporters.iter().for_each(|r| r.matched(process));
+    }
+
+    fn will_signal(&self, process: &Process, signal: Signal) {
+        self.reporters
+            .iter()
+            .for_each(|r| r.will_signal(process, signal));
+    }
+
+    fn would_no_longer_signal(&self, process: &ProcessDescription) {
+        self.reporters
+            .iter()
+            .for_each(|r| r.would_no_longer_signal(process));
+    }
+
+    fn escalating(&self, signal: Signal, is_final: bool) {
+        self.reporters
+            .iter()
+            .for_each(|r| r.escalating(signal, is_final));
+    }
+
+    fn signaled(&self, process: &Process, signal: Signal) {
+        self.reporters
+            .iter()
+            .for_each(|r| r.signaled(process, signal));
+    }
+
+    fn killed(&self, process: &Process, signal: Signal) {
+        self.reporters
+            .iter()
+            .for_each(|r| r.killed(process, signal));
+    }
+
+    fn died(&self, process: &Process, termination_time: Option<Duration>) {
+        self.reporters
+            .iter()
+            .for_each(|r| r.died(process, termination_time));
+    }
+
+    fn failed(&self, process: &Process, signal: Signal, error: &KillError) {
+        self.reporters
+            .iter()
+            .for_each(|r| r.failed(process, signal, error));
+    }
+
+    fn waiting(&self, remaining: usize, total: usize, elapsed: Duration) {
+        self.reporters
+            .iter()
+            .for_each(|r| r.waiting(remaining, total, elapsed));
+    }
+
+    fn timed_out(&self, processes: &[Process]) {
+        self.reporters.iter().for_each(|r| r.timed_out(processes));
+    }
+
+    fn finished(&self, summary: &Summary) {
+        self.reporters.iter().for_each(|r| r.finished(summary));
+    }
+
+    fn flush(&self) {
+        self.reporters.iter().for_each(|r| r.flush());
+    }
+}
+
+/// Ignores everything.
+pub struct NullReporter;
+
+impl Reporter for NullReporter {
+    fn warning(&self, _message: &str) {}
+    fn info(&self, _message: &str) {}
+    fn nothing_matched(&self) {}
+    fn skipped(&self, _process: &Process, _reason: &str) {}
+    fn refused(&self, _process: &Process, _reason: &str) {}
+    fn matched(&self, _process: &Process) {}
+    fn will_signal(&self, _process: &Process, _signal: Signal) {}
+    fn would_no_longer_signal(&self, _process: &ProcessDescription) {}
+    fn escalating(&self, _signal: Signal, _is_final: bool) {}
+    fn signaled(&self, _process: &Process, _signal: Signal) {}
+    fn killed(&self, _process: &Process, _signal: Signal) {}
+    fn died(&self, _process: &Process, _termination_time: Option<Duration>) {}
+    fn failed(&self, _process: &Process, _signal: Signal, _error: &KillError) {}
+    fn waiting(&self, _remaining: usize, _total: usize, _elapsed: Duration) {}
+    fn timed_out(&self, _processes: &[Process]) {}
+    fn finished(&self, _summary: &Summary) {}
+    fn flush(&self) {}
+}
+
+/// Explains what it means that the process is still alive after the terminate signal, to help
+/// finding out why an application does not shut down.
+pub fn survival_note(options: &Options, process: &Process, signal: Signal) -> String {
+    let reason = if signal.is_catchable() {
+        format!(
+            "it could have handled {}, but ignored it or did not finish shutting down in time",
+            signal
+        )
+    } else {
+        format!(
+            "{} cannot be caught, so it never got the chance to shut down by itself",
+            signal
+        )
+    };
+
+    format!(
+        "Still alive: {process}; {reason}.",
+        process = human_process_description(options, process),
+        reason = reason,
+    )
+}
+
+pub fn human_process_description(options: &Options, process: &Process) -> String {
+    describe_process(options, &options.colors, process)
+}
+
+fn describe_process(options: &Options, colors: &Colors, process: &Process) -> String {
+    let group = if options.process_group {
+        format!(", pgid {}", process.pgid())
+    } else {
+        String::new()
+    };
+
+    // Show the full path of the executable when matching on more than the basename, so it is
+    // visible which installation of a program matched.
+    let name = match (options.match_mode, process.exe()) {
+        (MatchMode::Basename, _) | (_, None) => process.name().to_string(),
+        (_, Some(exe)) => exe.to_string_lossy().into_owned(),
+    };
+
+    match options.match_mode {
+        MatchMode::Basename | MatchMode::ExePath => format!(
+            "{green}{pid}{reset} ({green}{name}{reset}{group})",
+            pid = process.pid(),
+            name = name,
+            group = group,
+            green = colors.green(),
+            reset = colors.reset()
+        ),
+        MatchMode::Commandline | MatchMode::Args => format!(
+            "{green}{pid}{reset} ({green}{name}{reset}{group}): {faded}{cmdline}{reset}",
+            pid = process.pid(),
+            name = name,
+            group = group,
+            cmdline = process.commandline(),
+            green = colors.green(),
+            faded = colors.faded(),
+            reset = colors.reset(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{self, Value};
+    use std::fs;
+    use std::time::UNIX_EPOCH;
+    use structopt::StructOpt;
+    use test_dir::TestDir;
+    use CliOptions;
+
+    fn options(args: &[&str]) -> Options {
+        let mut all_args = vec!["graceful-shutdown"];
+        all_args.extend_from_slice(args);
+        Options::from(CliOptions::from_iter(all_args))
+    }
+
+    fn fixed_clock() -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(1_528_034_875_123)
+    }
+
+    #[test]
+    fn it_prefixes_lines_with_timestamps_when_asked_to() {
+        let with = options(&["--timestamps"]);
+        let reporter = TtyReporter::new(&with).with_clock(fixed_clock);
+        assert_eq!(reporter.prefix(), "2018-06-03T14:07:55.123Z ");
+
+        let without = options(&[]);
+        let reporter = TtyReporter::new(&without).with_clock(fixed_clock);
+        assert_eq!(reporter.prefix(), "");
+    }
+
+    /// Remembers every single write, to see how lines were split up.
+    struct Writes<'b>(&'b RefCell<Vec<String>>);
+
+    impl<'b> Write for Writes<'b> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0
+                .borrow_mut()
+                .push(String::from_utf8_lossy(buf).into_owned());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn it_writes_whole_lines_to_the_output() {
+        let options = options(&["--verbose", "--timestamps", "--color", "always"]);
+        let process = Process::fake(42, "server", "server --port 80");
+        let term: Signal = "TERM".parse().unwrap();
+        let out = RefCell::new(Vec::new());
+        let err = RefCell::new(Vec::new());
+        {
+            let reporter = TtyReporter::new(&options)
+                .with_clock(fixed_clock)
+                .with_output(Writes(&out), Writes(&err));
+            reporter.warning("Careful");
+            reporter.signaled(&process, term);
+            reporter.will_signal(&process, term);
+            reporter.flush();
+        }
+
+        let out = out.into_inner();
+        let err = err.into_inner();
+        assert_eq!(out.len(), 1);
+        assert_eq!(err.len(), 2);
+        for line in out.iter().chain(err.iter()) {
+            assert!(line.starts_with("2018-06-03T14:07:55.123Z "), "{:?}", line);
+            assert!(line.ends_with('\n'), "{:?}", line);
+            assert_eq!(line.matches('\n').count(), 1, "{:?}", line);
+        }
+        assert!(err[0].contains("WARNING: Careful"));
+        assert!(err[1].contains("42"));
+    }
+
+    #[test]
+    fn it_describes_processes_according_to_the_match_mode() {
+        let process =
+            Process::fake(42, "server", "server --port 80").with_exe("/opt/app/bin/server");
+        let describe = |args: &[&str]| {
+            let mut args = args.to_vec();
+            args.extend_from_slice(&["--color", "never"]);
+            human_process_description(&options(&args), &process)
+        };
+
+        assert_eq!(describe(&[]), "42 (server)");
+        assert_eq!(describe(&["--match-path"]), "42 (/opt/app/bin/server)");
+        assert_eq!(
+            describe(&["--whole-command"]),
+            "42 (/opt/app/bin/server): server --port 80"
+        );
+        assert_eq!(
+            describe(&["--match-args"]),
+            "42 (/opt/app/bin/server): server --port 80"
+        );
+    }
+
+    #[test]
+    fn it_explains_why_processes_survived_the_terminate_signal() {
+        let process = Process::fake(42, "server", "server --port 80");
+        let options = options(&["--color", "never"]);
+
+        assert_eq!(
+            survival_note(&options, &process, "TERM".parse().unwrap()),
+            "Still alive: 42 (server); it could have handled TERM, but ignored it or did not finish shutting down in time."
+        );
+        assert_eq!(
+            survival_note(&options, &process, "STOP".parse().unwrap()),
+            "Still alive: 42 (server); STOP cannot be caught, so it never got the chance to shut down by itself."
+        );
+    }
+
+    #[test]
+    fn it_falls_back_to_the_name_without_an_executable_path() {
+        let process = Process::fake(42, "server", "server --port 80");
+        let options = options(&["--match-path", "--color", "never"]);
+
+        assert_eq!(human_process_description(&options, &process), "42 (server)");
+    }
+
+    #[test]
+    fn it_appends_timestamped_events_to_the_log_file() {
+        let dir = TestDir::new("log-file");
+        let path = dir.path().join("events.log");
+        fs::write(&path, "{\"outcome\":\"earlier\"}\n").unwrap();
+
+        let process = Process::fake(42, "nginx", "nginx -g daemon");
+        let signal: Signal = "term".parse().unwrap();
+        {
+            let reporter = LogFileReporter::open(&path).expect("Failed to open log");
+            reporter.warning("Not logged");
+            reporter.signaled(&process, signal);
+            reporter.died(&process, Some(Duration::from_millis(1500)));
+        }
+
+        let contents = fs::read_to_string(&path).unwrap();
+
+        let lines: Vec<Value> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["outcome"], "earlier");
+        assert_eq!(lines[1]["outcome"], "sent");
+        assert_eq!(lines[1]["pid"], 42);
+        assert_eq!(lines[1]["name"], "nginx");
+        assert_eq!(lines[1]["signal"], "SIGTERM");
+        assert_eq!(lines[2]["outcome"], "died");
+        assert_eq!(lines[2]["termination_ms"], 1500);
+        for line in &lines[1..] {
+            assert!(line["timestamp"].as_str().unwrap().ends_with('Z'));
+        }
+    }
+
+    #[test]
+    fn it_writes_events_without_timestamps_unless_asked_to() {
+        let process = Process::fake(42, "nginx", "nginx -g daemon");
+        let signal: Signal = "kill".parse().unwrap();
+        let mut output = Vec::new();
+        {
+            let reporter = JsonLinesReporter::new(&mut output);
+            reporter.info("Not written");
+            reporter.killed(&process, signal);
+        }
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"outcome\":\"killed\",\"pid\":42,\"name\":\"nginx\",\"cmdline\":\"nginx -g daemon\",\"signal\":\"SIGKILL\"}\n"
+        );
+    }
+
+    #[test]
+    fn it_fails_to_open_log_files_in_missing_directories() {
+        assert!(LogFileReporter::open(Path::new("/nonexistent/graceful-shutdown.log")).is_err());
+    }
+
+    #[test]
+    fn it_streams_events_to_a_unix_socket() {
+        use std::io::{BufRead, BufReader};
+        use std::os::unix::net::UnixListener;
+
+        let dir = TestDir::new("socket");
+        let path = dir.path().join("events.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let process = Process::fake(42, "nginx", "nginx -g daemon");
+        let signal: Signal = "term".parse().unwrap();
+        {
+            let reporter = SocketReporter::connect(&path).expect("Failed to connect");
+            reporter.info("Not streamed");
+            reporter.signaled(&process, signal);
+            reporter.flush();
+        }
+
+        let (stream, _) = listener.accept().unwrap();
+        let lines: Vec<Value> = BufReader::new(stream)
+            .lines()
+            .map(|line| serde_json::from_str(&line.unwrap()).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["outcome"], "sent");
+        assert_eq!(lines[0]["pid"], 42);
+        assert_eq!(lines[0]["signal"], "SIGTERM");
+    }
+
+    #[test]
+    fn it_gives_up_on_sockets_nobody_reads_from() {
+        use std::os::unix::net::UnixListener;
+
+        let dir = TestDir::new("stalled-socket");
+        let path = dir.path().join("events.sock");
+        let _listener = UnixListener::bind(&path).unwrap();
+
+        // Far more than fits in the buffer of the socket. Without giving up, this would block
+        // forever.
+        let process = Process::fake(42, "nginx", "nginx -g daemon");
+        let signal: Signal = "term".parse().unwrap();
+        let reporter = SocketReporter::connect(&path).expect("Failed to connect");
+        for _ in 0..100_000 {
+            reporter.signaled(&process, signal);
+        }
+        reporter.flush();
+    }
+}
@@ -0,0 +1,181 @@
+use nix::unistd::Pid;
+use processes::{KillError, Process};
+use signal::Signal;
+
+/// Delivers signals to processes and checks if they are still running.
+///
+/// Everything that touches real processes during a run goes through this, so the run logic can
+/// be tested without spawning and killing anything.
+pub trait SignalSender {
+    fn send(&self, process: &Process, signal: Signal) -> Result<(), KillError>;
+
+    fn is_alive(&self, process: &Process) -> bool;
+
+    fn threads(&self, process: &Process) -> Vec<Pid>;
+
+    fn send_to_thread(&self, process: &Process, tid: Pid, signal: Signal) -> Result<(), KillError>;
+}
+
+/// Signals real processes.
+pub struct NixSender;
+
+impl SignalSender for NixSender {
+    fn send(&self, process: &Process, signal: Signal) -> Result<(), KillError> {
+        process.send(signal)
+    }
+
+    fn is_alive(&self, process: &Process) -> bool {
+        process.is_alive()
+    }
+
+    fn threads(&self, process: &Process) -> Vec<Pid> {
+        process.threads()
+    }
+
+    fn send_to_thread(&self, process: &Process, tid: Pid, signal: Signal) -> Result<(), KillError> {
+        process.send_to_thread(tid, signal)
+    }
+}
+
+#[cfg(test)]
+pub use self::mock::MockSender;
+
+#[cfg(test)]
+mod mock {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::{HashMap, HashSet};
+
+    /// Pretends to signal processes, remembering every signal sent.
+    ///
+    /// Every process is alive until it receives the signal it is set up to exit on. Processes
+    /// without one never exit.
+    #[derive(Default)]
+    pub struct MockSender {
+        exits_on: HashMap<Pid, Signal>,
+        /// The error, and how many signals are sent to the process before it starts failing.
+        errors: HashMap<Pid, (usize, KillError)>,
+        delivered: RefCell<HashMap<Pid, usize>>,
+        threads: HashMap<Pid, Vec<Pid>>,
+        exited: RefCell<HashSet<Pid>>,
+        checks: RefCell<usize>,
+        sent: RefCell<Vec<String>>,
+    }
+
+    impl MockSender {
+        pub fn new() -> MockSender {
+            MockSender::default()
+        }
+
+        /// Makes the process exit as soon as it receives the signal.
+        pub fn exits_on(mut self, pid: i32, signal: &str) -> MockSender {
+            let signal = signal.parse().expect("Invalid signal in test");
+            self.exits_on.insert(Pid::from_raw(pid), signal);
+            self
+        }
+
+        /// Makes every signal to the process fail.
+        pub fn fails_with(self, pid: i32, error: KillError) -> MockSender {
+            self.fails_after(pid, 0, error)
+        }
+
+        /// Makes every signal to the process fail once the first ones were sent.
+        pub fn fails_after(mut self, pid: i32, sends: usize, error: KillError) -> MockSender {
+            self.errors.insert(Pid::from_raw(pid), (sends, error));
+            self
+        }
+
+        /// Gives the process more threads than the main thread, which has the same ID as the
+        /// process.
+        pub fn with_threads(mut self, pid: i32, tids: &[i32]) -> MockSender {
+            let mut threads = vec![Pid::from_raw(pid)];
+            threads.extend(tids.iter().map(|&tid| Pid::from_raw(tid)));
+            self.threads.insert(Pid::from_raw(pid), threads);
+            self
+        }
+
+        /// Every signal sent so far, like "100: TERM", or "100/101: TERM" for a thread.
+        pub fn sent(&self) -> Vec<String> {
+            self.sent.borrow().clone()
+        }
+
+        /// How many times it was checked if a process is alive.
+        pub fn checks(&self) -> usize {
+            *self.checks.borrow()
+        }
+
+        fn has_exited(&self, process: &Process) -> bool {
+            self.exited.borrow().contains(&process.pid())
+        }
+
+        fn error_for(&self, pid: Pid) -> Option<KillError> {
+            let delivered = self.delivered.borrow().get(&pid).cloned().unwrap_or(0);
+            match self.errors.get(&pid) {
+                Some(&(sends, ref error)) if delivered >= sends => Some(error.clone()),
+                _ => None,
+            }
+        }
+    }
+
+    impl SignalSender for MockSender {
+        fn send(&self, process: &Process, signal: Signal) -> Result<(), KillError> {
+            if let Some(error) = self.error_for(process.pid()) {
+                return Err(error);
+            }
+            if self.has_exited(process) {
+                return Err(KillError::DoesNotExist);
+            }
+
+            *self
+                .delivered
+                .borrow_mut()
+                .entry(process.pid())
+                .or_insert(0) += 1;
+            self.sent
+                .borrow_mut()
+                .push(format!("{}: {}", process.pid(), signal));
+            if self.exits_on.get(&process.pid()) == Some(&signal) {
+                self.exited.borrow_mut().insert(process.pid());
+            }
+            Ok(())
+        }
+
+        fn is_alive(&self, process: &Process) -> bool {
+            *self.checks.borrow_mut() += 1;
+            !self.has_exited(process)
+        }
+
+        fn threads(&self, process: &Process) -> Vec<Pid> {
+            if self.has_exited(process) {
+                return Vec::new();
+            }
+            self.threads
+                .get(&process.pid())
+                .cloned()
+                .unwrap_or_else(|| vec![process.pid()])
+        }
+
+        fn send_to_thread(
+            &self,
+            process: &Process,
+            tid: Pid,
+            signal: Signal,
+        ) -> Result<(), KillError> {
+            if let Some(error) = self.error_for(process.pid()) {
+                return Err(error);
+            }
+            if self.has_exited(process) {
+                return Err(KillError::DoesNotExist);
+            }
+
+            self.sent
+                .borrow_mut()
+                .push(format!("{}/{}: {}", process.pid(), tid, signal));
+            // Like with real processes, a fatal signal to any thread ends the whole process.
+            if self.exits_on.get(&process.pid()) == Some(&signal) {
+                self.exited.borrow_mut().insert(process.pid());
+            }
+            Ok(())
+        }
+    }
+}
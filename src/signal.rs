@@ -3,19 +3,57 @@ use std::fmt;
 use std::str::FromStr;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
-pub struct Signal(NixSignal);
+pub enum Signal {
+    Standard(NixSignal),
+    /// A real-time signal, as an offset from SIGRTMIN.
+    RealTime(i32),
+}
+
+/// Historical names that other tools still accept, with the signal they mean on Linux. The names
+/// are given without any SIG prefix.
+const ALIASES: &[(&str, NixSignal)] = &[
+    ("IOT", NixSignal::SIGABRT),
+    ("CLD", NixSignal::SIGCHLD),
+    ("POLL", NixSignal::SIGIO),
+    ("UNUSED", NixSignal::SIGSYS),
+];
+
+/// Number of the first real-time signal. The C library reserves some of the kernel's real-time
+/// signals for itself, so this must be asked at runtime.
+fn rtmin() -> i32 {
+    ::libc::SIGRTMIN()
+}
+
+/// Offset of the last real-time signal from SIGRTMIN.
+fn rtmax_offset() -> i32 {
+    ::libc::SIGRTMAX() - rtmin()
+}
 
 impl Signal {
     pub fn iterator() -> impl Iterator<Item = Signal> {
-        NixSignal::iterator().map(Signal)
+        NixSignal::iterator()
+            .map(Signal::Standard)
+            .chain((0..=rtmax_offset()).map(Signal::RealTime))
+    }
+
+    /// Every alias, without the SIG prefix, together with the signal it stands for.
+    pub fn aliases() -> impl Iterator<Item = (&'static str, Signal)> {
+        ALIASES
+            .iter()
+            .map(|&(alias, signal)| (alias, Signal::Standard(signal)))
     }
 
     pub fn name(self) -> String {
         format!("SIG{}", self.basename())
     }
 
-    pub fn basename(self) -> &'static str {
-        match self.0 {
+    pub fn basename(self) -> String {
+        let signal = match self {
+            Signal::Standard(signal) => signal,
+            Signal::RealTime(offset) => return real_time_basename(offset),
+        };
+
+        let basename = match signal {
             NixSignal::SIGABRT => "ABRT",
             NixSignal::SIGALRM => "ALRM",
             NixSignal::SIGHUP => "HUP",
@@ -47,35 +85,96 @@ impl Signal {
             NixSignal::SIGIO => "IO",
             NixSignal::SIGPWR => "PWR",
             NixSignal::SIGSYS => "SYS",
-        }
+        };
+        basename.to_owned()
+    }
+
+    /// Checks if processes can handle this signal themselves. SIGKILL and SIGSTOP are never seen
+    /// by the process, so they cannot be used for a graceful shutdown.
+    pub fn is_catchable(self) -> bool {
+        !matches!(
+            self,
+            Signal::Standard(NixSignal::SIGKILL) | Signal::Standard(NixSignal::SIGSTOP)
+        )
+    }
+
+    /// Checks if the signal always ends the process. Only SIGKILL does; every other signal can be
+    /// caught or ignored, or like SIGSTOP only pauses the process.
+    pub fn is_forceful(self) -> bool {
+        self == Signal::Standard(NixSignal::SIGKILL)
     }
 
     pub fn number(self) -> i32 {
-        self.0 as i32
+        match self {
+            Signal::Standard(signal) => signal as i32,
+            Signal::RealTime(offset) => rtmin() + offset,
+        }
     }
 }
 
-impl fmt::Display for Signal {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.basename().fmt(f)
+/// Names real-time signals the same way as bash does: the lower half counts up from RTMIN, and the
+/// upper half counts down from RTMAX.
+fn real_time_basename(offset: i32) -> String {
+    let max = rtmax_offset();
+    if offset == 0 {
+        String::from("RTMIN")
+    } else if offset == max {
+        String::from("RTMAX")
+    } else if offset <= max / 2 {
+        format!("RTMIN+{}", offset)
+    } else {
+        format!("RTMAX-{}", max - offset)
     }
 }
 
-impl From<Signal> for NixSignal {
-    fn from(signal: Signal) -> NixSignal {
-        signal.0
+/// Parses names like "RTMIN+3" or "RTMAX-1" (without any SIG prefix) into real-time signals.
+fn parse_real_time(basename: &str) -> Result<Signal, ParseError> {
+    // The direction is given by the name, so the number itself must not have a sign. Numbers too
+    // large for an offset are out of range anyway.
+    let parse_offset = |offset: &str| {
+        if offset.is_empty() || !offset.bytes().all(|byte| byte.is_ascii_digit()) {
+            return Err(ParseError::UnknownSignalName);
+        }
+        offset
+            .parse::<i32>()
+            .map_err(|_| ParseError::RealTimeOutOfRange)
+    };
+
+    let offset = if let Some(rest) = basename.strip_prefix("RTMIN") {
+        match rest.strip_prefix('+') {
+            Some(offset) => parse_offset(offset)?,
+            None if rest.is_empty() => 0,
+            None => return Err(ParseError::UnknownSignalName),
+        }
+    } else if let Some(rest) = basename.strip_prefix("RTMAX") {
+        match rest.strip_prefix('-') {
+            Some(offset) => rtmax_offset()
+                .checked_sub(parse_offset(offset)?)
+                .ok_or(ParseError::RealTimeOutOfRange)?,
+            None if rest.is_empty() => rtmax_offset(),
+            None => return Err(ParseError::UnknownSignalName),
+        }
+    } else {
+        return Err(ParseError::UnknownSignalName);
+    };
+
+    if (0..=rtmax_offset()).contains(&offset) {
+        Ok(Signal::RealTime(offset))
+    } else {
+        Err(ParseError::RealTimeOutOfRange)
     }
 }
 
-impl From<Signal> for Option<NixSignal> {
-    fn from(signal: Signal) -> Option<NixSignal> {
-        Some(signal.0)
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.basename().fmt(f)
     }
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum ParseError {
     UnknownSignalName,
+    RealTimeOutOfRange,
 }
 
 impl FromStr for Signal {
@@ -100,7 +199,12 @@ impl FromStr for Signal {
             }
         }
 
-        Err(ParseError::UnknownSignalName)
+        let basename = upper_sig.strip_prefix("SIG").unwrap_or(&upper_sig);
+        if let Some((_, signal)) = Signal::aliases().find(|&(alias, _)| alias == basename) {
+            return Ok(signal);
+        }
+
+        parse_real_time(basename)
     }
 }
 
@@ -111,20 +215,48 @@ mod tests {
     #[test]
     fn it_parses_strings_with_basename() {
         let sig: Signal = "kiLL".parse().expect("Failed to parse");
-        assert_eq!(sig, Signal(NixSignal::SIGKILL));
+        assert_eq!(sig, Signal::Standard(NixSignal::SIGKILL));
     }
 
     #[test]
     fn it_parses_strings_with_name() {
         let sig: Signal = "SiGkiLL".parse().expect("Failed to parse");
-        assert_eq!(sig, Signal(NixSignal::SIGKILL));
+        assert_eq!(sig, Signal::Standard(NixSignal::SIGKILL));
     }
 
     #[test]
     fn it_parses_strings_with_signal_number() {
-        let string = Signal(NixSignal::SIGKILL).number().to_string();
+        let string = Signal::Standard(NixSignal::SIGKILL).number().to_string();
         let sig: Signal = string.parse().expect("Failed to parse");
-        assert_eq!(sig, Signal(NixSignal::SIGKILL));
+        assert_eq!(sig, Signal::Standard(NixSignal::SIGKILL));
+    }
+
+    #[test]
+    fn it_parses_signal_numbers_with_a_plus_sign() {
+        assert_eq!("+15".parse(), Ok(Signal::Standard(NixSignal::SIGTERM)));
+    }
+
+    #[test]
+    fn it_parses_aliases() {
+        let expected = [
+            ("IOT", "ABRT"),
+            ("CLD", "CHLD"),
+            ("POLL", "IO"),
+            ("UNUSED", "SYS"),
+        ];
+        assert_eq!(Signal::aliases().count(), expected.len());
+
+        for &(alias, basename) in &expected {
+            let signal: Signal = basename.parse().unwrap();
+            assert_eq!(alias.parse(), Ok(signal), "{} should be {}", alias, basename);
+            assert_eq!(format!("SIG{}", alias).parse(), Ok(signal));
+            assert_eq!(alias.to_lowercase().parse(), Ok(signal));
+        }
+
+        for (alias, signal) in Signal::aliases() {
+            assert_eq!(alias.parse(), Ok(signal));
+            assert_eq!(signal.basename().parse(), Ok(signal));
+        }
     }
 
     #[test]
@@ -143,6 +275,90 @@ mod tests {
         );
     }
 
+    #[test]
+    fn it_parses_real_time_signals() {
+        let max = rtmax_offset();
+
+        assert_eq!("rtmin".parse(), Ok(Signal::RealTime(0)));
+        assert_eq!("SIGRTMIN+3".parse(), Ok(Signal::RealTime(3)));
+        assert_eq!("RTMAX".parse(), Ok(Signal::RealTime(max)));
+        assert_eq!("sigrtmax-2".parse(), Ok(Signal::RealTime(max - 2)));
+        assert_eq!((rtmin() + 5).to_string().parse(), Ok(Signal::RealTime(5)));
+
+        assert_eq!(Signal::RealTime(0).number(), ::libc::SIGRTMIN());
+        assert_eq!(Signal::RealTime(max).number(), ::libc::SIGRTMAX());
+    }
+
+    #[test]
+    fn it_renders_real_time_signals() {
+        let max = rtmax_offset();
+
+        assert_eq!(Signal::RealTime(0).basename(), "RTMIN");
+        assert_eq!(Signal::RealTime(1).basename(), "RTMIN+1");
+        assert_eq!(Signal::RealTime(max - 1).basename(), "RTMAX-1");
+        assert_eq!(Signal::RealTime(max).basename(), "RTMAX");
+        assert_eq!(Signal::RealTime(3).to_string(), "RTMIN+3");
+    }
+
+    #[test]
+    fn it_does_not_parse_out_of_range_real_time_signals() {
+        let too_far = (rtmax_offset() + 1).to_string();
+
+        assert_eq!(
+            format!("RTMIN+{}", too_far).parse::<Signal>(),
+            Err(ParseError::RealTimeOutOfRange)
+        );
+        assert_eq!(
+            format!("RTMAX-{}", too_far).parse::<Signal>(),
+            Err(ParseError::RealTimeOutOfRange)
+        );
+        assert_eq!(
+            "RTMIN-1".parse::<Signal>(),
+            Err(ParseError::UnknownSignalName)
+        );
+        assert_eq!(
+            "RTMAX+1".parse::<Signal>(),
+            Err(ParseError::UnknownSignalName)
+        );
+        assert_eq!(
+            "RTMIN+x".parse::<Signal>(),
+            Err(ParseError::UnknownSignalName)
+        );
+    }
+
+    #[test]
+    fn it_does_not_overflow_on_huge_real_time_offsets() {
+        for name in &["RTMAX--2147483648", "RTMIN+-1", "RTMAX-+1", "RTMIN+", "RTMAX-"] {
+            assert_eq!(
+                name.parse::<Signal>(),
+                Err(ParseError::UnknownSignalName),
+                "{}",
+                name
+            );
+        }
+        for name in &["RTMAX-2147483647", "RTMIN+2147483647", "RTMIN+99999999999"] {
+            assert_eq!(
+                name.parse::<Signal>(),
+                Err(ParseError::RealTimeOutOfRange),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn it_knows_which_signals_can_be_caught() {
+        for name in &["TERM", "INT", "HUP", "QUIT", "USR1", "CONT", "RTMIN+2"] {
+            let signal: Signal = name.parse().unwrap();
+            assert!(signal.is_catchable(), "{} should be catchable", name);
+        }
+
+        for name in &["KILL", "STOP"] {
+            let signal: Signal = name.parse().unwrap();
+            assert!(!signal.is_catchable(), "{} should not be catchable", name);
+        }
+    }
+
     #[test]
     fn it_roundtrips_all_signals_parsing() {
         for signal in Signal::iterator() {
@@ -0,0 +1,55 @@
+use std::fmt;
+
+/// Counts what happened to the processes during a run.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    /// Processes that exited after getting the terminate signal.
+    pub terminated: usize,
+    /// Processes that had to be sent the kill signal.
+    pub killed: usize,
+    /// Processes that were still alive when giving up, without being killed.
+    pub alive: usize,
+    /// Processes that could not be signaled.
+    pub failed: usize,
+    /// Processes that were matched, but never signaled.
+    pub skipped: usize,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} terminated, {} killed, {} still alive, {} failed, {} skipped",
+            self.terminated, self.killed, self.alive, self.failed, self.skipped,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_formats_empty_summaries() {
+        assert_eq!(
+            Summary::default().to_string(),
+            "0 terminated, 0 killed, 0 still alive, 0 failed, 0 skipped",
+        );
+    }
+
+    #[test]
+    fn it_formats_all_counters() {
+        let summary = Summary {
+            terminated: 3,
+            killed: 1,
+            alive: 2,
+            failed: 1,
+            skipped: 4,
+        };
+
+        assert_eq!(
+            summary.to_string(),
+            "3 terminated, 1 killed, 2 still alive, 1 failed, 4 skipped",
+        );
+    }
+}
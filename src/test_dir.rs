@@ -0,0 +1,31 @@
+use nix::unistd::Pid;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// An empty temporary directory for tests that need real files. It is removed again when dropped.
+///
+/// Tests run in parallel, so every test needs a name of its own.
+pub struct TestDir {
+    path: PathBuf,
+}
+
+impl TestDir {
+    pub fn new(name: &str) -> TestDir {
+        let path =
+            ::std::env::temp_dir().join(format!("graceful-shutdown-{}-{}", name, Pid::this()));
+        // Left behind by an earlier run that was interrupted.
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TestDir { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}